@@ -1,42 +1,183 @@
+use crate::mapping::SpecMatch;
 use crate::repo_watcher::ChangedFile;
+use crate::ruby::rspec::RSpecEvent;
+use crate::test_runner::{ExampleHistory, ExampleResult, RunId, RunResults};
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::stream::{self, Stream, StreamExt};
-use tokio::sync::{mpsc, watch};
+use tokio::sync::{broadcast, mpsc, watch};
 
 #[derive(Debug, Clone)]
 pub enum Event {
     Start,
     FilesChanged(Vec<ChangedFile>),
-    TestRunning,
-    TestPassed,
-    TestFailed,
+    SpecsSelected(String, Vec<SpecMatch>),
+    /// A run has started in the project root, for the given locations
+    TestRunning(RunId, PathBuf, Vec<String>),
+    TestEvent(RunId, RSpecEvent),
+    /// Run the specs selected for a changed file
+    RunFile(PathBuf),
+    RerunFailures,
+    /// Run the specs selected for every changed file
+    RunAllChanged,
+    CancelRun,
+    ClearChanged,
+    ToggleWatching,
+    /// The spec files of a project, relative to its root
+    SpecFilesIndexed(String, Vec<String>),
+    /// Run the given locations of a project
+    RunLocations(String, Vec<String>),
+    /// Compare changes against another branch, once the controller has
+    /// checked that it exists
+    SetBranch(String),
+    /// Changes are now compared against the branch
+    BranchChanged(String),
+    /// The recent results of a project's examples, keyed by example id
+    HistoryUpdated(String, BTreeMap<String, ExampleHistory>),
+    /// Something went wrong that spec_detect carries on from, such as a run
+    /// that couldn't start, for the front end to show
+    Error(String),
     Quit,
 }
 
+impl Event {
+    /// Whether the controller acts on the event, or needs the state after
+    /// it to act on others.
+    pub fn is_command(&self) -> bool {
+        matches!(
+            self,
+            Event::RunFile(_)
+                | Event::RerunFailures
+                | Event::RunAllChanged
+                | Event::CancelRun
+                | Event::RunLocations(_, _)
+                | Event::SetBranch(_)
+                | Event::ToggleWatching
+                | Event::Quit
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub should_quit: bool,
+    /// The branch changes are compared against
+    pub branch: String,
+    /// Whether file changes select and run specs
+    pub watching: bool,
     pub changed_files: Vec<ChangedFile>,
     pub last_changed_files: Vec<ChangedFile>,
+    /// The specs selected for the changed files, keyed by project name
+    pub selections: BTreeMap<String, Vec<SpecMatch>>,
+    /// The latest run of each project, keyed by project name
+    pub runs: BTreeMap<String, RunResults>,
+    /// How long recent runs took to finish, oldest first
+    pub run_times: VecDeque<Duration>,
+    /// Every spec file, keyed by project name
+    pub spec_files: BTreeMap<String, Vec<String>>,
+    /// Spec files that have been run, most recent first, as project name and
+    /// path
+    pub recent_specs: VecDeque<(String, String)>,
+    /// The recent results of each example, keyed by project name and then
+    /// example id
+    pub history: BTreeMap<String, BTreeMap<String, ExampleHistory>>,
+    /// The latest `Error` event's message
+    pub error: Option<String>,
 }
 
+/// How many run times to keep in `AppState::run_times`
+const RUN_TIMES: usize = 50;
+/// How many spec files to keep in `AppState::recent_specs`
+const RECENT_SPECS: usize = 100;
+
 impl AppState {
     pub fn new() -> AppState {
         AppState {
             should_quit: false,
+            branch: String::new(),
+            watching: true,
             changed_files: vec![],
             last_changed_files: vec![],
+            selections: BTreeMap::new(),
+            runs: BTreeMap::new(),
+            run_times: VecDeque::new(),
+            spec_files: BTreeMap::new(),
+            recent_specs: VecDeque::new(),
+            history: BTreeMap::new(),
+            error: None,
         }
     }
 
+    /// The specs selected for a changed file, across all projects.
+    pub fn matches_for<'a>(&'a self, file: &'a ChangedFile) -> impl Iterator<Item = &'a SpecMatch> {
+        self.selections
+            .values()
+            .flatten()
+            .filter(move |m| m.changed == file.path)
+    }
+
+    /// Whether a run in progress includes any spec selected for `file`.
+    pub fn is_running(&self, file: &ChangedFile) -> bool {
+        self.selections.iter().any(|(project, matches)| {
+            self.runs.get(project).map_or(false, |run| {
+                !run.finished
+                    && matches
+                        .iter()
+                        .any(|m| m.changed == file.path && run.locations.contains(&m.location))
+            })
+        })
+    }
+
+    /// Every failed example of the latest runs, with the run it is from.
+    pub fn failures(&self) -> impl Iterator<Item = (&RunResults, &ExampleResult)> {
+        self.runs
+            .values()
+            .flat_map(|run| run.failures().map(move |e| (run, e)))
+    }
+
     pub fn on(&mut self, event: Event) -> anyhow::Result<()> {
         match event {
             Event::Start => {}
             Event::FilesChanged(files) => {
                 self.on_file_event(files);
             }
-            Event::TestRunning => {}
-            Event::TestPassed => {}
-            Event::TestFailed => {}
+            Event::SpecsSelected(project, matches) => {
+                self.on_specs_selected(project, matches);
+            }
+            Event::TestRunning(id, root, locations) => {
+                self.on_test_running(id, root, locations);
+            }
+            Event::TestEvent(id, event) => {
+                self.on_test_event(id, event);
+            }
+            Event::ClearChanged => {
+                self.on_clear_changed();
+            }
+            Event::ToggleWatching => {
+                self.watching = !self.watching;
+            }
+            Event::SpecFilesIndexed(project, files) => {
+                self.spec_files.insert(project, files);
+            }
+            Event::HistoryUpdated(project, history) => {
+                self.history.insert(project, history);
+            }
+            Event::BranchChanged(branch) => {
+                self.branch = branch;
+            }
+            Event::Error(message) => {
+                self.error = Some(message);
+            }
+            // Handled by the controller, which runs the specs
+            Event::SetBranch(_)
+            | Event::RunFile(_)
+            | Event::RerunFailures
+            | Event::RunAllChanged
+            | Event::CancelRun
+            | Event::RunLocations(_, _) => {}
             Event::Quit => {
                 self.on_quit();
             }
@@ -61,15 +202,70 @@ impl AppState {
         Ok(())
     }
 
+    /// Replace the selections for the files that were selected for again,
+    /// keeping those for files that have changed before.
+    pub fn on_specs_selected(&mut self, project: String, matches: Vec<SpecMatch>) {
+        let selections = self.selections.entry(project).or_default();
+        selections.retain(|s| !matches.iter().any(|m| m.changed == s.changed));
+        selections.extend(matches);
+    }
+
+    pub fn on_test_running(&mut self, id: RunId, root: PathBuf, locations: Vec<String>) {
+        for location in locations.iter() {
+            // Locations may be ids like `spec/a_spec.rb[1:2]` or have a line
+            let file = location
+                .split(|c| c == '[' || c == ':')
+                .next()
+                .unwrap_or(location)
+                .trim_start_matches("./");
+            let recent = (id.project.clone(), file.to_owned());
+
+            self.recent_specs.retain(|r| *r != recent);
+            self.recent_specs.push_front(recent);
+        }
+        self.recent_specs.truncate(RECENT_SPECS);
+
+        self.runs
+            .insert(id.project.clone(), RunResults::new(id, root, locations));
+    }
+
+    pub fn on_test_event(&mut self, id: RunId, event: RSpecEvent) {
+        match self.runs.get_mut(&id.project) {
+            Some(results) if results.run == id => {
+                let exit = event == RSpecEvent::Exit;
+                results.on_rspec_event(event);
+
+                if exit {
+                    if self.run_times.len() == RUN_TIMES {
+                        self.run_times.pop_front();
+                    }
+                    self.run_times.push_back(results.elapsed());
+                }
+            }
+            // Events from a run that has been replaced
+            _ => {}
+        }
+    }
+
+    pub fn on_clear_changed(&mut self) {
+        self.changed_files.clear();
+        self.last_changed_files.clear();
+        self.selections.clear();
+    }
+
     pub fn on_quit(&mut self) {
         self.should_quit = true;
     }
 }
 
+type Commands = mpsc::UnboundedReceiver<(Event, AppState)>;
+
 #[derive(Clone)]
 pub struct AppStateManager {
     event_tx: mpsc::Sender<Event>,
     watch_rx: watch::Receiver<(Event, AppState)>,
+    broadcast_tx: broadcast::Sender<(Event, AppState)>,
+    commands_rx: Arc<Mutex<Option<Commands>>>,
 }
 
 impl AppStateManager {
@@ -77,6 +273,9 @@ impl AppStateManager {
         let state = AppState::new();
         let (event_tx, mut event_rx) = mpsc::channel::<Event>(10);
         let (watch_tx, watch_rx) = watch::channel((Event::Start, state));
+        let (broadcast_tx, _) = broadcast::channel::<(Event, AppState)>(256);
+        let spawn_broadcast_tx = broadcast_tx.clone();
+        let (commands_tx, commands_rx) = mpsc::unbounded_channel::<(Event, AppState)>();
 
         let mut spawn_rx = watch_rx.clone();
 
@@ -88,11 +287,18 @@ impl AppStateManager {
                 match event {
                     Some(Event::Quit) => {
                         state.on(Event::Quit);
+                        commands_tx.send((Event::Quit, state.clone())).ok();
+                        spawn_broadcast_tx.send((Event::Quit, state.clone())).ok();
                         watch_tx.broadcast((Event::Quit, state));
                         break;
                     }
                     Some(event) => {
                         state.on(event.clone());
+                        if event.is_command() {
+                            commands_tx.send((event.clone(), state.clone())).ok();
+                        }
+                        // Sending fails when nothing has subscribed
+                        spawn_broadcast_tx.send((event.clone(), state.clone())).ok();
                         watch_tx.broadcast((event, state));
                     }
                     None => {
@@ -102,17 +308,40 @@ impl AppStateManager {
             }
         });
 
-        AppStateManager { event_tx, watch_rx }
+        AppStateManager {
+            event_tx,
+            watch_rx,
+            broadcast_tx,
+            commands_rx: Arc::new(Mutex::new(Some(commands_rx))),
+        }
     }
 
     pub async fn get_state(&self) -> Option<(Event, AppState)> {
         self.watch_rx.clone().recv().await
     }
 
+    /// The state after the latest event.
+    pub fn state(&self) -> AppState {
+        self.watch_rx.borrow().1.clone()
+    }
+
     pub fn stream(&self) -> impl Stream<Item = (Event, AppState)> {
         self.watch_rx.clone()
     }
 
+    /// Every event with the state after it, unlike `stream` which skips to
+    /// the latest state when the receiver falls behind.
+    pub fn subscribe(&self) -> broadcast::Receiver<(Event, AppState)> {
+        self.broadcast_tx.subscribe()
+    }
+
+    /// The events the controller acts on, with the state after each. Unlike
+    /// `subscribe` none are missed however far behind the receiver falls.
+    /// There is only the one receiver, for the first to ask for it.
+    pub fn commands(&self) -> Option<Commands> {
+        self.commands_rx.lock().unwrap().take()
+    }
+
     pub async fn dispatch(&self, event: Event) -> anyhow::Result<()> {
         self.dispatcher()
             .send(event)
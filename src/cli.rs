@@ -1,27 +1,39 @@
+mod reporter;
+
+pub use reporter::Reporter;
+
 use crate::app_state::{AppStateManager, Event};
-use crate::some_loop;
+use crate::project::Project;
 use crate::Program;
 use async_trait::async_trait;
-use tokio::stream::StreamExt;
+use std::sync::Arc;
+use tokio::sync::broadcast::RecvError;
+
+pub struct CliApp {
+    projects: Arc<Vec<Project>>,
+}
 
-pub struct CliApp {}
+impl CliApp {
+    pub fn new(projects: Arc<Vec<Project>>) -> Self {
+        CliApp { projects }
+    }
+}
 
 #[async_trait]
 impl Program for CliApp {
     async fn run<'stream>(&self, app: AppStateManager) -> anyhow::Result<()> {
-        let watch_state = app.stream();
-        tokio::pin!(watch_state);
+        let mut events = app.subscribe();
+        let mut reporter = Reporter::stdout(&self.projects);
 
-        some_loop!((event, app_state) = watch_state.next() => {
-            match event {
-                Event::FilesChanged(files) => {
-                    println!("{:?}", files);
-                    println!("So I reckon the following have now changed:");
-                    println!("{:?}", app_state.changed_files);
-                },
-                _ => {}
+        loop {
+            match events.recv().await {
+                Ok((Event::Quit, _)) | Err(RecvError::Closed) => break,
+                Ok((event, app_state)) => reporter.on_event(&event, &app_state)?,
+                Err(RecvError::Lagged(missed)) => {
+                    eprintln!("Missed {} events", missed);
+                }
             }
-        });
+        }
 
         Ok(())
     }
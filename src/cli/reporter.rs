@@ -0,0 +1,254 @@
+use crate::app_state::{AppState, Event};
+use crate::mapping::SpecMatch;
+use crate::project::Project;
+use crate::repo_watcher::ChangedFile;
+use crate::ruby::rspec::{OutputStream, RSpecEvent};
+use crate::test_runner::{ExampleResult, RunResults};
+use crate::util::paint::Painter;
+use crate::util::shell;
+
+use std::collections::HashMap;
+use std::io::{self, Stdout, Write};
+use termion::color;
+
+/// Reports changes, selections and runs a line at a time, with a dot per
+/// passing example as rspec's progress formatter does.
+pub struct Reporter<W: Write> {
+    out: W,
+    painter: Painter,
+    /// Whether the current line has progress dots on it
+    in_progress: bool,
+    /// The command to run a project's specs from the repository root, by
+    /// project name
+    rerun_commands: HashMap<String, String>,
+}
+
+impl Reporter<Stdout> {
+    /// Report to stdout, in colour when it is a terminal.
+    pub fn stdout(projects: &[Project]) -> Self {
+        let out = io::stdout();
+        let color = termion::is_tty(&out);
+        Reporter::new(out, color, projects)
+    }
+}
+
+/// rspec as a project runs it, in the project root since the locations it
+/// reports are relative to that.
+fn rerun_command(project: &Project) -> String {
+    let config = &project.rspec;
+    let mut env: Vec<(&String, &String)> = config.env.iter().collect();
+    env.sort();
+
+    let mut words: Vec<String> = env
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, shell::quote(value)))
+        .collect();
+    if config.use_bundler {
+        words.push(String::from("bundle exec"));
+    }
+    words.push(shell::quote(&config.path_to_rspec).into_owned());
+    let rspec = words.join(" ");
+
+    if project.root.as_os_str().is_empty() {
+        rspec
+    } else {
+        format!(
+            "cd {} && {}",
+            shell::quote(&project.root.to_string_lossy()),
+            rspec
+        )
+    }
+}
+
+impl<W: Write> Reporter<W> {
+    pub fn new(out: W, color: bool, projects: &[Project]) -> Self {
+        Reporter {
+            out,
+            painter: Painter::new(color),
+            in_progress: false,
+            rerun_commands: projects
+                .iter()
+                .map(|p| (p.name.clone(), rerun_command(p)))
+                .collect(),
+        }
+    }
+
+    /// Finish a line of progress dots before writing a line of anything else.
+    fn end_progress(&mut self) -> io::Result<()> {
+        if self.in_progress {
+            self.in_progress = false;
+            writeln!(self.out)?;
+        }
+        Ok(())
+    }
+
+    fn progress(&mut self, mark: String) -> io::Result<()> {
+        self.in_progress = true;
+        write!(self.out, "{}", mark)?;
+        self.out.flush()
+    }
+
+    pub fn changed_files(&mut self, files: &[ChangedFile]) -> io::Result<()> {
+        self.end_progress()?;
+        writeln!(self.out, "Changed files:")?;
+
+        for file in files {
+            let status = self.painter.paint(color::Yellow, file.status_letter());
+            writeln!(self.out, "  {} {}", status, file.path.display())?;
+        }
+        Ok(())
+    }
+
+    pub fn specs_selected(&mut self, project: &str, matches: &[SpecMatch]) -> io::Result<()> {
+        self.end_progress()?;
+        writeln!(self.out, "Selected in {}:", project)?;
+
+        for m in matches {
+            let location = if m.reason.is_suggestion() {
+                self.painter
+                    .paint(color::Cyan, &format!("? {}", m.location))
+            } else {
+                m.location.clone()
+            };
+            let why = self.painter.paint(
+                color::LightBlack,
+                &format!("{} {}", m.changed.display(), m.reason),
+            );
+            writeln!(self.out, "  {}  {}", location, why)?;
+        }
+        Ok(())
+    }
+
+    fn failure(&mut self, number: usize, example: &ExampleResult) -> io::Result<()> {
+        let description = example.description.as_ref().unwrap_or(&example.id);
+        writeln!(self.out, "  {}) {}", number, description)?;
+
+        if let Some(exception) = &example.exception {
+            for line in exception.lines() {
+                writeln!(self.out, "     {}", self.painter.paint(color::Red, line))?;
+            }
+        }
+
+        if let Some(frame) = example.failure_frame() {
+            let location = format!("# {}:{}", frame.path.display(), frame.line);
+            writeln!(
+                self.out,
+                "     {}",
+                self.painter.paint(color::Cyan, &location)
+            )?;
+        }
+
+        writeln!(self.out)
+    }
+
+    /// The failures of a finished run, its counts, and the commands to rerun
+    /// each failure.
+    pub fn summary(&mut self, results: &RunResults) -> io::Result<()> {
+        self.end_progress()?;
+
+        let failures: Vec<&ExampleResult> = results.failures().collect();
+        if !failures.is_empty() {
+            writeln!(self.out, "\nFailures:\n")?;
+            for (i, example) in failures.iter().enumerate() {
+                self.failure(i + 1, example)?;
+            }
+        }
+
+        let counts = format!(
+            "{} examples, {} failures",
+            results.completed(),
+            results.failed()
+        );
+        let counts = if failures.is_empty() {
+            self.painter.paint(color::Green, &counts)
+        } else {
+            self.painter.paint(color::Red, &counts)
+        };
+        writeln!(
+            self.out,
+            "\n{}: {} in {:.2}s",
+            results.run.project,
+            counts,
+            results.elapsed().as_secs_f64()
+        )?;
+
+        if results.cancelled {
+            writeln!(
+                self.out,
+                "{}",
+                self.painter.paint(color::Yellow, "Cancelled")
+            )?;
+        }
+        for error in results.errors.iter() {
+            writeln!(self.out, "{}", self.painter.paint(color::Red, error))?;
+        }
+
+        if !failures.is_empty() {
+            let command = self
+                .rerun_commands
+                .get(&results.run.project)
+                .map_or("rspec", |c| c.as_str());
+
+            writeln!(self.out, "\nFailed examples:\n")?;
+            for example in failures {
+                let location = example.location.as_ref().unwrap_or(&example.id);
+                let rerun = format!("{} {}", command, shell::quote(location));
+                let rerun = self.painter.paint(color::Red, &rerun);
+                let description = example.description.as_deref().unwrap_or("");
+                let description = self
+                    .painter
+                    .paint(color::Cyan, &format!("# {}", description));
+                writeln!(self.out, "{} {}", rerun, description)?;
+            }
+        }
+
+        writeln!(self.out)
+    }
+
+    pub fn on_event(&mut self, event: &Event, state: &AppState) -> io::Result<()> {
+        match event {
+            Event::FilesChanged(files) => self.changed_files(files),
+            Event::SpecsSelected(project, matches) => self.specs_selected(project, matches),
+            Event::TestRunning(id, _, locations) => {
+                self.end_progress()?;
+                writeln!(
+                    self.out,
+                    "Running {} locations in {}",
+                    locations.len(),
+                    id.project
+                )
+            }
+            Event::TestEvent(id, event) => match event {
+                RSpecEvent::ExamplePassed { .. } => {
+                    let mark = self.painter.paint(color::Green, ".");
+                    self.progress(mark)
+                }
+                RSpecEvent::ExampleFailed { .. } => {
+                    let mark = self.painter.paint(color::Red, "F");
+                    self.progress(mark)
+                }
+                RSpecEvent::Output { stream, line } => {
+                    self.end_progress()?;
+                    match stream {
+                        OutputStream::Stdout => writeln!(self.out, "{}", line),
+                        OutputStream::Stderr => {
+                            writeln!(self.out, "{}", self.painter.paint(color::Red, line))
+                        }
+                    }
+                }
+                RSpecEvent::Exit => match state.runs.get(&id.project) {
+                    Some(results) if results.run == *id => self.summary(results),
+                    _ => Ok(()),
+                },
+                _ => Ok(()),
+            },
+            // Kept off stdout, which has only the report on it
+            Event::Error(message) => {
+                self.end_progress()?;
+                eprintln!("{}", self.painter.paint(color::Red, message));
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
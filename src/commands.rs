@@ -0,0 +1,70 @@
+mod doctor;
+mod explain;
+mod init;
+mod list;
+mod run;
+
+use crate::configuration::Configuration;
+use crate::lsp::LspApp;
+use crate::program::Command;
+use crate::project::Project;
+use crate::repo_watcher::{ChangedFile, CodeRepo};
+
+use anyhow::Context;
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+
+/// Exit codes for commands
+pub const SUCCESS: i32 = 0;
+pub const FAILURE: i32 = 1;
+
+/// Run a command to completion, returning the code to exit with.
+pub async fn execute(command: Command) -> anyhow::Result<i32> {
+    match command {
+        Command::Run => run::run().await,
+        Command::List { format } => list::list(format),
+        Command::Explain { path } => explain::explain(&path),
+        Command::Init { force } => init::init(force),
+        Command::Doctor => doctor::doctor(),
+        Command::Lsp => crate::watch(|projects| Box::new(LspApp::new(projects)))
+            .await
+            .map(|_| SUCCESS),
+    }
+}
+
+/// The directory the command was started in relative to the repository
+/// root, which it has changed to, with the configuration and its projects.
+fn load_projects() -> anyhow::Result<(PathBuf, Configuration, Vec<Project>)> {
+    let started = crate::enter_repository()?;
+    let config = Configuration::read_configuration(&started)?;
+
+    let (problems, mut problems_rx) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        while let Some(problem) = problems_rx.recv().await {
+            eprintln!("{}", problem);
+        }
+    });
+    let projects =
+        Project::from_configuration(&config, &problems).context("Invalid project configuration")?;
+
+    // Commands select once, so need what is learned in the background first
+    tokio::task::block_in_place(|| {
+        for project in projects.iter() {
+            project.wait_for_selectors();
+        }
+    });
+
+    Ok((started, config, projects))
+}
+
+/// Everything changed from the base branch, including uncommitted and
+/// untracked files, that some project includes.
+fn changed_files(config: &Configuration, projects: &[Project]) -> anyhow::Result<Vec<ChangedFile>> {
+    let repo = CodeRepo::open(".").context("Could not open the repository")?;
+
+    Ok(repo
+        .all_changed_files(&config.branch)?
+        .into_iter()
+        .filter(|f| projects.iter().any(|p| p.include_path(&f.path)))
+        .collect())
+}
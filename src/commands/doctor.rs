@@ -0,0 +1,307 @@
+use crate::commands::{FAILURE, SUCCESS};
+use crate::configuration::{Configuration, ProjectConfiguration};
+use crate::mapping::MapRule;
+use crate::project;
+use crate::repo_watcher::CodeRepo;
+use crate::ruby::rspec::{OutputStream, RSpec, RSpecConfiguration, RSpecEvent};
+use crate::util::paint::Painter;
+use crate::util::path_filter::PathFilter;
+
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use termion::color;
+
+/// Lines of rspec's stderr to show when the dry run fails
+const STDERR_LINES: usize = 10;
+
+/// Prints each check as it passes or fails, counting the failures.
+struct Doctor {
+    painter: Painter,
+    problems: usize,
+}
+
+impl Doctor {
+    fn new() -> Self {
+        Doctor {
+            painter: Painter::new(termion::is_tty(&std::io::stdout())),
+            problems: 0,
+        }
+    }
+
+    fn ok(&self, what: &str) {
+        println!("{} {}", self.painter.paint(color::Green, "✓"), what);
+    }
+
+    fn problem<E: Display>(&mut self, what: &str, err: E) {
+        self.problems += 1;
+        println!("{} {}: {}", self.painter.paint(color::Red, "✗"), what, err);
+    }
+
+    /// Suggest how to fix the problem just reported.
+    fn hint(&self, text: &str) {
+        for line in text.lines() {
+            println!("    {}", line);
+        }
+    }
+
+    fn check<T>(&mut self, what: &str, result: anyhow::Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.ok(what);
+                Some(value)
+            }
+            Err(err) => {
+                self.problem(what, format!("{:#}", err));
+                None
+            }
+        }
+    }
+
+    fn finish(&self) -> i32 {
+        match self.problems {
+            0 => {
+                println!("\nNo problems found");
+                SUCCESS
+            }
+            1 => {
+                println!("\n1 problem found");
+                FAILURE
+            }
+            n => {
+                println!("\n{} problems found", n);
+                FAILURE
+            }
+        }
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).map_or(false, |m| {
+        m.is_file() && m.permissions().mode() & 0o111 != 0
+    })
+}
+
+/// Where `program` would be run from: relative to `dir` when it is a path,
+/// otherwise the first match on `PATH`.
+fn find_executable(program: &str, dir: &Path) -> Option<PathBuf> {
+    if program.contains('/') {
+        return Some(dir.join(program)).filter(|p| is_executable(p));
+    }
+
+    env::var_os("PATH").and_then(|paths| {
+        env::split_paths(&paths)
+            .map(|p| p.join(program))
+            .find(|p| is_executable(p))
+    })
+}
+
+/// Check each glob on its own, so every bad one is reported.
+fn check_globs(doctor: &mut Doctor, name: &str, globs: &[String]) {
+    let mut valid = true;
+    for glob in globs {
+        if let Err(err) = PathFilter::new(&[glob]) {
+            doctor.problem(name, err);
+            valid = false;
+        }
+    }
+
+    if valid {
+        doctor.ok(&format!("{} globs are valid", name));
+    }
+}
+
+fn check_rules(doctor: &mut Doctor, rules: &[(String, String)]) {
+    let mut valid = true;
+    for (pattern, target) in rules {
+        if let Err(err) = MapRule::new(pattern, target) {
+            doctor.problem("[map] rule", format!("{:#}", err));
+            valid = false;
+        }
+    }
+
+    if valid {
+        doctor.ok(&format!("{} [map] rules are valid", rules.len()));
+    }
+}
+
+/// The executable spec_detect runs, whether rspec itself or bundler.
+fn check_executable(doctor: &mut Doctor, rspec: &RSpecConfiguration, root: &Path) -> bool {
+    let (program, hint) = if rspec.use_bundler {
+        (
+            "bundle",
+            "Install bundler with `gem install bundler`, or set use_bundler = false in [rspec]",
+        )
+    } else {
+        (
+            rspec.path_to_rspec.as_str(),
+            "Set path_to_rspec in [rspec] to the rspec executable, or set use_bundler = true \
+             to run it with `bundle exec`",
+        )
+    };
+
+    match find_executable(program, root) {
+        Some(path) => {
+            doctor.ok(&format!("Runs rspec with {}", path.display()));
+            true
+        }
+        None => {
+            doctor.problem("rspec executable", format!("{} not found", program));
+            doctor.hint(hint);
+            false
+        }
+    }
+}
+
+/// Load every spec with the formatter but run none of them, which shows
+/// whether rspec starts and the formatter's events come through.
+fn check_dry_run(doctor: &mut Doctor, rspec: &RSpecConfiguration, root: &Path) {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let run = RSpec::new(rspec.clone())
+        .working_dir(root)
+        .dry_run(true)
+        .run(Vec::<String>::new(), tx);
+    let run = match run {
+        Ok(run) => run,
+        Err(err) => return doctor.problem("Dry run", err),
+    };
+
+    let mut count = None;
+    let mut errors = vec![];
+    let mut stderr = vec![];
+    for event in rx.iter() {
+        match event {
+            RSpecEvent::Start { count: c } => count = Some(c.unwrap_or(0)),
+            RSpecEvent::Error { msg } => errors.push(msg),
+            RSpecEvent::Output {
+                stream: OutputStream::Stderr,
+                line,
+            } => stderr.push(line),
+            RSpecEvent::Exit => break,
+            _ => {}
+        }
+    }
+    run.wait().ok();
+
+    let stderr_tail = stderr[stderr.len().saturating_sub(STDERR_LINES)..].join("\n");
+    match (count, errors.first()) {
+        (Some(count), None) => doctor.ok(&format!(
+            "Dry run loaded {} examples with the formatter",
+            count
+        )),
+        (None, _) => {
+            doctor.problem("Dry run", "the formatter reported nothing");
+            doctor.hint("Check that rspec runs from the project root, and what it printed:");
+            doctor.hint(&stderr_tail);
+        }
+        (Some(_), Some(error)) => {
+            doctor.problem("Dry run", error);
+            doctor.hint(&stderr_tail);
+        }
+    }
+}
+
+fn check_project(doctor: &mut Doctor, config: &ProjectConfiguration) {
+    println!("\nProject {}", config.name);
+
+    let root: PathBuf = Path::new(&config.root)
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect();
+    let root = if root.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        root
+    };
+    if !root.is_dir() {
+        doctor.problem("Root", format!("{} is not a directory", root.display()));
+        doctor.hint("Set root to the project's directory, relative to spec_detect.toml");
+        return;
+    }
+
+    let (include, map) = match config.preset.as_deref() {
+        Some(name) => match doctor.check(&format!("Preset {}", name), project::with_preset(config))
+        {
+            Some(resolved) => resolved,
+            None => (config.include.clone(), config.map.clone()),
+        },
+        None => (config.include.clone(), config.map.clone()),
+    };
+
+    if include.is_empty() {
+        doctor.problem("include", "no globs, so every change is ignored");
+        doctor.hint("Add include globs or a preset, or run `spec_detect init`");
+    } else {
+        check_globs(doctor, "include", &include);
+    }
+    check_globs(doctor, "spec_files", &config.spec_files);
+    check_rules(
+        doctor,
+        map.get("rspec").map_or(&[], |rules| rules.as_slice()),
+    );
+
+    if check_executable(doctor, &config.rspec, &root) {
+        check_dry_run(doctor, &config.rspec, &root);
+    }
+}
+
+/// Check the repository, configuration and rspec setup, reporting every
+/// problem found with a suggestion for fixing it.
+pub fn doctor() -> anyhow::Result<i32> {
+    let mut doctor = Doctor::new();
+
+    let repo = doctor.check(
+        "Git repository",
+        CodeRepo::open(".").map_err(anyhow::Error::from),
+    );
+    if repo.is_none() {
+        doctor.hint("Run spec_detect from inside the repository");
+    }
+
+    // Project roots are relative to the repository root, and spec_detect.toml
+    // is looked for from the directory doctor was started in up to it
+    let started = match repo {
+        Some(_) => match doctor.check("Repository root", crate::enter_repository()) {
+            Some(started) => started,
+            // spec_detect.toml would be looked for from the wrong directory
+            None => return Ok(doctor.finish()),
+        },
+        None => PathBuf::new(),
+    };
+    let config = match doctor.check(
+        "spec_detect.toml",
+        Configuration::read_configuration(&started),
+    ) {
+        Some(config) => config,
+        None => return Ok(doctor.finish()),
+    };
+
+    if let Some(repo) = repo {
+        let what = format!("Base branch {}", config.branch);
+        if doctor
+            .check(&what, repo.check_branch(&config.branch))
+            .is_none()
+        {
+            if let Some(branch) = repo.default_branch() {
+                doctor.hint(&format!("The default branch looks to be {}", branch));
+            }
+        } else {
+            match repo.all_changed_files(&config.branch) {
+                Ok(files) => doctor.ok(&format!(
+                    "{} files changed from {}",
+                    files.len(),
+                    config.branch
+                )),
+                Err(err) => doctor.problem("Changed files", format!("{:#}", err)),
+            }
+        }
+    }
+
+    for project in config.projects().iter() {
+        check_project(&mut doctor, project);
+    }
+
+    Ok(doctor.finish())
+}
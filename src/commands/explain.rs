@@ -0,0 +1,216 @@
+use crate::commands::{self, SUCCESS};
+use crate::configuration::normalize;
+use crate::project::Project;
+use crate::repo_watcher::ChangedFile;
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+fn explain_rules<W: Write>(out: &mut W, project: &Project, path: &Path) -> io::Result<()> {
+    let path_str = path.to_string_lossy();
+
+    writeln!(out, "  [map] rules:")?;
+    if project.rules.rules().is_empty() {
+        writeln!(out, "    none")?;
+    }
+
+    for rule in project.rules.rules() {
+        writeln!(out, "    {} => {}", rule.pattern, rule.target)?;
+
+        match rule.captures(&path_str) {
+            None => writeln!(out, "      doesn't match")?,
+            Some(captures) => {
+                for (i, capture) in captures.iter().enumerate() {
+                    match capture {
+                        Some(capture) => writeln!(out, "      ${} = {:?}", i + 1, capture)?,
+                        None => writeln!(out, "      ${} didn't participate", i + 1)?,
+                    }
+                }
+
+                if let Some(target) = rule.apply(&path_str) {
+                    let exists = if project.root.join(&target).is_file() {
+                        "exists"
+                    } else {
+                        "doesn't exist, so isn't selected"
+                    };
+                    writeln!(out, "      target {} {}", target, exists)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn explain_project<W: Write>(out: &mut W, project: &Project, path: &Path) -> io::Result<()> {
+    let root = if project.root.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        &project.root
+    };
+    writeln!(out, "Project {} (root {})", project.name, root.display())?;
+
+    let relative = match project.relative_path(path) {
+        Some(relative) => relative,
+        None => return writeln!(out, "  outside the project root\n"),
+    };
+    writeln!(out, "  path in project: {}", relative.display())?;
+
+    match project.path_filter().matching_glob(&relative) {
+        Some(glob) => writeln!(out, "  included by {:?}", glob)?,
+        None => {
+            writeln!(
+                out,
+                "  not included, as it matches none of {:?}\n",
+                project.path_filter().globs()
+            )?;
+            return Ok(());
+        }
+    }
+
+    if project.spec_filter().include_path(&relative) {
+        writeln!(out, "  is a spec file")?;
+    }
+
+    explain_rules(out, project, &relative)?;
+
+    let file = ChangedFile::new(path.to_path_buf());
+    let matches = project.select(&[file]);
+
+    writeln!(out, "  selected:")?;
+    if matches.is_empty() {
+        writeln!(out, "    nothing")?;
+    }
+    for m in matches {
+        writeln!(out, "    {} ({})", m.location, m.reason)?;
+    }
+
+    writeln!(out)
+}
+
+/// A path given relative to the directory spec_detect was started in, as
+/// the path relative to the repository root that projects compare against.
+fn repository_path(started: &Path, path: &Path) -> PathBuf {
+    normalize(&started.join(path))
+}
+
+/// Show each project's view of `path`: whether it is included, how each
+/// `[map]` rule applies to it, and what ends up selected. `path` is
+/// relative to the directory spec_detect was started in.
+pub fn explain(path: &Path) -> anyhow::Result<i32> {
+    let (started, _, projects) = commands::load_projects()?;
+    let path = repository_path(&started, path);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    for project in projects.iter() {
+        explain_project(&mut out, project, &path)?;
+    }
+
+    Ok(SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::ProjectConfiguration;
+    use crate::util::test_dir::TestDir;
+
+    use std::collections::HashMap;
+    use tokio::sync::mpsc;
+
+    fn project(dir: &TestDir) -> Project {
+        let mut map = HashMap::new();
+        map.insert(
+            String::from("rspec"),
+            vec![
+                (
+                    String::from(r"app/(models|jobs)/(.+)\.rb"),
+                    String::from("spec/$1/$2_spec.rb"),
+                ),
+                (
+                    String::from(r"lib/(.+)\.rb"),
+                    String::from("spec/$1_spec.rb"),
+                ),
+            ],
+        );
+        let config = ProjectConfiguration {
+            name: String::from("web"),
+            root: dir.path.to_string_lossy().into_owned(),
+            include: vec![String::from("app/**/*.rb"), String::from("spec/**/*.rb")],
+            map,
+            ..ProjectConfiguration::default()
+        };
+        let (problems, _) = mpsc::unbounded_channel();
+
+        Project::new(&config, &problems).unwrap()
+    }
+
+    fn explained(project: &Project, path: &Path) -> String {
+        let mut out = vec![];
+        explain_project(&mut out, project, path).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn paths_are_relative_to_where_spec_detect_started() {
+        let started = Path::new("apps/web");
+
+        assert_eq!(
+            repository_path(started, Path::new("app/models/user.rb")),
+            PathBuf::from("apps/web/app/models/user.rb")
+        );
+        assert_eq!(
+            repository_path(started, Path::new("./../shared/lib/a.rb")),
+            PathBuf::from("apps/shared/lib/a.rb")
+        );
+        assert_eq!(
+            repository_path(Path::new(""), Path::new("./app/a.rb")),
+            PathBuf::from("app/a.rb")
+        );
+    }
+
+    #[test]
+    fn explains_how_rules_map_a_file() {
+        let dir = TestDir::new("explain-rules");
+        dir.write("app/models/user.rb", "")
+            .write("spec/models/user_spec.rb", "");
+        let project = project(&dir);
+
+        let output = explained(&project, &dir.path.join("app/models/user.rb"));
+
+        assert!(output.contains("  path in project: app/models/user.rb\n"));
+        assert!(output.contains("  included by \"app/**/*.rb\"\n"));
+        assert!(output.contains(
+            "      $1 = \"models\"\n      $2 = \"user\"\n      target spec/models/user_spec.rb exists\n"
+        ));
+        assert!(output.contains("    lib/(.+)\\.rb => spec/$1_spec.rb\n      doesn't match\n"));
+        assert!(output.contains("  selected:\n    spec/models/user_spec.rb ("));
+    }
+
+    #[test]
+    fn explains_a_missing_target() {
+        let dir = TestDir::new("explain-missing");
+        dir.write("app/jobs/mail.rb", "");
+        let project = project(&dir);
+
+        let output = explained(&project, &dir.path.join("app/jobs/mail.rb"));
+
+        assert!(output.contains("target spec/jobs/mail_spec.rb doesn't exist, so isn't selected"));
+        assert!(output.ends_with("  selected:\n    nothing\n\n"));
+    }
+
+    #[test]
+    fn explains_files_the_project_ignores() {
+        let dir = TestDir::new("explain-ignored");
+        let project = project(&dir);
+
+        let output = explained(&project, &dir.path.join("config/routes.rb"));
+        assert!(output.contains("  not included, as it matches none of"));
+        assert!(!output.contains("[map] rules"));
+
+        let output = explained(&project, Path::new("elsewhere/a.rb"));
+        assert!(output.ends_with("  outside the project root\n\n"));
+    }
+}
@@ -0,0 +1,392 @@
+use crate::commands::{FAILURE, SUCCESS};
+use crate::mapping::presets;
+use crate::repo_watcher::CodeRepo;
+
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+const CONFIG_FILE: &str = "spec_detect.toml";
+
+/// What `init` found out about the project in the current directory.
+struct Detected {
+    /// None outside a git repository
+    branch: Option<String>,
+    rails: bool,
+    rspec_rails: bool,
+    /// rspec is in the Gemfile, so runs with `bundle exec`
+    bundled_rspec: bool,
+    binstub: bool,
+    dot_rspec: bool,
+    /// Where the specs live, `spec` unless `.rspec` says otherwise or there
+    /// is only a `test` directory
+    spec_dir: String,
+    lib: bool,
+}
+
+/// Whether a Gemfile or its lockfile lists `gem`.
+fn bundles(gemfile: &str, lockfile: &str, gem: &str) -> bool {
+    let declarations = [format!("gem \"{}\"", gem), format!("gem '{}'", gem)];
+    let in_gemfile = gemfile
+        .lines()
+        .map(str::trim_start)
+        .any(|l| declarations.iter().any(|d| l.starts_with(d.as_str())));
+
+    // Locked as `    name (version)` and depended on as `  name`
+    let locked = format!("{} (", gem);
+    let in_lockfile = lockfile
+        .lines()
+        .map(str::trim)
+        .any(|l| l == gem || l.starts_with(&locked));
+
+    in_gemfile || in_lockfile
+}
+
+/// The `--default-path` set in a `.rspec` file.
+fn default_path(dot_rspec: &str) -> Option<String> {
+    let mut words = dot_rspec.split_whitespace();
+
+    while let Some(word) = words.next() {
+        if word == "--default-path" {
+            return words.next().map(String::from);
+        }
+        if let Some(path) = word.strip_prefix("--default-path=") {
+            return Some(path.to_owned());
+        }
+    }
+
+    None
+}
+
+fn detect(root: &Path) -> Detected {
+    let read = |name: &str| fs::read_to_string(root.join(name)).unwrap_or_default();
+    let gemfile = read("Gemfile");
+    let lockfile = read("Gemfile.lock");
+    let dot_rspec = read(".rspec");
+
+    let rspec_rails = bundles(&gemfile, &lockfile, "rspec-rails");
+    let bundled_rspec = rspec_rails
+        || ["rspec", "rspec-core"]
+            .iter()
+            .any(|gem| bundles(&gemfile, &lockfile, gem));
+
+    let spec_dir = default_path(&dot_rspec).unwrap_or_else(|| {
+        if !root.join("spec").is_dir() && root.join("test").is_dir() {
+            String::from("test")
+        } else {
+            String::from("spec")
+        }
+    });
+
+    Detected {
+        branch: CodeRepo::open(root)
+            .ok()
+            .and_then(|repo| repo.default_branch()),
+        rails: rspec_rails
+            || bundles(&gemfile, &lockfile, "rails")
+            || root.join("config/application.rb").is_file(),
+        rspec_rails,
+        bundled_rspec,
+        binstub: root.join("bin/rspec").is_file(),
+        dot_rspec: root.join(".rspec").is_file(),
+        spec_dir,
+        lib: root.join("lib").is_dir(),
+    }
+}
+
+impl Detected {
+    fn preset(&self) -> Option<&'static str> {
+        match (self.rails, self.spec_dir.as_str()) {
+            (true, "spec") => Some("rails"),
+            (false, "spec") if self.lib => Some("gem"),
+            _ => None,
+        }
+    }
+
+    /// What was found, as shown after writing the configuration.
+    fn findings(&self) -> Vec<String> {
+        let mut findings = vec![];
+
+        match &self.branch {
+            Some(branch) => findings.push(format!("comparing changes against {}", branch)),
+            None => findings.push(String::from(
+                "no git repository found, so assuming the base branch is master",
+            )),
+        }
+        if self.rspec_rails {
+            findings.push(String::from("rspec-rails is in the Gemfile"));
+        } else if self.rails {
+            findings.push(String::from("a Rails app"));
+        }
+        if self.dot_rspec {
+            findings.push(String::from(".rspec is present"));
+        }
+        findings.push(format!("specs are in {}/", self.spec_dir));
+        if self.binstub {
+            findings.push(String::from("running rspec with bin/rspec"));
+        } else if self.bundled_rspec {
+            findings.push(String::from("running rspec with bundle exec"));
+        }
+
+        findings
+    }
+}
+
+fn toml_list(out: &mut String, values: &[String]) {
+    out.push_str("[\n");
+    for value in values {
+        writeln!(out, "  {},", value).unwrap();
+    }
+    out.push_str("]\n");
+}
+
+/// The commented configuration for what was detected. Patterns are written
+/// as literal strings so their backslashes needn't be escaped.
+fn configuration(detected: &Detected) -> String {
+    let mut out = String::new();
+    let dir = &detected.spec_dir;
+
+    out.push_str("# spec_detect configuration, generated by `spec_detect init`\n\n");
+
+    out.push_str("# Changes are everything that differs from this branch\n");
+    writeln!(
+        out,
+        "branch = \"{}\"\n",
+        detected.branch.as_deref().unwrap_or("master")
+    )
+    .unwrap();
+
+    let preset = detected.preset().and_then(|name| presets::find(name).ok());
+    let include: Vec<String> = match preset {
+        Some(preset) => {
+            writeln!(
+                out,
+                "# The {} preset maps files to specs in the usual places. Rules in\n\
+                 # [map] are tried first, and replace its rules for the same pattern.\n\
+                 preset = \"{}\"\n",
+                preset.name, preset.name
+            )
+            .unwrap();
+            preset
+                .include
+                .iter()
+                .map(|g| format!("\"{}\"", g))
+                .collect()
+        }
+        None if detected.lib => vec![
+            String::from("\"lib/**/*.rb\""),
+            format!("\"{}/**/*.rb\"", dir),
+        ],
+        None => vec![String::from("\"**/*.rb\"")],
+    };
+
+    out.push_str("# Changed files matching none of these globs are ignored\ninclude = ");
+    toml_list(&mut out, &include);
+    out.push('\n');
+
+    if dir != "spec" {
+        out.push_str("# Spec files, for finding specs to run by name\n");
+        writeln!(out, "spec_files = [\"{}/**/*_spec.rb\"]\n", dir).unwrap();
+    }
+
+    out.push_str(
+        "[map]\n\
+         # Each rule is [pattern, target]. The pattern is a regex that must match\n\
+         # the whole changed path, and the target may use its groups as $1, $2 and\n\
+         # so on. Targets that don't exist are skipped.\n\
+         rspec = ",
+    );
+    let rules = match preset {
+        Some(_) => vec![format!(
+            "# ['app/components/(.+)\\.rb', '{}/components/$1_spec.rb']",
+            dir
+        )],
+        None if detected.lib => vec![
+            format!("['{0}/(.+)_spec\\.rb', '{0}/$1_spec.rb']", dir),
+            format!("['lib/(.+)\\.rb', '{}/$1_spec.rb']", dir),
+        ],
+        None => vec![format!("['{0}/(.+)_spec\\.rb', '{0}/$1_spec.rb']", dir)],
+    };
+    toml_list(&mut out, &rules);
+    out.push('\n');
+
+    out.push_str("[rspec]\n");
+    if detected.binstub {
+        out.push_str(
+            "# The binstub sets up bundler itself\n\
+             path_to_rspec = \"bin/rspec\"\n\
+             use_bundler = false\n",
+        );
+    } else if detected.bundled_rspec {
+        out.push_str(
+            "# rspec is in the Gemfile, so runs with `bundle exec`\n\
+             path_to_rspec = \"rspec\"\n\
+             use_bundler = true\n",
+        );
+    } else {
+        out.push_str(
+            "path_to_rspec = \"rspec\"\n\
+             use_bundler = false\n",
+        );
+    }
+    out.push_str("# Environment variables to run rspec with\n# env = { RAILS_ENV = \"test\" }\n");
+
+    out
+}
+
+/// Write a spec_detect.toml for the project in the current directory.
+pub fn init(force: bool) -> anyhow::Result<i32> {
+    if Path::new(CONFIG_FILE).exists() && !force {
+        eprintln!(
+            "{} already exists, use --force to overwrite it",
+            CONFIG_FILE
+        );
+        return Ok(FAILURE);
+    }
+
+    let detected = detect(Path::new("."));
+    fs::write(CONFIG_FILE, configuration(&detected))?;
+
+    println!("Wrote {}:", CONFIG_FILE);
+    for finding in detected.findings() {
+        println!("  {}", finding);
+    }
+
+    Ok(SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::Configuration;
+    use crate::project::Project;
+    use crate::util::test_dir::TestDir;
+
+    use config::{Config, FileFormat};
+    use tokio::sync::mpsc;
+
+    /// The generated configuration, read as spec_detect.toml would be.
+    fn round_trip(detected: &Detected) -> Configuration {
+        let mut config = Config::new();
+        config
+            .merge(config::File::from_str(
+                &configuration(detected),
+                FileFormat::Toml,
+            ))
+            .unwrap();
+        let config: Configuration = config.try_into().unwrap();
+
+        let (problems, _) = mpsc::unbounded_channel();
+        Project::from_configuration(&config, &problems).unwrap();
+        config
+    }
+
+    #[test]
+    fn finds_gems_in_the_gemfile_or_lockfile() {
+        let gemfile =
+            "source 'https://rubygems.org'\n  gem 'rails', '~> 6.0'\ngem \"rspec-rails\"\n";
+        let lockfile = "GEM\n  specs:\n    rspec-core (3.9.2)\n\nDEPENDENCIES\n  pg\n";
+
+        assert!(bundles(gemfile, "", "rails"));
+        assert!(bundles(gemfile, "", "rspec-rails"));
+        assert!(bundles("", lockfile, "rspec-core"));
+        assert!(bundles("", lockfile, "pg"));
+        assert!(!bundles(gemfile, lockfile, "rspec"));
+        assert!(!bundles("gem 'railsy'\n", "", "rails"));
+    }
+
+    #[test]
+    fn reads_the_default_path_from_dot_rspec() {
+        assert_eq!(
+            default_path("--require spec_helper\n--default-path specs\n"),
+            Some(String::from("specs"))
+        );
+        assert_eq!(
+            default_path("--default-path=test/specs"),
+            Some(String::from("test/specs"))
+        );
+        assert_eq!(default_path("--require spec_helper\n"), None);
+    }
+
+    #[test]
+    fn configures_a_rails_app_with_its_preset() {
+        let dir = TestDir::new("init-rails");
+        dir.write("Gemfile", "gem 'rails'\ngem 'rspec-rails'\n")
+            .write("config/application.rb", "")
+            .write("spec/spec_helper.rb", "");
+
+        let detected = detect(&dir.path);
+        assert!(detected.rails && detected.rspec_rails && detected.bundled_rspec);
+        assert_eq!(detected.branch, None);
+        assert_eq!(detected.preset(), Some("rails"));
+
+        let config = round_trip(&detected);
+        assert_eq!(config.branch, "master");
+        assert_eq!(config.preset.as_deref(), Some("rails"));
+        assert_eq!(config.rspec.path_to_rspec, "rspec");
+        assert!(config.rspec.use_bundler);
+    }
+
+    #[test]
+    fn configures_a_gem_with_its_preset() {
+        let dir = TestDir::new("init-gem");
+        dir.write("Gemfile", "gemspec\n")
+            .write("Gemfile.lock", "DEPENDENCIES\n  rspec\n")
+            .write("lib/thing.rb", "")
+            .write("spec/thing_spec.rb", "");
+
+        let detected = detect(&dir.path);
+        assert!(!detected.rails && detected.lib && detected.bundled_rspec);
+        assert_eq!(detected.preset(), Some("gem"));
+
+        let config = round_trip(&detected);
+        assert_eq!(config.preset.as_deref(), Some("gem"));
+    }
+
+    #[test]
+    fn configures_specs_in_a_test_directory() {
+        let dir = TestDir::new("init-test-dir");
+        dir.write("lib/thing.rb", "")
+            .write("test/thing_spec.rb", "");
+
+        let detected = detect(&dir.path);
+        assert_eq!(detected.spec_dir, "test");
+        assert_eq!(detected.preset(), None);
+
+        let config = round_trip(&detected);
+        assert_eq!(config.preset, None);
+        assert_eq!(config.include, vec!["lib/**/*.rb", "test/**/*.rb"]);
+        assert_eq!(config.spec_files, vec!["test/**/*_spec.rb"]);
+        assert_eq!(
+            config.map["rspec"],
+            vec![
+                (
+                    String::from(r"test/(.+)_spec\.rb"),
+                    String::from("test/$1_spec.rb")
+                ),
+                (
+                    String::from(r"lib/(.+)\.rb"),
+                    String::from("test/$1_spec.rb")
+                ),
+            ]
+        );
+        assert!(!config.rspec.use_bundler);
+    }
+
+    #[test]
+    fn runs_the_binstub_without_bundler() {
+        let dir = TestDir::new("init-binstub");
+        dir.write("Gemfile", "gem 'rspec'\n")
+            .write("bin/rspec", "")
+            .write("spec/thing_spec.rb", "");
+
+        let detected = detect(&dir.path);
+        assert!(detected.binstub);
+        assert_eq!(detected.preset(), None);
+
+        let config = round_trip(&detected);
+        assert_eq!(config.include, vec!["**/*.rb"]);
+        assert_eq!(config.rspec.path_to_rspec, "bin/rspec");
+        assert!(!config.rspec.use_bundler);
+    }
+}
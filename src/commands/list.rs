@@ -0,0 +1,309 @@
+use crate::commands::{self, SUCCESS};
+use crate::mapping::{self, Reason, SpecMatch};
+use crate::program::ListFormat;
+use crate::repo_watcher::ChangedFile;
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize)]
+struct ListedSpec<'a> {
+    project: &'a str,
+    location: &'a str,
+    /// Why the spec was selected, which for a `[map]` rule includes its
+    /// pattern and target
+    reason: &'a Reason,
+}
+
+#[derive(Serialize)]
+struct ListedFile<'a> {
+    path: &'a Path,
+    status: &'static str,
+    specs: Vec<ListedSpec<'a>>,
+}
+
+#[derive(Serialize)]
+struct Listing<'a> {
+    files: Vec<ListedFile<'a>>,
+    /// The distinct locations to run, keyed by project name. Suggestions
+    /// aren't included.
+    locations: BTreeMap<&'a str, Vec<String>>,
+    #[serde(skip)]
+    roots: BTreeMap<&'a str, &'a Path>,
+}
+
+/// A project's name, its root relative to the repository root, and what it
+/// selected.
+type Selection = (String, PathBuf, Vec<SpecMatch>);
+
+fn listing<'a>(files: &'a [ChangedFile], selections: &'a [Selection]) -> Listing<'a> {
+    let files = files
+        .iter()
+        .map(|file| ListedFile {
+            path: &file.path,
+            status: file.status_letter(),
+            specs: selections
+                .iter()
+                .flat_map(|(project, _, matches)| {
+                    matches
+                        .iter()
+                        .filter(move |m| m.changed == file.path)
+                        .map(move |m| ListedSpec {
+                            project,
+                            location: &m.location,
+                            reason: &m.reason,
+                        })
+                })
+                .collect(),
+        })
+        .collect();
+
+    let locations = selections
+        .iter()
+        .map(|(project, _, matches)| (project.as_str(), mapping::locations(matches)))
+        .filter(|(_, locations)| !locations.is_empty())
+        .collect();
+    let roots = selections
+        .iter()
+        .map(|(project, root, _)| (project.as_str(), root.as_path()))
+        .collect();
+
+    Listing {
+        files,
+        locations,
+        roots,
+    }
+}
+
+fn write_text<W: Write>(out: &mut W, listing: &Listing) -> io::Result<()> {
+    for file in listing.files.iter() {
+        writeln!(out, "{} {}", file.status, file.path.display())?;
+
+        for spec in file.specs.iter() {
+            let suggestion = if spec.reason.is_suggestion() {
+                "? "
+            } else {
+                ""
+            };
+            writeln!(
+                out,
+                "    {}{} ({}: {})",
+                suggestion, spec.location, spec.project, spec.reason
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn write_json<W: Write>(out: &mut W, listing: &Listing) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, listing)?;
+    writeln!(out)
+}
+
+/// The locations of every project relative to the repository root, where
+/// `xargs -0 rspec` would be run, rather than to their project's root.
+fn write_null<W: Write>(out: &mut W, listing: &Listing) -> io::Result<()> {
+    for (project, locations) in listing.locations.iter() {
+        let root = listing.roots[project];
+
+        for location in locations {
+            if root.as_os_str().is_empty() {
+                write!(out, "{}\0", location)?;
+            } else {
+                let location = location.trim_start_matches("./");
+                write!(out, "{}\0", root.join(location).display())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print what would run for the current changes.
+pub fn list(format: ListFormat) -> anyhow::Result<i32> {
+    let (_, config, projects) = commands::load_projects()?;
+    let files = commands::changed_files(&config, &projects)?;
+
+    let selections: Vec<Selection> = projects
+        .iter()
+        .map(|project| {
+            (
+                project.name.clone(),
+                project.root.clone(),
+                project.select(&files),
+            )
+        })
+        .collect();
+    let listing = listing(&files, &selections);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    match format {
+        ListFormat::Text => write_text(&mut out, &listing)?,
+        ListFormat::Json => write_json(&mut out, &listing)?,
+        ListFormat::Null => write_null(&mut out, &listing)?,
+    }
+
+    Ok(SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Delta;
+    use serde_json::{json, Value};
+    use std::path::PathBuf;
+
+    fn spec(changed: &str, location: &str, reason: Reason) -> SpecMatch {
+        SpecMatch {
+            changed: PathBuf::from(changed),
+            location: location.to_owned(),
+            reason,
+        }
+    }
+
+    fn rule() -> Reason {
+        Reason::Rule {
+            pattern: String::from("app/(.+)\\.rb"),
+            target: String::from("spec/$1_spec.rb"),
+        }
+    }
+
+    fn files() -> Vec<ChangedFile> {
+        vec![
+            ChangedFile {
+                path: PathBuf::from("web/app/models/user.rb"),
+                status: Delta::Modified,
+            },
+            ChangedFile {
+                path: PathBuf::from("web/app/models/account.rb"),
+                status: Delta::Added,
+            },
+        ]
+    }
+
+    fn selections() -> Vec<Selection> {
+        vec![
+            (
+                String::from("web"),
+                PathBuf::from("web"),
+                vec![
+                    spec("web/app/models/user.rb", "spec/models/user_spec.rb", rule()),
+                    spec(
+                        "web/app/models/account.rb",
+                        "spec/models/user_spec.rb",
+                        Reason::Requires { depth: 1 },
+                    ),
+                    spec(
+                        "web/app/models/user.rb",
+                        "spec/requests/users_spec.rb",
+                        Reason::CoChange { score: 0.5 },
+                    ),
+                ],
+            ),
+            (String::from("admin"), PathBuf::from("admin"), vec![]),
+        ]
+    }
+
+    fn output(write: fn(&mut Vec<u8>, &Listing) -> io::Result<()>) -> String {
+        let (files, selections) = (files(), selections());
+        let mut out = vec![];
+        write(&mut out, &listing(&files, &selections)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn text_lists_specs_under_each_file() {
+        assert_eq!(
+            output(write_text).lines().collect::<Vec<_>>(),
+            vec![
+                "M web/app/models/user.rb",
+                "    spec/models/user_spec.rb (web: maps from app/(.+)\\.rb)",
+                "    ? spec/requests/users_spec.rb (web: changed with it in 50% of commits)",
+                "A web/app/models/account.rb",
+                "    spec/models/user_spec.rb (web: requires it)",
+            ]
+        );
+    }
+
+    #[test]
+    fn json_attributes_each_spec_to_its_rule() {
+        let listing: Value = serde_json::from_str(&output(write_json)).unwrap();
+
+        assert_eq!(
+            listing,
+            json!({
+                "files": [
+                    {
+                        "path": "web/app/models/user.rb",
+                        "status": "M",
+                        "specs": [
+                            {
+                                "project": "web",
+                                "location": "spec/models/user_spec.rb",
+                                "reason": {
+                                    "type": "rule",
+                                    "pattern": "app/(.+)\\.rb",
+                                    "target": "spec/$1_spec.rb",
+                                },
+                            },
+                            {
+                                "project": "web",
+                                "location": "spec/requests/users_spec.rb",
+                                "reason": { "type": "co_change", "score": 0.5 },
+                            },
+                        ],
+                    },
+                    {
+                        "path": "web/app/models/account.rb",
+                        "status": "A",
+                        "specs": [
+                            {
+                                "project": "web",
+                                "location": "spec/models/user_spec.rb",
+                                "reason": { "type": "requires", "depth": 1 },
+                            },
+                        ],
+                    },
+                ],
+                "locations": { "web": ["spec/models/user_spec.rb"] },
+            })
+        );
+    }
+
+    #[test]
+    fn null_separates_each_location_to_run_once() {
+        assert_eq!(output(write_null), "web/spec/models/user_spec.rb\0");
+    }
+
+    #[test]
+    fn null_locations_are_relative_to_the_repository_root() {
+        let files = files();
+        let selections = vec![
+            (
+                String::from("admin"),
+                PathBuf::from("apps/admin"),
+                vec![spec(
+                    "apps/admin/app/models/user.rb",
+                    "./spec/models/user_spec.rb[1:2]",
+                    Reason::Coverage,
+                )],
+            ),
+            (
+                String::from("root"),
+                PathBuf::new(),
+                vec![spec("lib/tasks.rb", "spec/lib/tasks_spec.rb:4", rule())],
+            ),
+        ];
+        let mut out = vec![];
+
+        write_null(&mut out, &listing(&files, &selections)).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "apps/admin/spec/models/user_spec.rb[1:2]\0spec/lib/tasks_spec.rb:4\0"
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use crate::app_state::AppStateManager;
+use crate::cli::Reporter;
+use crate::commands::{self, FAILURE, SUCCESS};
+use crate::mapping;
+use crate::test_runner::{RunId, TestRunner};
+
+use std::collections::HashSet;
+use tokio::sync::broadcast::RecvError;
+
+/// Select the specs for everything changed, run them once and report.
+pub async fn run() -> anyhow::Result<i32> {
+    let (_, config, projects) = commands::load_projects()?;
+    let files = commands::changed_files(&config, &projects)?;
+
+    let state_manager = AppStateManager::new();
+    let mut events = state_manager.subscribe();
+    let mut runner = TestRunner::new(state_manager.dispatcher());
+    let mut reporter = Reporter::stdout(&projects);
+
+    reporter.changed_files(&files)?;
+
+    let mut running: HashSet<RunId> = HashSet::new();
+    for project in projects.iter() {
+        let matches = project.select(&files);
+        let locations = mapping::locations(&matches);
+
+        if !matches.is_empty() {
+            reporter.specs_selected(&project.name, &matches)?;
+        }
+
+        if !locations.is_empty() {
+            let run = runner.queue(project, locations).await?;
+            running.insert(run.id);
+        }
+    }
+
+    if running.is_empty() {
+        println!("No specs to run");
+        return Ok(SUCCESS);
+    }
+
+    let mut passed = true;
+    while !running.is_empty() {
+        let (event, state) = match events.recv().await {
+            Ok(received) => received,
+            // Missed events are only missed from the report, as whether the
+            // runs have finished comes from the state
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        };
+
+        reporter.on_event(&event, &state)?;
+
+        for results in state.runs.values() {
+            if results.finished && running.remove(&results.run) {
+                passed &= results.failed() == 0 && results.errors.is_empty();
+            }
+        }
+    }
+
+    Ok(if passed { SUCCESS } else { FAILURE })
+}
@@ -1,31 +1,289 @@
 use crate::ruby::rspec::RSpecConfiguration;
-use config::{Config, ConfigError};
+use config::Config;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+const CONFIG_FILE: &str = "spec_detect.toml";
+
+/// Selection strategies used alongside the `[map]` rules.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SelectConfiguration {
+    /// Select specs that require a changed file, directly or transitively
+    pub requires: bool,
+    /// Select specs that reference the constant an autoloaded file defines
+    pub constants: bool,
+    /// Constant names for file names that don't camelize simply, such as
+    /// `api = "API"`
+    pub inflections: HashMap<String, String>,
+    /// Record which files each example executes while running specs, and
+    /// select exactly the examples that executed a changed file
+    pub coverage: bool,
+    /// Suggest specs that have often changed in the same commits as a
+    /// changed file
+    pub co_change: bool,
+    /// How many commits of history to learn co-changes from
+    pub co_change_commits: usize,
+    /// The share of a file's commits a spec must appear in to be suggested
+    pub co_change_min_score: f64,
+    /// Select specs that use a factory, fixture, shared example group or
+    /// support module when its definition changes
+    pub support: bool,
+}
+
+impl Default for SelectConfiguration {
+    fn default() -> Self {
+        SelectConfiguration {
+            requires: false,
+            constants: false,
+            inflections: HashMap::new(),
+            coverage: false,
+            co_change: false,
+            co_change_commits: 500,
+            co_change_min_score: 0.3,
+            support: false,
+        }
+    }
+}
+
+fn default_spec_files() -> Vec<String> {
+    vec![String::from("spec/**/*_spec.rb")]
+}
+
+/// A single project within the repository. Paths in `include` and `map` are
+/// relative to `root`, which is itself relative to the directory
+/// spec_detect.toml is in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectConfiguration {
+    pub name: String,
+    pub root: String,
+    /// A named set of mapping rules, such as `rails`, that `map` is layered
+    /// over. Its include globs are used if `include` is empty.
+    pub preset: Option<String>,
+    pub include: Vec<String>,
+    pub rspec: RSpecConfiguration,
+    pub map: HashMap<String, Vec<(String, String)>>,
+    pub spec_files: Vec<String>,
+    pub select: SelectConfiguration,
+}
+
+impl Default for ProjectConfiguration {
+    fn default() -> Self {
+        ProjectConfiguration {
+            name: String::from("default"),
+            root: String::from("."),
+            preset: None,
+            include: vec![],
+            rspec: RSpecConfiguration::default(),
+            map: HashMap::new(),
+            spec_files: default_spec_files(),
+            select: SelectConfiguration::default(),
+        }
+    }
+}
+
+/// `path` without `.` components and with `..` applied to what precedes it,
+/// so project roots compare equal to the paths changed files have.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normal.file_name().is_some() => {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+
+    normal
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Configuration {
     pub branch: String,
+    pub preset: Option<String>,
     pub include: Vec<String>,
     pub rspec: RSpecConfiguration,
     pub map: HashMap<String, Vec<(String, String)>>,
+    pub spec_files: Vec<String>,
+    pub select: SelectConfiguration,
+    pub projects: Vec<ProjectConfiguration>,
+    /// Arguments for opening a file at a line, keyed by the editor's command
+    /// name, e.g. `code = "--goto {file}:{line}"`. Editors not listed here or
+    /// known already are given `+{line} {file}`.
+    pub editors: HashMap<String, String>,
+    /// The directory spec_detect.toml was read from, relative to the
+    /// repository root
+    #[serde(skip)]
+    pub directory: PathBuf,
 }
 
 impl Default for Configuration {
     fn default() -> Self {
         Configuration {
             branch: String::from("master"),
+            preset: None,
             include: vec![],
             rspec: RSpecConfiguration::default(),
             map: HashMap::new(),
+            spec_files: default_spec_files(),
+            select: SelectConfiguration::default(),
+            projects: vec![],
+            editors: HashMap::new(),
+            directory: PathBuf::new(),
         }
     }
 }
 
 impl Configuration {
-    pub fn read_configuration() -> Result<Self, ConfigError> {
-        let mut config = Config::try_from(&Configuration::default())?;
-        config.merge(config::File::with_name("spec_detect"))?;
-        config.try_into()
+    /// Read the spec_detect.toml in `dir`, or the nearest directory above it,
+    /// from the repository root in the current directory. `dir` is relative
+    /// to the repository root, such as the one spec_detect was started in.
+    pub fn read_configuration(dir: &Path) -> anyhow::Result<Self> {
+        Self::read_from(Path::new("."), dir)
+    }
+
+    fn read_from(repo_root: &Path, dir: &Path) -> anyhow::Result<Self> {
+        let directory = dir
+            .ancestors()
+            .find(|d| repo_root.join(d).join(CONFIG_FILE).is_file())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "No {} in this directory or above it in the repository, run `spec_detect init` to create one",
+                    CONFIG_FILE
+                )
+            })?;
+
+        // Defaults come from serde rather than `Config::try_from`, which
+        // would set them as overrides of the file's values
+        let mut config = Config::new();
+        config.merge(config::File::from(
+            repo_root.join(directory).join(CONFIG_FILE),
+        ))?;
+
+        let mut config: Configuration = config.try_into()?;
+        config.directory = normalize(directory);
+        config.check_project_names()?;
+        Ok(config)
+    }
+
+    /// Runs and selections are kept by project name, so no two projects can
+    /// share one.
+    fn check_project_names(&self) -> anyhow::Result<()> {
+        let mut names = HashSet::new();
+
+        for project in self.projects.iter() {
+            if !names.insert(project.name.as_str()) {
+                anyhow::bail!(
+                    "More than one project is named {}, give each [[projects]] its own `name`",
+                    project.name
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The projects to watch, with roots relative to the repository root.
+    /// When no `[[projects]]` are declared the top level settings describe a
+    /// single project rooted where spec_detect.toml is.
+    pub fn projects(&self) -> Vec<ProjectConfiguration> {
+        let projects = if self.projects.is_empty() {
+            vec![ProjectConfiguration {
+                preset: self.preset.clone(),
+                include: self.include.clone(),
+                rspec: self.rspec.clone(),
+                map: self.map.clone(),
+                spec_files: self.spec_files.clone(),
+                select: self.select.clone(),
+                ..ProjectConfiguration::default()
+            }]
+        } else {
+            self.projects.clone()
+        };
+
+        projects
+            .into_iter()
+            .map(|project| ProjectConfiguration {
+                root: normalize(&self.directory.join(&project.root))
+                    .to_string_lossy()
+                    .into_owned(),
+                ..project
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_dir::TestDir;
+
+    /// A repository root holding spec_detect.toml files at each of `configs`.
+    fn repo_root(name: &str, configs: &[(&str, &str)]) -> TestDir {
+        let root = TestDir::new(&format!("configuration-{}", name));
+
+        for (dir, toml) in configs {
+            root.write(Path::new(dir).join(CONFIG_FILE), toml);
+        }
+        root.write("app/models/.keep", "");
+        root
+    }
+
+    fn roots(config: &Configuration) -> Vec<String> {
+        config.projects().into_iter().map(|p| p.root).collect()
+    }
+
+    #[test]
+    fn finds_the_configuration_above_a_subdirectory() {
+        let root = repo_root(
+            "above",
+            &[("", "[[projects]]\nname = \"web\"\nroot = \"apps/web\"\n")],
+        );
+
+        let config = Configuration::read_from(&root.path, Path::new("app/models")).unwrap();
+
+        assert_eq!(config.directory, PathBuf::new());
+        assert_eq!(roots(&config), vec!["apps/web"]);
+    }
+
+    #[test]
+    fn resolves_roots_against_the_configuration_directory() {
+        let root = repo_root(
+            "nested",
+            &[
+                ("", "branch = \"main\"\n"),
+                ("apps/web", "[[projects]]\nname = \"web\"\nroot = \".\"\n\n[[projects]]\nname = \"shared\"\nroot = \"../shared\"\n"),
+            ],
+        );
+        root.write("apps/web/app/.keep", "");
+
+        let config = Configuration::read_from(&root.path, Path::new("apps/web/app")).unwrap();
+
+        assert_eq!(config.directory, PathBuf::from("apps/web"));
+        assert_eq!(roots(&config), vec!["apps/web", "apps/shared"]);
+    }
+
+    #[test]
+    fn a_single_project_is_rooted_at_the_configuration() {
+        let root = repo_root("single", &[("apps/api", "include = [\"**/*.rb\"]\n")]);
+
+        let config = Configuration::read_from(&root.path, Path::new("apps/api")).unwrap();
+
+        assert_eq!(roots(&config), vec!["apps/api"]);
+    }
+
+    #[test]
+    fn reports_a_missing_configuration() {
+        let root = repo_root("missing", &[]);
+
+        let err = Configuration::read_from(&root.path, Path::new("app/models")).unwrap_err();
+
+        assert!(err.to_string().starts_with("No spec_detect.toml"));
     }
 }
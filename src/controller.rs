@@ -0,0 +1,229 @@
+use crate::app_state::{AppState, Event};
+use crate::mapping;
+use crate::project::Project;
+use crate::repo_watcher::{ChangedFile, CodeRepo};
+use crate::test_runner::TestRunner;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::stream::{Stream, StreamExt};
+use tokio::sync::mpsc;
+
+/// Selects and runs specs, both as files change and when asked to by an
+/// event such as `RunFile` or `RerunFailures`.
+pub struct Controller {
+    projects: Arc<Vec<Project>>,
+    /// Shared with the repository watcher
+    branch: Arc<Mutex<String>>,
+    runner: TestRunner,
+    dispatcher: mpsc::Sender<Event>,
+    state: AppState,
+}
+
+impl Controller {
+    pub fn new(
+        projects: Arc<Vec<Project>>,
+        branch: Arc<Mutex<String>>,
+        dispatcher: mpsc::Sender<Event>,
+    ) -> Self {
+        Controller {
+            projects,
+            branch,
+            runner: TestRunner::new(dispatcher.clone()),
+            dispatcher,
+            state: AppState::new(),
+        }
+    }
+
+    /// Select and run specs for changed files and for `commands`, the
+    /// events from `AppStateManager::commands`, until told to quit.
+    pub async fn run<S>(
+        mut self,
+        files: S,
+        mut commands: mpsc::UnboundedReceiver<(Event, AppState)>,
+    ) where
+        S: Stream<Item = Vec<ChangedFile>>,
+    {
+        tokio::pin!(files);
+
+        for project in self.projects.iter() {
+            if let Err(err) = self.index_spec_files(project).await {
+                self.report(err).await;
+            }
+
+            let history = project.results.lock().unwrap().examples().clone();
+            self.dispatcher
+                .send(Event::HistoryUpdated(project.name.clone(), history))
+                .await
+                .ok();
+        }
+
+        loop {
+            tokio::select! {
+                Some(files) = files.next() => {
+                    if self.state.watching {
+                        if let Err(err) = self.on_files_changed(files).await {
+                            self.report(err).await;
+                        }
+                    }
+                }
+                command = commands.recv() => {
+                    match command {
+                        Some((Event::Quit, _)) | None => break,
+                        Some((event, state)) => {
+                            self.state = state;
+                            if let Err(err) = self.on_event(event).await {
+                                self.report(err).await;
+                            }
+                        }
+                    }
+                }
+                else => break,
+            }
+        }
+    }
+
+    /// Have the front end show a problem, and carry on.
+    async fn report(&self, err: anyhow::Error) {
+        self.dispatcher
+            .clone()
+            .send(Event::Error(format!("{:#}", err)))
+            .await
+            .ok();
+    }
+
+    async fn on_event(&mut self, event: Event) -> anyhow::Result<()> {
+        match event {
+            Event::RunFile(path) => self.run_file(&path).await,
+            Event::RerunFailures => self.rerun_failures().await,
+            Event::RunAllChanged => {
+                let files = self.state.changed_files.clone();
+                self.select_and_run(&files).await
+            }
+            Event::RunLocations(name, locations) => {
+                match self.projects.iter().find(|p| p.name == name) {
+                    Some(project) => self.runner.queue(project, locations).await.map(|_| ()),
+                    None => Err(anyhow::anyhow!("No project named {}", name)),
+                }
+            }
+            Event::CancelRun => {
+                for project in self.projects.iter() {
+                    self.runner.cancel(&project.name);
+                }
+                Ok(())
+            }
+            Event::SetBranch(branch) => self.set_branch(branch).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Hand the branch to the repository watcher, which then reports what
+    /// has changed from it in place of the current changes.
+    async fn set_branch(&mut self, branch: String) -> anyhow::Result<()> {
+        if *self.branch.lock().unwrap() == branch {
+            return Ok(());
+        }
+
+        CodeRepo::open(".")?.check_branch(&branch)?;
+        *self.branch.lock().unwrap() = branch.clone();
+        self.dispatcher.send(Event::BranchChanged(branch)).await?;
+        self.dispatcher.send(Event::ClearChanged).await?;
+
+        Ok(())
+    }
+
+    async fn on_files_changed(&mut self, files: Vec<ChangedFile>) -> anyhow::Result<()> {
+        self.dispatcher
+            .send(Event::FilesChanged(files.clone()))
+            .await?;
+
+        for project in self.projects.iter() {
+            project.update(&files);
+
+            let new_spec = files
+                .iter()
+                .any(|f| f.status == git2::Delta::Added && project.is_spec_file(&f.path));
+            if new_spec {
+                self.index_spec_files(project).await?;
+            }
+        }
+
+        self.select_and_run(&files).await
+    }
+
+    async fn index_spec_files(&self, project: &Project) -> anyhow::Result<()> {
+        self.dispatcher
+            .clone()
+            .send(Event::SpecFilesIndexed(
+                project.name.clone(),
+                project.spec_files(),
+            ))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn select_and_run(&mut self, files: &[ChangedFile]) -> anyhow::Result<()> {
+        for project in self.projects.iter() {
+            let matches = project.select(files);
+            let locations = mapping::locations(&matches);
+
+            if !matches.is_empty() {
+                self.dispatcher
+                    .send(Event::SpecsSelected(project.name.clone(), matches))
+                    .await?;
+            }
+
+            if !locations.is_empty() {
+                if let Err(err) = self.runner.queue(project, locations).await {
+                    let err = err.context(format!("Could not run specs for {}", project.name));
+                    self.report(err).await;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn run_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        for project in self.projects.iter() {
+            let matches: Vec<_> = self
+                .state
+                .selections
+                .get(&project.name)
+                .into_iter()
+                .flatten()
+                .filter(|m| m.changed == path)
+                .cloned()
+                .collect();
+            let locations = mapping::locations(&matches);
+
+            if !locations.is_empty() {
+                self.runner.queue(project, locations).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn rerun_failures(&mut self) -> anyhow::Result<()> {
+        let mut failures: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for (run, example) in self.state.failures() {
+            // Example ids pick out exactly the failed example, where a line
+            // number could match others around it
+            failures
+                .entry(run.run.project.as_str())
+                .or_default()
+                .push(example.id.clone());
+        }
+
+        for project in self.projects.iter() {
+            if let Some(ids) = failures.get(project.name.as_str()) {
+                self.runner.queue(project, ids.clone()).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,20 +1,106 @@
 use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
+use std::time::Duration;
 
-use tokio::sync::watch::{self, Receiver};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
 
-use termion::event::Key;
-use termion::input::TermRead;
+use termion::event::{self, Event, Key};
 
-pub fn listen() -> Receiver<Key> {
-    let (tx, rx) = watch::channel(Key::Null);
+/// How long to wait for input before checking whether input is paused
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Stop reading keys, so that another program such as an editor can have
+/// the terminal's input.
+pub fn pause() {
+    PAUSED.store(true, Ordering::SeqCst);
+}
+
+pub fn resume() {
+    PAUSED.store(false, Ordering::SeqCst);
+}
+
+/// Whether there is input waiting on stdin. Reading blocks, so polling first
+/// is what lets the input thread notice it has been paused rather than
+/// taking the next key from whatever has the terminal.
+fn stdin_ready(timeout: Duration) -> bool {
+    let mut fds = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    unsafe { libc::poll(&mut fds, 1, timeout.as_millis() as libc::c_int) > 0 }
+}
+
+/// Read whatever input is waiting straight from stdin. Reading through
+/// `io::stdin` would buffer input that `poll` then can't see.
+fn read_stdin(buf: &mut [u8]) -> io::Result<usize> {
+    let read = unsafe {
+        libc::read(
+            libc::STDIN_FILENO,
+            buf.as_mut_ptr() as *mut libc::c_void,
+            buf.len(),
+        )
+    };
+
+    if read < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(read as usize)
+    }
+}
+
+/// The keys in a read from stdin. An escape on its own is the Esc key, as
+/// termion reads it, and anything that doesn't parse is dropped.
+fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut bytes = bytes.iter().map(|b| Ok(*b)).peekable();
+    let mut keys = vec![];
+
+    while let Some(Ok(byte)) = bytes.next() {
+        if byte == 0x1B && bytes.peek().is_none() {
+            keys.push(Key::Esc);
+        } else if let Ok(Event::Key(key)) = event::parse_event(byte, &mut bytes) {
+            keys.push(key);
+        }
+    }
+
+    keys
+}
+
+/// Every key pressed, in order. The channel closes when stdin does, or
+/// after an error reading it.
+pub fn listen() -> UnboundedReceiver<io::Result<Key>> {
+    let (tx, rx) = mpsc::unbounded_channel();
     let _input_handle = {
         thread::spawn(move || {
-            let stdin = io::stdin();
-            for evt in stdin.keys() {
-                if let Ok(key) = evt {
-                    if let Err(err) = tx.broadcast(key) {
-                        eprintln!("{}", err);
+            let mut buf = [0; 1024];
+
+            loop {
+                if PAUSED.load(Ordering::SeqCst) {
+                    thread::sleep(POLL_TIMEOUT);
+                    continue;
+                }
+
+                if !stdin_ready(POLL_TIMEOUT) {
+                    continue;
+                }
+
+                let read = match read_stdin(&mut buf) {
+                    Ok(0) => return,
+                    Ok(read) => read,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => {
+                        tx.send(Err(err)).ok();
+                        return;
+                    }
+                };
+
+                for key in parse_keys(&buf[..read]) {
+                    // Sending fails once the UI has stopped listening
+                    if tx.send(Ok(key)).is_err() {
                         return;
                     }
                 }
@@ -0,0 +1,486 @@
+mod diagnostics;
+mod lenses;
+mod transport;
+mod uri;
+
+use diagnostics::LineSpans;
+use lenses::LensFinder;
+
+use crate::app_state::{AppState, AppStateManager, Event};
+use crate::project::Project;
+use crate::rpc::protocol::{
+    Notification, Request, Response, RpcError, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND,
+    PARSE_ERROR,
+};
+use crate::ruby::rspec::RSpecEvent;
+use crate::Program;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::env;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::BufReader;
+use tokio::sync::broadcast::RecvError;
+use tokio::sync::mpsc;
+
+/// The command the code lenses run, with the project and its locations as
+/// arguments.
+pub const RUN_COMMAND: &str = "spec_detect.run";
+
+/// A language server on stdin and stdout. Failed examples are published as
+/// diagnostics where they failed, and code lenses run a spec file, one of
+/// its examples, or the specs a file maps to.
+pub struct LspApp {
+    projects: Arc<Vec<Project>>,
+}
+
+impl LspApp {
+    pub fn new(projects: Arc<Vec<Project>>) -> Self {
+        LspApp { projects }
+    }
+}
+
+#[derive(Deserialize)]
+struct TextDocument {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CodeLensParams {
+    text_document: TextDocument,
+}
+
+#[derive(Deserialize)]
+struct ExecuteCommandParams {
+    command: String,
+    #[serde(default)]
+    arguments: Vec<Value>,
+}
+
+fn params<'de, T: Deserialize<'de>>(params: &'de Value) -> Result<T, RpcError> {
+    T::deserialize(params).map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+}
+
+/// Whether the failures the diagnostics show may have changed.
+fn changes_failures(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::TestRunning(..)
+            | Event::TestEvent(_, RSpecEvent::ExampleFailed { .. })
+            | Event::TestEvent(_, RSpecEvent::Exit)
+    )
+}
+
+struct Server {
+    app: AppStateManager,
+    projects: Arc<Vec<Project>>,
+    /// The repository root, which changed files are relative to
+    cwd: PathBuf,
+    /// Nothing is published until the client has initialized
+    initialized: bool,
+    /// The files with diagnostics, to clear once they no longer fail
+    published: BTreeSet<String>,
+    spans: LineSpans,
+    lenses: LensFinder,
+    out: Box<dyn Write + Send + Sync>,
+}
+
+impl Server {
+    fn new(app: AppStateManager, projects: Arc<Vec<Project>>, cwd: PathBuf) -> Self {
+        Server {
+            app,
+            projects,
+            cwd,
+            initialized: false,
+            published: BTreeSet::new(),
+            spans: LineSpans::default(),
+            lenses: LensFinder::new(),
+            out: Box::new(io::stdout()),
+        }
+    }
+
+    fn send<T: Serialize>(&mut self, message: &T) -> anyhow::Result<()> {
+        transport::write_message(&mut self.out, message)
+    }
+
+    /// A document's path relative to the repository root.
+    fn relative_path(&self, uri: &str) -> Option<PathBuf> {
+        let path = uri::to_path(uri)?;
+        path.strip_prefix(&self.cwd)
+            .map(Path::to_path_buf)
+            .ok()
+            .or_else(|| {
+                let cwd = self.cwd.canonicalize().ok()?;
+                let path = path.canonicalize().ok()?;
+                path.strip_prefix(cwd).map(Path::to_path_buf).ok()
+            })
+    }
+
+    fn initialize(&self) -> Value {
+        // Lenses are found from the files on disk, so no document sync is
+        // asked for
+        json!({
+            "capabilities": {
+                "codeLensProvider": { "resolveProvider": false },
+                "executeCommandProvider": { "commands": [RUN_COMMAND] },
+            },
+            "serverInfo": {
+                "name": "spec_detect",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        })
+    }
+
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Value, RpcError> {
+        if params.command != RUN_COMMAND {
+            return Err(RpcError::new(
+                INVALID_PARAMS,
+                format!("No command {}", params.command),
+            ));
+        }
+
+        let (project, locations): (String, Vec<String>) =
+            serde_json::from_value(Value::Array(params.arguments)).map_err(|_| {
+                RpcError::new(INVALID_PARAMS, "Expected a project and its locations")
+            })?;
+        if !self.projects.iter().any(|p| p.name == project) {
+            return Err(RpcError::failed(format!("No project named {}", project)));
+        }
+
+        self.app
+            .dispatch(Event::RunLocations(project, locations))
+            .await
+            .map_err(RpcError::failed)?;
+        Ok(Value::Null)
+    }
+
+    async fn call(&mut self, method: &str, params_value: &Value) -> Result<Value, RpcError> {
+        match method {
+            "initialize" => Ok(self.initialize()),
+            "shutdown" => Ok(Value::Null),
+            "textDocument/codeLens" => {
+                let params: CodeLensParams = params(params_value)?;
+                let lenses = match self.relative_path(&params.text_document.uri) {
+                    Some(path) => self.lenses.lenses(&self.projects, &self.cwd, &path),
+                    None => vec![],
+                };
+                Ok(Value::Array(lenses))
+            }
+            "workspace/executeCommand" => self.execute_command(params(params_value)?).await,
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("No method {}", method),
+            )),
+        }
+    }
+
+    /// Handle a message from the client, returning whether it asked the
+    /// server to exit. Notifications other than `initialized` and `exit` and
+    /// responses to the server are ignored.
+    async fn on_message(&mut self, body: &[u8]) -> anyhow::Result<bool> {
+        let value: Value = match serde_json::from_slice(body) {
+            Ok(value) => value,
+            Err(err) => {
+                let error = RpcError::new(PARSE_ERROR, err.to_string());
+                self.send(&Response::new(Value::Null, Err(error)))?;
+                return Ok(false);
+            }
+        };
+        if value.get("method").is_none() {
+            return Ok(false);
+        }
+
+        let request: Request = match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(err) => {
+                let error = RpcError::new(INVALID_REQUEST, err.to_string());
+                self.send(&Response::new(Value::Null, Err(error)))?;
+                return Ok(false);
+            }
+        };
+
+        match request.id {
+            Some(id) => {
+                let result = self.call(&request.method, &request.params).await;
+                self.send(&Response::new(id, result))?;
+                Ok(false)
+            }
+            None => {
+                if request.method == "initialized" {
+                    self.initialized = true;
+                }
+                Ok(request.method == "exit")
+            }
+        }
+    }
+
+    /// Tell the user about a problem, as the client shows messages.
+    fn show_error(&mut self, message: &str) -> anyhow::Result<()> {
+        if !self.initialized {
+            return Ok(());
+        }
+
+        // A type of 1 is an error
+        self.send(&Notification::new(
+            "window/showMessage",
+            json!({ "type": 1, "message": message }),
+        ))
+    }
+
+    fn publish(&mut self, state: &AppState) -> anyhow::Result<()> {
+        if !self.initialized {
+            return Ok(());
+        }
+
+        let diagnostics = diagnostics::diagnostics(state, &self.cwd, &mut self.spans);
+        let cleared: Vec<String> = self
+            .published
+            .iter()
+            .filter(|uri| !diagnostics.contains_key(*uri))
+            .cloned()
+            .collect();
+
+        for uri in cleared {
+            self.send(&Notification::new(
+                "textDocument/publishDiagnostics",
+                json!({ "uri": uri, "diagnostics": [] }),
+            ))?;
+        }
+        for (uri, diagnostics) in diagnostics.iter() {
+            self.send(&Notification::new(
+                "textDocument/publishDiagnostics",
+                json!({ "uri": uri, "diagnostics": diagnostics }),
+            ))?;
+        }
+
+        self.published = diagnostics.keys().cloned().collect();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Program for LspApp {
+    async fn run<'stream>(&self, app: AppStateManager) -> anyhow::Result<()> {
+        let mut events = app.subscribe();
+        let mut server = Server::new(app, Arc::clone(&self.projects), env::current_dir()?);
+
+        // Messages are read on their own task, since a read cancelled
+        // part way through a message would lose the rest of it
+        let (mut tx, mut messages) = mpsc::channel::<Vec<u8>>(16);
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(tokio::io::stdin());
+            loop {
+                let body = match transport::read_message(&mut reader).await {
+                    Ok(Some(body)) => body,
+                    Ok(None) => break,
+                    Err(err) => {
+                        eprintln!("{:#}", err);
+                        break;
+                    }
+                };
+                if tx.send(body).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        loop {
+            tokio::select! {
+                message = messages.recv() => match message {
+                    Some(body) => {
+                        if server.on_message(&body).await? {
+                            break;
+                        }
+                    }
+                    // The client has gone, so there is no one to run for
+                    None => break,
+                },
+                event = events.recv() => match event {
+                    Ok((Event::Quit, _)) | Err(RecvError::Closed) => return Ok(()),
+                    Ok((event, state)) if changes_failures(&event) => {
+                        if let Event::TestRunning(..) = event {
+                            server.spans.clear();
+                        }
+                        server.publish(&state)?
+                    }
+                    Ok((Event::Error(message), _)) => server.show_error(&message)?,
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(_)) => server.publish(&server.app.state())?,
+                }
+            }
+        }
+
+        server.app.dispatch(Event::Quit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// What the server writes, kept for the test to read back.
+    #[derive(Clone, Default)]
+    struct Written(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Written {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Written {
+        /// The messages written since last taken.
+        async fn take(&self) -> Vec<Value> {
+            let bytes = std::mem::take(&mut *self.0.lock().unwrap());
+            let mut reader = BufReader::new(&bytes[..]);
+            let mut messages = vec![];
+            while let Some(body) = transport::read_message(&mut reader).await.unwrap() {
+                messages.push(serde_json::from_slice(&body).unwrap());
+            }
+            messages
+        }
+    }
+
+    fn server() -> (Server, Written) {
+        let written = Written::default();
+        let mut server = Server::new(
+            AppStateManager::new(),
+            Arc::new(vec![]),
+            PathBuf::from("/repo"),
+        );
+        server.out = Box::new(written.clone());
+        (server, written)
+    }
+
+    async fn send(server: &mut Server, message: Value) -> bool {
+        server
+            .on_message(&serde_json::to_vec(&message).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn initialize_advertises_only_what_is_handled() {
+        let (mut server, written) = server();
+
+        send(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+        )
+        .await;
+
+        let messages = written.take().await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["id"], 1);
+        assert_eq!(
+            messages[0]["result"]["capabilities"],
+            json!({
+                "codeLensProvider": { "resolveProvider": false },
+                "executeCommandProvider": { "commands": [RUN_COMMAND] },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn nothing_is_pushed_before_initialized() {
+        let (mut server, written) = server();
+
+        send(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+        )
+        .await;
+        written.take().await;
+        server.show_error("No spec_detect.toml").unwrap();
+        server.publish(&AppState::new()).unwrap();
+        assert!(written.take().await.is_empty());
+
+        send(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+        )
+        .await;
+        assert!(written.take().await.is_empty());
+        server.show_error("No spec_detect.toml").unwrap();
+
+        assert_eq!(
+            written.take().await,
+            vec![json!({
+                "jsonrpc": "2.0",
+                "method": "window/showMessage",
+                "params": { "type": 1, "message": "No spec_detect.toml" },
+            })]
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_are_answered_before_initialized() {
+        let (mut server, written) = server();
+
+        send(
+            &mut server,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "textDocument/codeLens",
+                "params": { "textDocument": { "uri": "file:///elsewhere/a.rb" } },
+            }),
+        )
+        .await;
+        send(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "textDocument/hover", "params": {} }),
+        )
+        .await;
+        send(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 4, "method": "textDocument/codeLens", "params": {} }),
+        )
+        .await;
+
+        let messages = written.take().await;
+        assert_eq!(
+            messages[0],
+            json!({ "jsonrpc": "2.0", "id": 2, "result": [] })
+        );
+        assert_eq!(messages[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(messages[2]["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn notifications_get_no_response_and_exit_stops() {
+        let (mut server, written) = server();
+
+        let did_save = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didSave",
+            "params": { "textDocument": { "uri": "file:///repo/a.rb" } },
+        });
+        assert!(!send(&mut server, did_save).await);
+        assert!(send(&mut server, json!({ "jsonrpc": "2.0", "method": "exit" })).await);
+        assert!(written.take().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn bad_messages_are_errors() {
+        let (mut server, written) = server();
+
+        server.on_message(b"{").await.unwrap();
+        send(&mut server, json!({ "jsonrpc": "2.0", "id": 1 })).await;
+
+        let messages = written.take().await;
+        assert_eq!(messages[0]["error"]["code"], PARSE_ERROR);
+        // Without a method it is taken as a response to the server
+        assert_eq!(messages.len(), 1);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::app_state::AppState;
+use crate::lsp::uri;
+use crate::test_runner::ExampleResult;
+
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Where the lines failures are on start and end, in the UTF-16 code units
+/// LSP counts characters in. Each is read once, as every failure publishes
+/// them all again, and forgotten when a run starts since the files may have
+/// changed.
+#[derive(Default)]
+pub struct LineSpans {
+    spans: HashMap<(PathBuf, usize), (usize, usize)>,
+}
+
+impl LineSpans {
+    pub fn clear(&mut self) {
+        self.spans.clear();
+    }
+
+    fn get(&mut self, path: &Path, line: usize) -> (usize, usize) {
+        *self
+            .spans
+            .entry((path.to_path_buf(), line))
+            .or_insert_with(|| {
+                let source = fs::read_to_string(path).unwrap_or_default();
+                let text = source.lines().nth(line).unwrap_or_default();
+                let indent = &text[..text.len() - text.trim_start().len()];
+
+                (indent.encode_utf16().count(), text.encode_utf16().count())
+            })
+    }
+}
+
+fn message(example: &ExampleResult) -> String {
+    match (&example.description, &example.exception) {
+        (Some(description), Some(exception)) => format!("{}\n{}", description, exception),
+        (Some(text), None) | (None, Some(text)) => text.clone(),
+        (None, None) => format!("{} failed", example.id),
+    }
+}
+
+/// The diagnostics for the failed examples of the latest runs, by the URI of
+/// the file each failed in.
+pub fn diagnostics(
+    state: &AppState,
+    cwd: &Path,
+    spans: &mut LineSpans,
+) -> BTreeMap<String, Vec<Value>> {
+    let mut diagnostics: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+
+    for (run, example) in state.failures() {
+        let frame = match example.failure_frame() {
+            Some(frame) => frame,
+            None => continue,
+        };
+        let path: PathBuf = cwd
+            .join(&run.root)
+            .join(&frame.path)
+            .components()
+            .filter(|c| *c != Component::CurDir)
+            .collect();
+        let line = frame.line.saturating_sub(1);
+        let (start, end) = spans.get(&path, line);
+
+        diagnostics
+            .entry(uri::from_path(&path))
+            .or_default()
+            .push(json!({
+                "range": {
+                    "start": { "line": line, "character": start },
+                    "end": { "line": line, "character": end },
+                },
+                "severity": 1,
+                "source": "spec_detect",
+                "code": example.id,
+                "message": message(example),
+            }));
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ruby::rspec::RSpecEvent;
+    use crate::test_runner::{RunId, RunResults};
+    use crate::util::test_dir::TestDir;
+
+    const SPEC: &str =
+        "describe User do\n  it \"has a name\" do\n    expect(user.name).to eq \"é\"\n  end\nend\n";
+
+    fn failed(id: &str, location: &str, backtrace: &[&str]) -> RSpecEvent {
+        RSpecEvent::ExampleFailed {
+            id: id.to_owned(),
+            load_time: None,
+            location: Some(location.to_owned()),
+            description: Some(String::from("User has a name")),
+            run_time: 0.1,
+            exception: Some(String::from("expected \"é\"")),
+            backtrace: Some(backtrace.iter().map(|l| l.to_string()).collect()),
+        }
+    }
+
+    fn state(events: Vec<RSpecEvent>) -> AppState {
+        let run = RunId {
+            project: String::from("web"),
+            number: 1,
+        };
+        let mut results = RunResults::new(run, PathBuf::from("web"), vec![]);
+        for event in events {
+            results.on_rspec_event(event);
+        }
+
+        let mut state = AppState::new();
+        state.runs.insert(String::from("web"), results);
+        state
+    }
+
+    #[test]
+    fn failures_are_on_the_line_that_raised() {
+        let dir = TestDir::new("diagnostics-ranges");
+        dir.write("web/spec/models/user_spec.rb", SPEC);
+        let state = state(vec![failed(
+            "./spec/models/user_spec.rb[1:1]",
+            "./spec/models/user_spec.rb:2",
+            &[
+                "./app/models/user.rb:4:in `name'",
+                "./spec/models/user_spec.rb:3:in `block (2 levels)'",
+            ],
+        )]);
+
+        let diagnostics = diagnostics(&state, &dir.path, &mut LineSpans::default());
+
+        let uri = uri::from_path(&dir.path.join("web/spec/models/user_spec.rb"));
+        assert_eq!(diagnostics.keys().collect::<Vec<_>>(), vec![&uri]);
+        assert_eq!(
+            diagnostics[&uri],
+            vec![json!({
+                "range": {
+                    "start": { "line": 2, "character": 4 },
+                    "end": { "line": 2, "character": 31 },
+                },
+                "severity": 1,
+                "source": "spec_detect",
+                "code": "./spec/models/user_spec.rb[1:1]",
+                "message": "User has a name\nexpected \"é\"",
+            })]
+        );
+    }
+
+    #[test]
+    fn failures_without_a_backtrace_are_at_the_example() {
+        let dir = TestDir::new("diagnostics-location");
+        dir.write("web/spec/models/user_spec.rb", SPEC);
+        let state = state(vec![failed(
+            "./spec/models/user_spec.rb[1:1]",
+            "./spec/models/user_spec.rb:2",
+            &[],
+        )]);
+
+        let diagnostics = diagnostics(&state, &dir.path, &mut LineSpans::default());
+
+        let uri = uri::from_path(&dir.path.join("web/spec/models/user_spec.rb"));
+        assert_eq!(
+            diagnostics[&uri][0]["range"],
+            json!({
+                "start": { "line": 1, "character": 2 },
+                "end": { "line": 1, "character": 20 },
+            })
+        );
+    }
+
+    #[test]
+    fn line_spans_are_read_once_until_cleared() {
+        let dir = TestDir::new("diagnostics-spans");
+        dir.write("spec/a_spec.rb", "  it\n");
+        let path = dir.path.join("spec/a_spec.rb");
+        let mut spans = LineSpans::default();
+
+        assert_eq!(spans.get(&path, 0), (2, 4));
+        dir.write("spec/a_spec.rb", "it\n");
+        assert_eq!(spans.get(&path, 0), (2, 4));
+
+        spans.clear();
+        assert_eq!(spans.get(&path, 0), (0, 2));
+    }
+}
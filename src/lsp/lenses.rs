@@ -0,0 +1,210 @@
+use crate::lsp::RUN_COMMAND;
+use crate::mapping;
+use crate::project::Project;
+use crate::repo_watcher::ChangedFile;
+
+use regex::Regex;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+/// Lines that start an example, which rspec can run by `file:line`.
+const EXAMPLE_PATTERN: &str = r"^\s*(?:it|its|specify|example|scenario)(?:\s|\(|\{|$)";
+
+fn lens(line: usize, title: &str, project: &Project, locations: Vec<String>) -> Value {
+    json!({
+        "range": {
+            "start": { "line": line, "character": 0 },
+            "end": { "line": line, "character": 0 },
+        },
+        "command": {
+            "title": title,
+            "command": RUN_COMMAND,
+            "arguments": [project.name, locations],
+        },
+    })
+}
+
+/// Finds the code lenses for files, with the example pattern compiled once.
+pub struct LensFinder {
+    example: Regex,
+}
+
+impl LensFinder {
+    pub fn new() -> Self {
+        LensFinder {
+            example: Regex::new(EXAMPLE_PATTERN).unwrap(),
+        }
+    }
+
+    /// The code lenses for a file relative to the repository root at `cwd`: a
+    /// spec file can be run whole or an example at a time, and any other file
+    /// can run the specs it maps to.
+    pub fn lenses(&self, projects: &[Project], cwd: &Path, path: &Path) -> Vec<Value> {
+        let mut lenses = vec![];
+
+        for project in projects.iter().filter(|p| p.include_path(path)) {
+            let relative = match project.relative_path(path) {
+                Some(relative) => relative.to_string_lossy().into_owned(),
+                None => continue,
+            };
+
+            if project.is_spec_file(path) {
+                lenses.push(lens(0, "Run this file", project, vec![relative.clone()]));
+
+                let source = fs::read_to_string(cwd.join(path)).unwrap_or_default();
+                for (number, _) in source
+                    .lines()
+                    .enumerate()
+                    .filter(|(_, line)| self.example.is_match(line))
+                {
+                    let location = format!("{}:{}", relative, number + 1);
+                    lenses.push(lens(number, "Run this example", project, vec![location]));
+                }
+            } else {
+                let matches = project.select(&[ChangedFile::new(path.to_path_buf())]);
+                let locations = mapping::locations(&matches);
+                let title = match locations.len() {
+                    0 => continue,
+                    1 => String::from("Run the spec for this file"),
+                    n => format!("Run {} specs for this file", n),
+                };
+                lenses.push(lens(0, &title, project, locations));
+            }
+        }
+
+        lenses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::ProjectConfiguration;
+    use crate::util::test_dir::TestDir;
+    use std::collections::HashMap;
+    use tokio::sync::mpsc;
+
+    /// A project rooted at `root`, which is relative to the repository root
+    /// or absolute.
+    fn projects(root: &Path) -> Vec<Project> {
+        let mut map = HashMap::new();
+        map.insert(
+            String::from("rspec"),
+            vec![
+                (
+                    String::from(r"app/models/(.+)\.rb"),
+                    String::from("spec/models/$1_spec.rb"),
+                ),
+                (
+                    String::from(r"app/models/admin/(.+)\.rb"),
+                    String::from("spec/admin/$1_spec.rb"),
+                ),
+            ],
+        );
+        let config = ProjectConfiguration {
+            name: String::from("web"),
+            root: root.to_string_lossy().into_owned(),
+            include: vec![String::from("app/**/*.rb"), String::from("spec/**/*.rb")],
+            map,
+            ..ProjectConfiguration::default()
+        };
+        let (problems, _) = mpsc::unbounded_channel();
+
+        vec![Project::new(&config, &problems).unwrap()]
+    }
+
+    /// Each lens's line, title and command arguments.
+    fn lenses(dir: &TestDir, root: &Path, path: &str) -> Vec<(u64, String, Value)> {
+        LensFinder::new()
+            .lenses(&projects(root), &dir.path, &root.join(path))
+            .into_iter()
+            .map(|lens| {
+                (
+                    lens["range"]["start"]["line"].as_u64().unwrap(),
+                    lens["command"]["title"].as_str().unwrap().to_owned(),
+                    lens["command"]["arguments"].clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spec_files_run_whole_or_an_example_at_a_time() {
+        let dir = TestDir::new("lenses-spec");
+        dir.write(
+            "web/spec/models/user_spec.rb",
+            "describe User do\n  it \"has a name\" do\n  end\n\n  specify { expect(1).to eq 1 }\n  its(:name) { is_expected.to eq \"x\" }\n  it_behaves_like \"a model\"\n  items = []\nend\n",
+        );
+
+        let run = |location: &str| json!(["web", [location]]);
+        assert_eq!(
+            lenses(&dir, Path::new("web"), "spec/models/user_spec.rb"),
+            vec![
+                (
+                    0,
+                    String::from("Run this file"),
+                    run("spec/models/user_spec.rb")
+                ),
+                (
+                    1,
+                    String::from("Run this example"),
+                    run("spec/models/user_spec.rb:2")
+                ),
+                (
+                    4,
+                    String::from("Run this example"),
+                    run("spec/models/user_spec.rb:5")
+                ),
+                (
+                    5,
+                    String::from("Run this example"),
+                    run("spec/models/user_spec.rb:6")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_files_run_the_specs_they_map_to() {
+        let dir = TestDir::new("lenses-mapped");
+        dir.write("web/spec/models/user_spec.rb", "")
+            .write("web/spec/models/admin/role_spec.rb", "")
+            .write("web/spec/admin/role_spec.rb", "");
+        // Mapped specs are looked for from the current directory unless the
+        // root is absolute
+        let root = dir.path.join("web");
+
+        assert_eq!(
+            lenses(&dir, &root, "app/models/user.rb"),
+            vec![(
+                0,
+                String::from("Run the spec for this file"),
+                json!(["web", ["spec/models/user_spec.rb"]])
+            )]
+        );
+        assert_eq!(
+            lenses(&dir, &root, "app/models/admin/role.rb"),
+            vec![(
+                0,
+                String::from("Run 2 specs for this file"),
+                json!([
+                    "web",
+                    ["spec/models/admin/role_spec.rb", "spec/admin/role_spec.rb"]
+                ])
+            )]
+        );
+    }
+
+    #[test]
+    fn files_outside_projects_or_unmapped_have_none() {
+        let dir = TestDir::new("lenses-none");
+        let web = Path::new("web");
+
+        assert!(lenses(&dir, Path::new(""), "README.md").is_empty());
+        assert!(lenses(&dir, web, "app/views/users/index.html.erb").is_empty());
+        assert!(lenses(&dir, web, "config/routes.rb").is_empty());
+        // No spec to run yet
+        assert!(lenses(&dir, web, "app/models/user.rb").is_empty());
+    }
+}
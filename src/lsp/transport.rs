@@ -0,0 +1,78 @@
+use serde::Serialize;
+use std::io::Write;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+/// Read the body of a message framed by a `Content-Length` header, or None
+/// once the input ends.
+pub async fn read_message<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut length = None;
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        // The only other header, Content-Type, is always the default
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+
+    let length = length.ok_or_else(|| anyhow::anyhow!("Message without a Content-Length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Some(body))
+}
+
+pub fn write_message<W: Write, T: Serialize>(out: &mut W, message: &T) -> anyhow::Result<()> {
+    let body = serde_json::to_vec(message)?;
+
+    write!(out, "Content-Length: {}\r\n\r\n", body.len())?;
+    out.write_all(&body)?;
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::BufReader;
+
+    #[tokio::test]
+    async fn messages_are_framed_by_content_length() {
+        let input = b"Content-Length: 2\r\n\r\n{}Content-Type: application/vscode-jsonrpc; charset=utf-8\r\nContent-Length: 4\r\n\r\nnull";
+        let mut reader = BufReader::new(&input[..]);
+
+        assert_eq!(
+            read_message(&mut reader).await.unwrap(),
+            Some(b"{}".to_vec())
+        );
+        assert_eq!(
+            read_message(&mut reader).await.unwrap(),
+            Some(b"null".to_vec())
+        );
+        assert_eq!(read_message(&mut reader).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn a_message_needs_a_content_length() {
+        let mut reader = BufReader::new(&b"Content-Type: text/plain\r\n\r\n{}"[..]);
+
+        assert!(read_message(&mut reader).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn a_truncated_body_is_an_error() {
+        let mut reader = BufReader::new(&b"Content-Length: 10\r\n\r\n{}"[..]);
+
+        assert!(read_message(&mut reader).await.is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+/// The `file://` URI of an absolute path.
+pub fn from_path(path: &Path) -> String {
+    let mut uri = String::from("file://");
+
+    for &byte in path.as_os_str().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => write!(uri, "%{:02X}", byte).unwrap(),
+        }
+    }
+
+    uri
+}
+
+/// The path of a `file://` URI.
+pub fn to_path(uri: &str) -> Option<PathBuf> {
+    let mut encoded = uri.strip_prefix("file://")?.bytes();
+    let mut bytes = vec![];
+
+    while let Some(byte) = encoded.next() {
+        if byte == b'%' {
+            let hex = [encoded.next()?, encoded.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_path_escapes_reserved_bytes() {
+        assert_eq!(
+            from_path(Path::new("/src/my app/a+b.rb")),
+            "file:///src/my%20app/a%2Bb.rb"
+        );
+    }
+
+    #[test]
+    fn to_path_decodes_escapes() {
+        assert_eq!(
+            to_path("file:///src/my%20app/a%2bb.rb"),
+            Some(PathBuf::from("/src/my app/a+b.rb"))
+        );
+    }
+
+    #[test]
+    fn non_utf8_paths_round_trip() {
+        let path = PathBuf::from(OsString::from_vec(b"/src/\xffcaf\xc3\xa9.rb".to_vec()));
+
+        assert_eq!(to_path(&from_path(&path)), Some(path));
+    }
+
+    #[test]
+    fn other_uris_and_bad_escapes_have_no_path() {
+        assert_eq!(to_path("untitled:Untitled-1"), None);
+        assert_eq!(to_path("file:///src/a%2"), None);
+        assert_eq!(to_path("file:///src/a%zz.rb"), None);
+    }
+}
@@ -5,65 +5,129 @@ extern crate tokio;
 use structopt::StructOpt;
 mod app_state;
 mod cli;
+mod commands;
 mod configuration;
+mod controller;
 mod input;
+mod lsp;
+mod mapping;
+mod ndjson;
 mod program;
+mod project;
 mod repo_watcher;
+mod rpc;
 mod ruby;
 mod some_loop;
 mod test_runner;
 mod ui;
 mod util;
 
-use app_state::{AppStateManager, Event};
+use app_state::AppStateManager;
 use configuration::Configuration;
-use repo_watcher::{ChangedFile, RepoWatcher};
-// use ruby::{RSpec, RSpecConfiguration};
-use util::path_filter::PathFilter;
+use controller::Controller;
+use project::Project;
+use repo_watcher::{ChangedFile, CodeRepo, RepoWatcher};
 
 use anyhow::{Context, Result};
-use program::Program;
+use program::{OutputFormat, Program};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::stream::{Stream, StreamExt};
+use tokio::sync::mpsc::{self, UnboundedSender};
 
 use state::LocalStorage;
 
 static CONFIG: LocalStorage<Configuration> = LocalStorage::new();
 
+/// The changed files the projects include, and the branch they are changes
+/// from, which can be changed while watching.
 fn watch_repo(
     branch: &str,
-    path_filter: PathFilter,
-) -> Result<impl Stream<Item = Vec<ChangedFile>>> {
+    projects: Arc<Vec<Project>>,
+    problems: UnboundedSender<String>,
+) -> Result<(impl Stream<Item = Vec<ChangedFile>>, Arc<Mutex<String>>)> {
     let watcher = RepoWatcher::new(".", branch)?;
-    Ok(watcher
-        .watch(Duration::from_millis(1000), true)
+    let files = watcher
+        .watch(Duration::from_millis(1000), true, problems)?
         .map(move |files| {
             files
                 .into_iter()
-                .filter(|f| path_filter.include_path(&f.path))
+                .filter(|f| projects.iter().any(|p| p.include_path(&f.path)))
                 .collect::<Vec<ChangedFile>>()
-        }))
+        });
+
+    Ok((files, watcher.branch()))
 }
 
-fn program_from_opt(opt: &program::Opt) -> Box<dyn Program> {
-    if opt.cli {
-        Box::new(cli::CliApp {})
-    } else {
-        Box::new(ui::TuiApp {})
+fn program_from_opt(opt: &program::Opt, projects: Arc<Vec<Project>>) -> Box<dyn Program> {
+    match opt.format {
+        Some(OutputFormat::Ndjson) => Box::new(ndjson::NdjsonApp {}),
+        Some(OutputFormat::Jsonrpc) => Box::new(rpc::RpcApp {}),
+        Some(OutputFormat::Text) => Box::new(cli::CliApp::new(projects)),
+        Some(OutputFormat::Tui) => Box::new(ui::TuiApp {}),
+        None if opt.cli => Box::new(cli::CliApp::new(projects)),
+        None => Box::new(ui::TuiApp {}),
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let opt = program::Opt::from_args();
-    let config = Configuration::read_configuration()?;
-    dbg!(&config);
-    let path_filter = PathFilter::new(&config).context("Invalid include configuration")?;
+/// Work from the root of the repository's working tree, which changed files
+/// and project roots are relative to, wherever spec_detect was started.
+/// Returns the directory it was started in, relative to that root.
+pub fn enter_repository() -> Result<PathBuf> {
+    let workdir = CodeRepo::open(".")
+        .context("Could not open the repository")?
+        .path()
+        .context("The repository has no working tree")?;
+    let started = std::env::current_dir()?.canonicalize()?;
+
+    std::env::set_current_dir(&workdir)
+        .with_context(|| format!("Could not change directory to {}", workdir.display()))?;
+
+    Ok(started
+        .strip_prefix(&workdir)
+        .map(Path::to_path_buf)
+        .unwrap_or_default())
+}
+
+/// Watch for changes and run their specs until quit, with the front end
+/// `program` makes for the projects.
+pub async fn watch<F>(program: F) -> Result<()>
+where
+    F: FnOnce(Arc<Vec<Project>>) -> Box<dyn Program>,
+{
+    let started = enter_repository()?;
+    let config = Configuration::read_configuration(&started)?;
+    let state_manager = AppStateManager::new();
+
+    // Problems found in the background are shown by the front end, which
+    // may have the terminal
+    let (problems, mut problems_rx) = mpsc::unbounded_channel::<String>();
+    let mut problems_dispatcher = state_manager.dispatcher();
+    tokio::spawn(async move {
+        while let Some(problem) = problems_rx.recv().await {
+            if problems_dispatcher
+                .send(app_state::Event::Error(problem))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    let projects = Arc::new(
+        Project::from_configuration(&config, &problems).context("Invalid project configuration")?,
+    );
 
     CONFIG.set(move || config.to_owned());
+    let program = program(Arc::clone(&projects));
 
-    let changed_files_stream = watch_repo(CONFIG.get().branch.as_str(), path_filter)?;
-    let state_manager = AppStateManager::new();
+    let (changed_files_stream, branch) = watch_repo(
+        CONFIG.get().branch.as_str(),
+        Arc::clone(&projects),
+        problems,
+    )?;
 
     let mut ctrl_c_dispatcher = state_manager.dispatcher();
     tokio::spawn(async move {
@@ -71,33 +135,27 @@ async fn main() -> Result<()> {
         ctrl_c_dispatcher.send(app_state::Event::Quit).await
     });
 
-    let mut files_dispatcher = state_manager.dispatcher();
-    tokio::spawn(async move {
-        tokio::pin!(changed_files_stream);
+    let controller = Controller::new(projects, branch, state_manager.dispatcher());
+    let commands = state_manager
+        .commands()
+        .context("The controller's commands are already taken")?;
+    tokio::spawn(controller.run(changed_files_stream, commands));
+    // The watcher has checked the configured branch exists already
+    state_manager
+        .dispatch(app_state::Event::BranchChanged(CONFIG.get().branch.clone()))
+        .await?;
 
-        some_loop!(files = changed_files_stream.next() => {
-            files_dispatcher
-                .send(Event::FilesChanged(files))
-                .await
-                .unwrap();
-        });
-    });
+    program.run(state_manager).await
+}
 
-    let state_stream = state_manager.stream();
-    tokio::spawn(async move {
-        tokio::pin!(state_stream);
-
-        some_loop!((event, app_state) = state_stream.next() => {
-            match event {
-                Event::FilesChanged(files) => {
-                    dbg!("files!");
-                    dbg!(files);
-                }
-                _ => {}
-            }
-        });
-    });
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut opt = program::Opt::from_args();
 
-    let program = program_from_opt(&opt);
-    program.run(state_manager).await
+    if let Some(command) = opt.command.take() {
+        let code = commands::execute(command).await?;
+        std::process::exit(code);
+    }
+
+    watch(|projects| program_from_opt(&opt, projects)).await
 }
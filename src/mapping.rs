@@ -0,0 +1,92 @@
+mod co_change;
+mod constants;
+mod coverage;
+mod map_rules;
+pub mod presets;
+mod requires;
+mod support;
+
+pub use co_change::{CoChangeIndex, CoChangeSelector};
+pub use constants::ConstantSelector;
+pub use coverage::{CoverageIndex, CoverageSelector};
+pub use map_rules::{MapRule, MapRules};
+pub use requires::RequireSelector;
+pub use support::SupportSelector;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Why a spec location was selected for a changed file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Reason {
+    Rule { pattern: String, target: String },
+    Requires { depth: usize },
+    Constant { constant: String },
+    Coverage,
+    CoChange { score: f64 },
+    Support { kind: String, name: String },
+}
+
+impl Reason {
+    /// Suggestions are shown as likely affected but not run automatically.
+    pub fn is_suggestion(&self) -> bool {
+        matches!(self, Reason::CoChange { .. })
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Rule { pattern, .. } => write!(f, "maps from {}", pattern),
+            Reason::Requires { depth: 1 } => write!(f, "requires it"),
+            Reason::Requires { depth } => write!(f, "requires it {} levels deep", depth),
+            Reason::Constant { constant } => write!(f, "references {}", constant),
+            Reason::Coverage => write!(f, "executed it"),
+            Reason::CoChange { score } => {
+                write!(f, "changed with it in {:.0}% of commits", score * 100.0)
+            }
+            Reason::Support { kind, name } => write!(f, "uses {} {}", kind, name),
+        }
+    }
+}
+
+/// A spec location selected to run because `changed` changed. Selectors
+/// work with paths relative to the project root, and `Project::select` then
+/// makes `changed` relative to the repository root like a `ChangedFile`.
+/// `location` stays relative to the project root, where rspec runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpecMatch {
+    pub changed: PathBuf,
+    pub location: String,
+    pub reason: Reason,
+}
+
+/// A strategy for selecting the specs affected by a set of changed files.
+/// Paths given to and returned from a selector are relative to `root`.
+pub trait Selector: Send + Sync {
+    fn select(&self, root: &Path, files: &[PathBuf]) -> Vec<SpecMatch>;
+
+    /// Called with changed files before `select`, so that a selector that
+    /// keeps an index of the project can bring it up to date.
+    fn update(&self, _root: &Path, _files: &[PathBuf]) {}
+
+    /// Block until anything indexed in the background is ready, for
+    /// commands that select once rather than as files change.
+    fn wait(&self) {}
+}
+
+/// Collect the distinct locations to run from a set of matches, in the order
+/// they were first selected. Suggestions are left out.
+pub fn locations(matches: &[SpecMatch]) -> Vec<String> {
+    let mut locations: Vec<String> = vec![];
+
+    for m in matches.iter().filter(|m| !m.reason.is_suggestion()) {
+        if !locations.contains(&m.location) {
+            locations.push(m.location.clone());
+        }
+    }
+
+    locations
+}
@@ -0,0 +1,172 @@
+use crate::mapping::{Reason, Selector, SpecMatch};
+use crate::util::path_filter::PathFilter;
+
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// A file and spec must have changed together at least this often before
+/// one is suggested for the other.
+const MIN_CO_CHANGES: usize = 2;
+
+/// How often each spec file has changed in the same commit as each source
+/// file, learned from the repository history.
+#[derive(Debug, Default)]
+pub struct CoChangeIndex {
+    changes: HashMap<PathBuf, usize>,
+    co_changes: HashMap<PathBuf, HashMap<PathBuf, usize>>,
+}
+
+impl CoChangeIndex {
+    /// Build the index from the files changed by each commit. Paths should
+    /// already be relative to the project root.
+    pub fn build(commits: &[Vec<PathBuf>], spec_files: &PathFilter) -> Self {
+        let mut index = CoChangeIndex::default();
+
+        for files in commits.iter() {
+            let (specs, sources): (Vec<&PathBuf>, Vec<&PathBuf>) =
+                files.iter().partition(|f| spec_files.include_path(f));
+
+            for source in sources.into_iter() {
+                *index.changes.entry(source.clone()).or_default() += 1;
+
+                let co_changes = index.co_changes.entry(source.clone()).or_default();
+                for spec in specs.iter() {
+                    *co_changes.entry((*spec).clone()).or_default() += 1;
+                }
+            }
+        }
+
+        index
+    }
+
+    /// Specs that have changed alongside `path`, scored by the share of the
+    /// commits touching `path` that also touched the spec. Highest first.
+    pub fn related(&self, path: &Path) -> Vec<(PathBuf, f64)> {
+        let changes = match self.changes.get(path) {
+            Some(changes) => *changes as f64,
+            None => return vec![],
+        };
+
+        let mut related: Vec<(PathBuf, f64)> = self
+            .co_changes
+            .get(path)
+            .map(|specs| {
+                specs
+                    .iter()
+                    .filter(|(_, count)| **count >= MIN_CO_CHANGES)
+                    .map(|(spec, count)| (spec.clone(), *count as f64 / changes))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        related.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        related
+    }
+}
+
+/// The index once it has been learned, with a way to wait for that.
+#[derive(Default)]
+struct Learned {
+    index: Mutex<Option<CoChangeIndex>>,
+    ready: Condvar,
+}
+
+/// Suggests specs that have historically changed alongside a changed file.
+/// These are shown as likely affected but aren't run automatically.
+pub struct CoChangeSelector {
+    learned: Arc<Learned>,
+    min_score: f64,
+}
+
+impl CoChangeSelector {
+    /// Learn the index on its own thread, since mining the history can take
+    /// a while. Nothing is suggested until it is done.
+    pub fn spawn<F>(learn: F, min_score: f64) -> Self
+    where
+        F: FnOnce() -> CoChangeIndex + Send + 'static,
+    {
+        let learned = Arc::new(Learned::default());
+        let learner = Arc::clone(&learned);
+
+        thread::spawn(move || {
+            // Even a learner that panics leaves an index, so that nothing
+            // waits on it forever
+            let index = panic::catch_unwind(AssertUnwindSafe(learn)).unwrap_or_default();
+            *learner.index.lock().unwrap() = Some(index);
+            learner.ready.notify_all();
+        });
+
+        CoChangeSelector { learned, min_score }
+    }
+}
+
+impl Selector for CoChangeSelector {
+    fn select(&self, root: &Path, files: &[PathBuf]) -> Vec<SpecMatch> {
+        let index = self.learned.index.lock().unwrap();
+        let index = match index.as_ref() {
+            Some(index) => index,
+            None => return vec![],
+        };
+
+        files
+            .iter()
+            .flat_map(|changed| {
+                index
+                    .related(changed)
+                    .into_iter()
+                    .filter(|(spec, score)| *score >= self.min_score && root.join(spec).is_file())
+                    .map(move |(spec, score)| SpecMatch {
+                        changed: changed.clone(),
+                        location: spec.to_string_lossy().into_owned(),
+                        reason: Reason::CoChange { score },
+                    })
+            })
+            .collect()
+    }
+
+    fn wait(&self) {
+        let mut index = self.learned.index.lock().unwrap();
+        while index.is_none() {
+            index = self.learned.ready.wait(index).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_dir::TestDir;
+    use std::sync::mpsc;
+
+    #[test]
+    fn suggests_nothing_until_learned() {
+        let dir = TestDir::new("co_change");
+        dir.write("spec/user_spec.rb", "");
+
+        let (learn_tx, learn_rx) = mpsc::channel::<()>();
+        let selector = CoChangeSelector::spawn(
+            move || {
+                learn_rx.recv().ok();
+                let commit = vec![PathBuf::from("user.rb"), PathBuf::from("spec/user_spec.rb")];
+                let spec_files = PathFilter::new(&[String::from("spec/**/*_spec.rb")]).unwrap();
+                CoChangeIndex::build(&[commit.clone(), commit], &spec_files)
+            },
+            0.3,
+        );
+        let files = [PathBuf::from("user.rb")];
+
+        assert!(selector.select(&dir.path, &files).is_empty());
+
+        learn_tx.send(()).unwrap();
+        selector.wait();
+        let locations: Vec<String> = selector
+            .select(&dir.path, &files)
+            .into_iter()
+            .map(|m| m.location)
+            .collect();
+        assert_eq!(locations, vec!["spec/user_spec.rb"]);
+    }
+}
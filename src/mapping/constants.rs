@@ -0,0 +1,120 @@
+use crate::mapping::{Reason, Selector, SpecMatch};
+use crate::ruby::zeitwerk::{references_constant, ConstantScanner, Inflector};
+use crate::util::path_filter::PathFilter;
+use crate::util::source_files;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Selects the spec files that reference the constant an autoloaded file
+/// defines. Spec sources are scanned once for constant references and
+/// rescanned as they change.
+pub struct ConstantSelector {
+    inflector: Inflector,
+    scanner: ConstantScanner,
+    spec_files: PathFilter,
+    /// Kept in path order, so specs are selected in the same order each run
+    references: Mutex<BTreeMap<PathBuf, BTreeSet<String>>>,
+}
+
+impl ConstantSelector {
+    pub fn new(root: &Path, spec_files: PathFilter, inflections: HashMap<String, String>) -> Self {
+        let selector = ConstantSelector {
+            inflector: Inflector::new(inflections),
+            scanner: ConstantScanner::new(),
+            spec_files,
+            references: Mutex::new(BTreeMap::new()),
+        };
+
+        let specs: Vec<PathBuf> = source_files::find_files(root, "rb")
+            .into_iter()
+            .filter(|f| selector.spec_files.include_path(f))
+            .collect();
+        selector.update(root, &specs);
+
+        selector
+    }
+}
+
+impl Selector for ConstantSelector {
+    fn update(&self, root: &Path, files: &[PathBuf]) {
+        let mut references = self.references.lock().unwrap();
+
+        for file in files.iter().filter(|f| self.spec_files.include_path(f)) {
+            match fs::read_to_string(root.join(file)) {
+                Ok(source) => {
+                    references.insert(file.clone(), self.scanner.scan(&source));
+                }
+                Err(_) => {
+                    references.remove(file);
+                }
+            }
+        }
+    }
+
+    fn select(&self, _root: &Path, files: &[PathBuf]) -> Vec<SpecMatch> {
+        let references = self.references.lock().unwrap();
+
+        files
+            .iter()
+            .filter_map(|changed| {
+                self.inflector
+                    .constant_for_path(changed)
+                    .map(|constant| (changed, constant))
+            })
+            .flat_map(|(changed, constant)| {
+                references
+                    .iter()
+                    .filter(|(spec, refs)| {
+                        *spec != changed && refs.iter().any(|r| references_constant(r, &constant))
+                    })
+                    .map(|(spec, _)| SpecMatch {
+                        changed: changed.clone(),
+                        location: spec.to_string_lossy().into_owned(),
+                        reason: Reason::Constant {
+                            constant: constant.clone(),
+                        },
+                    })
+                    .collect::<Vec<SpecMatch>>()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_dir::TestDir;
+
+    #[test]
+    fn selects_referencing_specs_in_path_order() {
+        let dir = TestDir::new("constants-order");
+        dir.write("app/models/admin/user.rb", "")
+            .write("spec/models/user_spec.rb", "describe Admin::User do\nend\n")
+            .write(
+                "spec/admin/users_spec.rb",
+                "module Admin\n  describe User do\n  end\nend\n",
+            )
+            .write("spec/b_spec.rb", "Admin::User::Role\n")
+            .write("spec/a_spec.rb", "User\n");
+        let selector = ConstantSelector::new(
+            &dir.path,
+            PathFilter::new(&["spec/**/*_spec.rb"]).unwrap(),
+            HashMap::new(),
+        );
+
+        let matches = selector.select(&dir.path, &[PathBuf::from("app/models/admin/user.rb")]);
+
+        let locations: Vec<&str> = matches.iter().map(|m| m.location.as_str()).collect();
+        assert_eq!(
+            locations,
+            vec![
+                "spec/admin/users_spec.rb",
+                "spec/b_spec.rb",
+                "spec/models/user_spec.rb"
+            ]
+        );
+    }
+}
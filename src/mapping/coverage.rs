@@ -0,0 +1,252 @@
+use crate::mapping::{Reason, Selector, SpecMatch};
+use crate::util::store;
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const COVERAGE_FILE: &str = "coverage.json";
+
+/// The spec file of an example id like `./spec/user_spec.rb[1:2]` or a
+/// location like `spec/user_spec.rb:12`, relative to the project root.
+fn spec_file(location: &str) -> &str {
+    let location = location.trim_start_matches("./");
+    match location.find(&['[', ':'][..]) {
+        Some(end) => &location[..end],
+        None => location,
+    }
+}
+
+/// The files each example executed, as reported by the formatter when
+/// coverage collection is on. Persisted under the project root so it
+/// survives restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CoverageIndex {
+    examples: HashMap<String, BTreeSet<PathBuf>>,
+    #[serde(skip)]
+    root: PathBuf,
+    #[serde(skip)]
+    path: PathBuf,
+    /// The examples recorded since the last run finished
+    #[serde(skip)]
+    ran: HashSet<String>,
+}
+
+impl CoverageIndex {
+    pub fn load(root: &Path) -> Self {
+        let path = store::store_path(root, COVERAGE_FILE);
+        let mut index: CoverageIndex = store::read_json(&path).unwrap_or_default();
+        index.root = root.to_path_buf();
+        index.path = path;
+        index
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        store::write_json(&self.path, self)
+    }
+
+    /// Replace what is known about example `id` with the files it executed
+    /// in its latest run.
+    pub fn record(&mut self, id: &str, files: &[String]) {
+        self.examples
+            .insert(id.to_owned(), files.iter().map(PathBuf::from).collect());
+        self.ran.insert(id.to_owned());
+    }
+
+    /// Forget the examples a run of `locations` shows are gone, once it has
+    /// run to completion: those whose spec file it ran whole without them,
+    /// and those whose spec file no longer exists. A run that was cancelled
+    /// or failed to load its specs shows nothing.
+    pub fn finish_run(&mut self, locations: &[String], completed: bool) {
+        let ran = std::mem::take(&mut self.ran);
+        if !completed {
+            return;
+        }
+
+        let whole_files: HashSet<&str> = locations
+            .iter()
+            .filter(|l| !l.contains(&['[', ':'][..]))
+            .map(|l| spec_file(l))
+            .collect();
+        let root = &self.root;
+
+        self.examples.retain(|id, _| {
+            let file = spec_file(id);
+            (ran.contains(id) || !whole_files.contains(file)) && root.join(file).exists()
+        });
+    }
+
+    /// The ids of every example that executed `path`.
+    pub fn examples_for(&self, path: &Path) -> Vec<&str> {
+        let mut ids: Vec<&str> = self
+            .examples
+            .iter()
+            .filter(|(_, files)| files.contains(path))
+            .map(|(id, _)| id.as_str())
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+/// Selects exactly the examples that executed a changed file.
+pub struct CoverageSelector {
+    index: Arc<Mutex<CoverageIndex>>,
+}
+
+impl CoverageSelector {
+    pub fn new(index: Arc<Mutex<CoverageIndex>>) -> Self {
+        CoverageSelector { index }
+    }
+}
+
+impl Selector for CoverageSelector {
+    fn select(&self, _root: &Path, files: &[PathBuf]) -> Vec<SpecMatch> {
+        let index = self.index.lock().unwrap();
+
+        files
+            .iter()
+            .flat_map(|changed| {
+                index
+                    .examples_for(changed)
+                    .into_iter()
+                    .map(move |id| SpecMatch {
+                        changed: changed.clone(),
+                        location: id.to_owned(),
+                        reason: Reason::Coverage,
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_dir::TestDir;
+
+    fn project(name: &str) -> TestDir {
+        let dir = TestDir::new(name);
+        dir.write("spec/user_spec.rb", "")
+            .write("spec/post_spec.rb", "");
+        dir
+    }
+
+    fn files(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|p| p.to_string()).collect()
+    }
+
+    fn ids(index: &CoverageIndex) -> Vec<&str> {
+        let mut ids: Vec<&str> = index.examples.keys().map(String::as_str).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn saved_coverage_is_loaded() {
+        let dir = project("coverage-round_trip");
+        let mut index = CoverageIndex::load(&dir.path);
+        index.record("./spec/user_spec.rb[1:1]", &files(&["app/models/user.rb"]));
+        index.save().unwrap();
+
+        let index = CoverageIndex::load(&dir.path);
+
+        assert_eq!(
+            index.examples_for(Path::new("app/models/user.rb")),
+            vec!["./spec/user_spec.rb[1:1]"]
+        );
+    }
+
+    #[test]
+    fn selects_the_examples_that_executed_a_file() {
+        let dir = project("coverage-select");
+        let index = Arc::new(Mutex::new(CoverageIndex::load(&dir.path)));
+        {
+            let mut index = index.lock().unwrap();
+            index.record("./spec/user_spec.rb[1:2]", &files(&["app/models/user.rb"]));
+            index.record(
+                "./spec/post_spec.rb[1:1]",
+                &files(&["app/models/post.rb", "app/models/user.rb"]),
+            );
+            index.record("./spec/post_spec.rb[1:2]", &files(&["app/models/post.rb"]));
+        }
+
+        let matches = CoverageSelector::new(index).select(
+            &dir.path,
+            &[
+                PathBuf::from("app/models/user.rb"),
+                PathBuf::from("lib/other.rb"),
+            ],
+        );
+
+        let locations: Vec<&str> = matches.iter().map(|m| m.location.as_str()).collect();
+        assert_eq!(
+            locations,
+            vec!["./spec/post_spec.rb[1:1]", "./spec/user_spec.rb[1:2]"]
+        );
+        assert!(matches.iter().all(|m| m.reason == Reason::Coverage));
+    }
+
+    #[test]
+    fn a_whole_file_run_forgets_examples_that_didnt_run() {
+        let dir = project("coverage-whole_file");
+        let mut index = CoverageIndex::load(&dir.path);
+        index.record("./spec/user_spec.rb[1:1]", &files(&["a.rb"]));
+        index.record("./spec/user_spec.rb[1:2]", &files(&["a.rb"]));
+        index.record("./spec/post_spec.rb[1:1]", &files(&["a.rb"]));
+        index.finish_run(&[], true);
+
+        index.record("./spec/user_spec.rb[1:1]", &files(&["a.rb"]));
+        index.finish_run(&files(&["spec/user_spec.rb"]), true);
+
+        assert_eq!(
+            ids(&index),
+            vec!["./spec/post_spec.rb[1:1]", "./spec/user_spec.rb[1:1]"]
+        );
+    }
+
+    #[test]
+    fn a_run_of_some_examples_forgets_nothing_it_didnt_run() {
+        let dir = project("coverage-partial");
+        let mut index = CoverageIndex::load(&dir.path);
+        index.record("./spec/user_spec.rb[1:1]", &files(&["a.rb"]));
+        index.record("./spec/user_spec.rb[1:2]", &files(&["a.rb"]));
+        index.finish_run(&[], true);
+
+        index.record("./spec/user_spec.rb[1:1]", &files(&["a.rb"]));
+        index.finish_run(&files(&["spec/user_spec.rb:3"]), true);
+
+        assert_eq!(
+            ids(&index),
+            vec!["./spec/user_spec.rb[1:1]", "./spec/user_spec.rb[1:2]"]
+        );
+    }
+
+    #[test]
+    fn examples_of_deleted_spec_files_are_forgotten() {
+        let dir = project("coverage-deleted");
+        let mut index = CoverageIndex::load(&dir.path);
+        index.record("./spec/user_spec.rb[1:1]", &files(&["a.rb"]));
+        index.record("./spec/post_spec.rb[1:1]", &files(&["a.rb"]));
+        index.finish_run(&[], true);
+
+        dir.remove("spec/post_spec.rb");
+        index.finish_run(&files(&["spec/user_spec.rb:3"]), true);
+
+        assert_eq!(ids(&index), vec!["./spec/user_spec.rb[1:1]"]);
+    }
+
+    #[test]
+    fn an_unfinished_run_forgets_nothing() {
+        let dir = project("coverage-unfinished");
+        let mut index = CoverageIndex::load(&dir.path);
+        index.record("./spec/user_spec.rb[1:1]", &files(&["a.rb"]));
+        index.finish_run(&[], true);
+
+        dir.remove("spec/user_spec.rb");
+        index.finish_run(&files(&["spec/user_spec.rb"]), false);
+
+        assert_eq!(ids(&index), vec!["./spec/user_spec.rb[1:1]"]);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::mapping::{Reason, Selector, SpecMatch};
+use anyhow::Context;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Rewrite `$1` style references as `${1}` so that a reference followed by
+/// word characters, as in `$1_spec.rb`, isn't read as a named group.
+fn normalize_target(target: &str) -> String {
+    let mut normalized = String::with_capacity(target.len());
+    let mut chars = target.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '$' && chars.peek().map_or(false, |n| n.is_ascii_digit()) {
+            normalized.push_str("${");
+            while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()).copied() {
+                normalized.push(d);
+                chars.next();
+            }
+            normalized.push('}');
+        } else {
+            normalized.push(c);
+        }
+    }
+
+    normalized
+}
+
+/// A single `[map]` rule. The pattern must match the whole path.
+#[derive(Debug, Clone)]
+pub struct MapRule {
+    pub pattern: String,
+    pub target: String,
+    regex: Regex,
+    expand: String,
+}
+
+impl MapRule {
+    pub fn new<S: AsRef<str>>(pattern: S, target: S) -> anyhow::Result<Self> {
+        let pattern = pattern.as_ref();
+        let target = target.as_ref();
+        let regex = Regex::new(&format!("^(?:{})$", pattern))
+            .with_context(|| format!("Invalid pattern {:?}", pattern))?;
+
+        Ok(MapRule {
+            pattern: pattern.to_owned(),
+            target: target.to_owned(),
+            regex,
+            expand: normalize_target(target),
+        })
+    }
+
+    /// The capture groups of the pattern for `path`, numbered from 1, if this
+    /// rule matches it.
+    pub fn captures(&self, path: &str) -> Option<Vec<Option<String>>> {
+        self.regex.captures(path).map(|caps| {
+            caps.iter()
+                .skip(1)
+                .map(|c| c.map(|c| c.as_str().to_owned()))
+                .collect()
+        })
+    }
+
+    /// The target for `path`, if this rule matches it.
+    pub fn apply(&self, path: &str) -> Option<String> {
+        self.regex.captures(path).map(|caps| {
+            let mut target = String::new();
+            caps.expand(&self.expand, &mut target);
+            target
+        })
+    }
+}
+
+/// The `[map]` rules for one test runner, tried in order.
+#[derive(Debug, Clone, Default)]
+pub struct MapRules {
+    rules: Vec<MapRule>,
+}
+
+impl MapRules {
+    pub fn new(map: &HashMap<String, Vec<(String, String)>>, runner: &str) -> anyhow::Result<Self> {
+        let rules: anyhow::Result<Vec<MapRule>> = map
+            .get(runner)
+            .map(|rules| rules.iter().map(|(p, t)| MapRule::new(p, t)).collect())
+            .unwrap_or_else(|| Ok(vec![]));
+
+        Ok(MapRules { rules: rules? })
+    }
+
+    pub fn rules(&self) -> &[MapRule] {
+        &self.rules
+    }
+
+    /// Map a path relative to `root` to spec locations that exist on disk.
+    pub fn select_path<P: AsRef<Path>>(&self, root: &Path, path: P) -> Vec<SpecMatch> {
+        let path = path.as_ref();
+        let path_str = match path.to_str() {
+            Some(s) => s,
+            None => return vec![],
+        };
+
+        self.rules
+            .iter()
+            .filter_map(|rule| {
+                rule.apply(path_str).map(|location| SpecMatch {
+                    changed: path.to_path_buf(),
+                    location,
+                    reason: Reason::Rule {
+                        pattern: rule.pattern.clone(),
+                        target: rule.target.clone(),
+                    },
+                })
+            })
+            .filter(|m| root.join(&m.location).is_file())
+            .collect()
+    }
+}
+
+impl Selector for MapRules {
+    fn select(&self, root: &Path, files: &[PathBuf]) -> Vec<SpecMatch> {
+        files
+            .iter()
+            .flat_map(|path| self.select_path(root, path))
+            .collect()
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+/// A named set of `[map]` rules for a common project layout, along with the
+/// `include` globs to use when a project doesn't set its own.
+pub struct Preset {
+    pub name: &'static str,
+    pub include: &'static [&'static str],
+    /// Rules as `(runner, pattern, target)`
+    pub rules: &'static [(&'static str, &'static str, &'static str)],
+}
+
+const RAILS_INCLUDE: &[&str] = &[
+    "app/**/*",
+    "lib/**/*.rb",
+    "config/**/*.rb",
+    "spec/**/*.rb",
+    "test/**/*.rb",
+    // Fixtures, whether yml sets or files, select the specs that use them
+    "spec/fixtures/**/*",
+    "test/fixtures/**/*",
+];
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "rails",
+        include: RAILS_INCLUDE,
+        rules: &[
+            ("rspec", r"spec/(.+)_spec\.rb", "spec/$1_spec.rb"),
+            ("rspec", r"app/models/(.+)\.rb", "spec/models/$1_spec.rb"),
+            (
+                "rspec",
+                r"app/controllers/(.+)_controller\.rb",
+                "spec/requests/$1_spec.rb",
+            ),
+            (
+                "rspec",
+                r"app/controllers/(.+)_controller\.rb",
+                "spec/controllers/$1_controller_spec.rb",
+            ),
+            (
+                "rspec",
+                r"app/controllers/(.+)_controller\.rb",
+                "spec/routing/$1_routing_spec.rb",
+            ),
+            ("rspec", r"app/jobs/(.+)\.rb", "spec/jobs/$1_spec.rb"),
+            ("rspec", r"app/mailers/(.+)\.rb", "spec/mailers/$1_spec.rb"),
+            (
+                "rspec",
+                r"app/serializers/(.+)\.rb",
+                "spec/serializers/$1_spec.rb",
+            ),
+            (
+                "rspec",
+                r"app/(helpers|services|policies|workers|decorators|presenters|validators|channels|forms|queries)/(.+)\.rb",
+                "spec/$1/$2_spec.rb",
+            ),
+            ("rspec", r"app/views/(.+)", "spec/views/$1_spec.rb"),
+            ("rspec", r"app/views/(.+)/[^/]+", "spec/requests/$1_spec.rb"),
+            ("rspec", r"lib/(.+)\.rb", "spec/lib/$1_spec.rb"),
+        ],
+    },
+    Preset {
+        name: "gem",
+        include: &["lib/**/*.rb", "spec/**/*.rb"],
+        rules: &[
+            ("rspec", r"spec/(.+)_spec\.rb", "spec/$1_spec.rb"),
+            ("rspec", r"lib/(.+)\.rb", "spec/$1_spec.rb"),
+            ("rspec", r"lib/[^/]+/(.+)\.rb", "spec/$1_spec.rb"),
+            ("rspec", r"lib/(.+)\.rb", "spec/lib/$1_spec.rb"),
+        ],
+    },
+];
+
+/// Presets for layouts spec_detect can't run yet, and why.
+const UNSUPPORTED: &[(&str, &str)] = &[(
+    "rails-minitest",
+    "it needs a minitest runner, which spec_detect doesn't have yet",
+)];
+
+pub fn find(name: &str) -> anyhow::Result<&'static Preset> {
+    if let Some((_, reason)) = UNSUPPORTED.iter().find(|(n, _)| *n == name) {
+        anyhow::bail!("The {} preset can't be used, {}", name, reason);
+    }
+
+    PRESETS.iter().find(|p| p.name == name).ok_or_else(|| {
+        let names: Vec<&str> = PRESETS.iter().map(|p| p.name).collect();
+        anyhow::anyhow!(
+            "Unknown preset {}, expected one of {}",
+            name,
+            names.join(", ")
+        )
+    })
+}
+
+/// Layer the custom `map` rules over a preset's. Custom rules come first,
+/// and replace any of the preset's rules with the same pattern.
+pub fn layer(
+    preset: &Preset,
+    map: &HashMap<String, Vec<(String, String)>>,
+) -> HashMap<String, Vec<(String, String)>> {
+    let mut layered = map.clone();
+
+    for (runner, pattern, target) in preset.rules.iter() {
+        let rules = layered.entry(runner.to_string()).or_default();
+        let overridden = map
+            .get(*runner)
+            .map_or(false, |custom| custom.iter().any(|(p, _)| p == pattern));
+
+        if !overridden {
+            rules.push((pattern.to_string(), target.to_string()));
+        }
+    }
+
+    layered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::MapRules;
+    use crate::util::path_filter::PathFilter;
+
+    fn targets(map: &HashMap<String, Vec<(String, String)>>, path: &str) -> Vec<String> {
+        MapRules::new(map, "rspec")
+            .unwrap()
+            .rules()
+            .iter()
+            .filter_map(|rule| rule.apply(path))
+            .collect()
+    }
+
+    fn preset_targets(name: &str, path: &str) -> Vec<String> {
+        targets(&layer(find(name).unwrap(), &HashMap::new()), path)
+    }
+
+    #[test]
+    fn every_preset_rule_is_valid() {
+        for preset in PRESETS {
+            MapRules::new(&layer(preset, &HashMap::new()), "rspec").unwrap();
+        }
+    }
+
+    #[test]
+    fn rails_maps_app_files_to_their_specs() {
+        assert_eq!(
+            preset_targets("rails", "app/models/user.rb"),
+            vec!["spec/models/user_spec.rb"]
+        );
+        assert_eq!(
+            preset_targets("rails", "app/controllers/users_controller.rb"),
+            vec![
+                "spec/requests/users_spec.rb",
+                "spec/controllers/users_controller_spec.rb",
+                "spec/routing/users_routing_spec.rb",
+            ]
+        );
+        assert_eq!(
+            preset_targets("rails", "app/services/billing/charge.rb"),
+            vec!["spec/services/billing/charge_spec.rb"]
+        );
+        assert_eq!(
+            preset_targets("rails", "app/views/users/index.html.erb"),
+            vec![
+                "spec/views/users/index.html.erb_spec.rb",
+                "spec/requests/users_spec.rb",
+            ]
+        );
+        assert_eq!(
+            preset_targets("rails", "lib/tasks/cleanup.rb"),
+            vec!["spec/lib/tasks/cleanup_spec.rb"]
+        );
+    }
+
+    #[test]
+    fn rails_includes_fixtures() {
+        let include = PathFilter::new(find("rails").unwrap().include).unwrap();
+
+        assert!(include.include_path("spec/fixtures/users.yml"));
+        assert!(include.include_path("spec/fixtures/files/avatar.png"));
+        assert!(include.include_path("test/fixtures/admin/roles.yml"));
+        assert!(!include.include_path("spec/spec_helper.txt"));
+    }
+
+    #[test]
+    fn rails_maps_a_spec_to_itself() {
+        assert_eq!(
+            preset_targets("rails", "spec/models/user_spec.rb"),
+            vec!["spec/models/user_spec.rb"]
+        );
+    }
+
+    #[test]
+    fn gem_maps_lib_files_with_and_without_the_gem_directory() {
+        assert_eq!(
+            preset_targets("gem", "lib/my_gem/parser.rb"),
+            vec![
+                "spec/my_gem/parser_spec.rb",
+                "spec/parser_spec.rb",
+                "spec/lib/my_gem/parser_spec.rb",
+            ]
+        );
+        assert_eq!(
+            preset_targets("gem", "spec/my_gem/parser_spec.rb"),
+            vec!["spec/my_gem/parser_spec.rb"]
+        );
+    }
+
+    #[test]
+    fn custom_rules_take_precedence() {
+        let mut map = HashMap::new();
+        map.insert(
+            String::from("rspec"),
+            vec![
+                (
+                    String::from(r"app/models/(.+)\.rb"),
+                    String::from("spec/unit/$1_spec.rb"),
+                ),
+                (
+                    String::from(r"app/components/(.+)\.rb"),
+                    String::from("spec/components/$1_spec.rb"),
+                ),
+            ],
+        );
+        let layered = layer(find("rails").unwrap(), &map);
+
+        assert_eq!(
+            targets(&layered, "app/models/user.rb"),
+            vec!["spec/unit/user_spec.rb"]
+        );
+        assert_eq!(
+            targets(&layered, "app/components/card.rb"),
+            vec!["spec/components/card_spec.rb"]
+        );
+        assert_eq!(
+            targets(&layered, "app/jobs/sync.rb"),
+            vec!["spec/jobs/sync_spec.rb"]
+        );
+        assert_eq!(
+            &layered["rspec"][..2],
+            &map["rspec"][..],
+            "custom rules are tried first"
+        );
+    }
+
+    #[test]
+    fn unsupported_and_unknown_presets_are_rejected() {
+        let minitest = find("rails-minitest").err().unwrap().to_string();
+        assert!(minitest.contains("minitest runner"), "{}", minitest);
+
+        let unknown = find("django").err().unwrap().to_string();
+        assert!(unknown.contains("rails, gem"), "{}", unknown);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::mapping::{Reason, Selector, SpecMatch};
+use crate::ruby::require_graph::RequireGraph;
+use crate::util::path_filter::PathFilter;
+use crate::util::source_files;
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Selects the spec files that require a changed file, however indirectly.
+/// The require graph is built once and then rescanned a file at a time as
+/// files change.
+pub struct RequireSelector {
+    graph: Mutex<RequireGraph>,
+    spec_files: PathFilter,
+}
+
+impl RequireSelector {
+    pub fn new(root: &Path, spec_files: PathFilter) -> Self {
+        let files = source_files::find_files(root, "rb");
+
+        RequireSelector {
+            graph: Mutex::new(RequireGraph::build(root, &files)),
+            spec_files,
+        }
+    }
+}
+
+impl Selector for RequireSelector {
+    fn update(&self, root: &Path, files: &[PathBuf]) {
+        let mut graph = self.graph.lock().unwrap();
+
+        for file in files
+            .iter()
+            .filter(|f| f.extension().map_or(false, |e| e == "rb"))
+        {
+            graph.update_file(root, file);
+        }
+    }
+
+    fn select(&self, _root: &Path, files: &[PathBuf]) -> Vec<SpecMatch> {
+        let graph = self.graph.lock().unwrap();
+
+        files
+            .iter()
+            .flat_map(|changed| {
+                graph
+                    .dependents(changed)
+                    .into_iter()
+                    .filter(|(dependent, _)| self.spec_files.include_path(dependent))
+                    .map(move |(dependent, depth)| SpecMatch {
+                        changed: changed.clone(),
+                        location: dependent.to_string_lossy().into_owned(),
+                        reason: Reason::Requires { depth },
+                    })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,83 @@
+use crate::mapping::{Reason, Selector, SpecMatch};
+use crate::ruby::spec_support::{support_kind, SpecUsage, SupportScanner};
+use crate::util::path_filter::PathFilter;
+use crate::util::source_files;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Selects the specs that use a factory, fixture, shared example group or
+/// support module defined in a changed file under `spec/factories`,
+/// `spec/fixtures` or `spec/support`.
+pub struct SupportSelector {
+    scanner: SupportScanner,
+    spec_files: PathFilter,
+    usages: Mutex<HashMap<PathBuf, SpecUsage>>,
+}
+
+impl SupportSelector {
+    pub fn new(root: &Path, spec_files: PathFilter) -> Self {
+        let selector = SupportSelector {
+            scanner: SupportScanner::new(),
+            spec_files,
+            usages: Mutex::new(HashMap::new()),
+        };
+
+        let specs: Vec<PathBuf> = source_files::find_files(root, "rb")
+            .into_iter()
+            .filter(|f| selector.spec_files.include_path(f))
+            .collect();
+        selector.update(root, &specs);
+
+        selector
+    }
+}
+
+impl Selector for SupportSelector {
+    fn update(&self, root: &Path, files: &[PathBuf]) {
+        let mut usages = self.usages.lock().unwrap();
+
+        for file in files.iter().filter(|f| self.spec_files.include_path(f)) {
+            match fs::read_to_string(root.join(file)) {
+                Ok(source) => {
+                    usages.insert(file.clone(), self.scanner.usage(&source));
+                }
+                Err(_) => {
+                    usages.remove(file);
+                }
+            }
+        }
+    }
+
+    fn select(&self, root: &Path, files: &[PathBuf]) -> Vec<SpecMatch> {
+        let usages = self.usages.lock().unwrap();
+        let mut matches = vec![];
+
+        for changed in files.iter().filter(|f| support_kind(f).is_some()) {
+            // Fixtures needn't be text, and their names come from the path
+            let source = fs::read_to_string(root.join(changed)).unwrap_or_default();
+
+            for definition in self.scanner.definitions(changed, &source) {
+                let mut specs: Vec<&PathBuf> = usages
+                    .iter()
+                    .filter(|(_, usage)| usage.uses(&definition))
+                    .map(|(spec, _)| spec)
+                    .collect();
+                specs.sort();
+
+                matches.extend(specs.into_iter().map(|spec| SpecMatch {
+                    changed: changed.clone(),
+                    location: spec.to_string_lossy().into_owned(),
+                    reason: Reason::Support {
+                        kind: definition.kind().to_owned(),
+                        name: definition.name().to_owned(),
+                    },
+                }));
+            }
+        }
+
+        matches
+    }
+}
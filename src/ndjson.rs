@@ -0,0 +1,45 @@
+mod message;
+
+pub use message::{root_string, File, Message, Run, Spec, Versioned, VERSION};
+
+use crate::app_state::AppStateManager;
+use crate::Program;
+use async_trait::async_trait;
+use std::io::{self, Write};
+use tokio::sync::broadcast::RecvError;
+
+/// Writes every event to stdout as a line of JSON, for editors and other
+/// tools to follow along with. See `Message` for the format.
+pub struct NdjsonApp {}
+
+fn write_message<W: Write>(out: &mut W, message: &Message) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut *out, &Versioned::new(message))?;
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+#[async_trait]
+impl Program for NdjsonApp {
+    async fn run<'stream>(&self, app: AppStateManager) -> anyhow::Result<()> {
+        let mut events = app.subscribe();
+        let stdout = io::stdout();
+
+        write_message(&mut stdout.lock(), &Message::Start)?;
+
+        loop {
+            let message = match events.recv().await {
+                Ok((event, app_state)) => Message::from_event(&event, &app_state),
+                Err(RecvError::Lagged(missed)) => Message::Lagged { missed },
+                Err(RecvError::Closed) => break,
+            };
+            write_message(&mut stdout.lock(), &message)?;
+
+            if let Message::Quit = message {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,639 @@
+use crate::app_state::{AppState, Event};
+use crate::mapping::{Reason, SpecMatch};
+use crate::repo_watcher::ChangedFile;
+use crate::ruby::rspec::{OutputStream, RSpecEvent};
+use crate::test_runner::{ExampleHistory, RunId};
+
+use git2::Delta;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The version of the format, which changes only when a message is removed
+/// or a field changes meaning. New messages and fields can appear within a
+/// version, so readers should ignore what they don't know.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Run {
+    pub project: String,
+    /// Counts up from 1 over the life of the process
+    pub number: usize,
+}
+
+impl From<&RunId> for Run {
+    fn from(id: &RunId) -> Self {
+        Run {
+            project: id.project.clone(),
+            number: id.number,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct File {
+    pub path: String,
+    /// `added`, `modified`, `renamed` and so on
+    pub status: &'static str,
+}
+
+/// Spelled out rather than taken from git2's names, which the format
+/// mustn't change with.
+fn status_name(status: Delta) -> &'static str {
+    match status {
+        Delta::Unmodified => "unmodified",
+        Delta::Added => "added",
+        Delta::Deleted => "deleted",
+        Delta::Modified => "modified",
+        Delta::Renamed => "renamed",
+        Delta::Copied => "copied",
+        Delta::Ignored => "ignored",
+        Delta::Untracked => "untracked",
+        Delta::Typechange => "typechange",
+        Delta::Unreadable => "unreadable",
+        Delta::Conflicted => "conflicted",
+    }
+}
+
+impl From<&ChangedFile> for File {
+    fn from(file: &ChangedFile) -> Self {
+        File {
+            path: path_string(&file.path),
+            status: status_name(file.status),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Spec {
+    /// The changed file, relative to the repository root
+    pub changed: String,
+    /// Relative to the project root
+    pub location: String,
+    pub reason: Reason,
+    /// Suggestions are shown but not run
+    pub suggestion: bool,
+}
+
+impl From<&SpecMatch> for Spec {
+    fn from(m: &SpecMatch) -> Self {
+        Spec {
+            changed: path_string(&m.changed),
+            location: m.location.clone(),
+            reason: m.reason.clone(),
+            suggestion: m.reason.is_suggestion(),
+        }
+    }
+}
+
+/// One line of output, describing an event. Paths are relative to the
+/// repository root unless they are said to be relative to a project root.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Message {
+    Start,
+    FilesChanged {
+        files: Vec<File>,
+    },
+    SpecsSelected {
+        project: String,
+        specs: Vec<Spec>,
+    },
+    RunStarted {
+        run: Run,
+        root: String,
+        locations: Vec<String>,
+    },
+    /// rspec has loaded the specs and knows how many examples will run
+    RunLoaded {
+        run: Run,
+        count: Option<i64>,
+    },
+    ExampleStarted {
+        run: Run,
+        id: String,
+        location: String,
+        description: Option<String>,
+    },
+    ExamplePassed {
+        run: Run,
+        id: String,
+        location: String,
+        description: String,
+        run_time: f64,
+    },
+    ExampleFailed {
+        run: Run,
+        id: String,
+        location: Option<String>,
+        description: Option<String>,
+        run_time: f64,
+        exception: Option<String>,
+        backtrace: Option<Vec<String>>,
+    },
+    /// The files, relative to the project root, an example executed
+    ExampleCoverage {
+        run: Run,
+        id: String,
+        files: Vec<String>,
+    },
+    /// Every example has finished, though rspec may still be exiting
+    ExamplesFinished {
+        run: Run,
+    },
+    /// A line rspec wrote outside of the formatter
+    Output {
+        run: Run,
+        stream: OutputStream,
+        line: String,
+    },
+    /// The run was killed before it finished, as when cancelled
+    RunCancelled {
+        run: Run,
+    },
+    RunError {
+        run: Run,
+        message: String,
+    },
+    RunFinished {
+        run: Run,
+        passed: usize,
+        failed: usize,
+        errors: Vec<String>,
+        /// In seconds
+        duration: f64,
+    },
+    RunFileRequested {
+        path: String,
+    },
+    RerunFailuresRequested,
+    RunAllChangedRequested,
+    RunLocationsRequested {
+        project: String,
+        locations: Vec<String>,
+    },
+    CancelRequested,
+    ChangedCleared,
+    /// The branch is checked before changes are compared against it, and
+    /// `branch_changed` follows if it exists
+    SetBranchRequested {
+        branch: String,
+    },
+    BranchChanged {
+        branch: String,
+    },
+    WatchingToggled {
+        watching: bool,
+    },
+    /// Every spec file of a project, relative to its root
+    SpecFilesIndexed {
+        project: String,
+        files: Vec<String>,
+    },
+    /// The recent results of each of a project's examples, keyed by id
+    HistoryUpdated {
+        project: String,
+        examples: BTreeMap<String, ExampleHistory>,
+    },
+    /// Something went wrong outside of a run that spec_detect carries on
+    /// from
+    Error {
+        message: String,
+    },
+    /// Some events were dropped because they were written out too slowly
+    Lagged {
+        missed: u64,
+    },
+    Quit,
+}
+
+/// A message as written out, with the format version alongside.
+#[derive(Debug, Serialize)]
+pub struct Versioned<'a> {
+    pub version: u32,
+    #[serde(flatten)]
+    pub message: &'a Message,
+}
+
+impl<'a> Versioned<'a> {
+    pub fn new(message: &'a Message) -> Self {
+        Versioned {
+            version: VERSION,
+            message,
+        }
+    }
+}
+
+pub fn path_string(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// A project root, which is empty for the repository root.
+pub fn root_string(root: &Path) -> String {
+    match path_string(root) {
+        root if root.is_empty() => String::from("."),
+        root => root,
+    }
+}
+
+fn test_message(run: &RunId, event: &RSpecEvent, state: &AppState) -> Message {
+    let run_id = run;
+    let run = Run::from(run_id);
+
+    match event.clone() {
+        RSpecEvent::Start { count } => Message::RunLoaded { run, count },
+        RSpecEvent::ExampleStarted {
+            id,
+            location,
+            description,
+        } => Message::ExampleStarted {
+            run,
+            id,
+            location,
+            description,
+        },
+        RSpecEvent::ExamplePassed {
+            id,
+            location,
+            description,
+            run_time,
+            ..
+        } => Message::ExamplePassed {
+            run,
+            id,
+            location,
+            description,
+            run_time,
+        },
+        RSpecEvent::ExampleFailed {
+            id,
+            location,
+            description,
+            run_time,
+            exception,
+            backtrace,
+            ..
+        } => Message::ExampleFailed {
+            run,
+            id,
+            location,
+            description,
+            run_time,
+            exception,
+            backtrace,
+        },
+        RSpecEvent::ExampleCoverage { id, files } => Message::ExampleCoverage { run, id, files },
+        RSpecEvent::Stop {} => Message::ExamplesFinished { run },
+        RSpecEvent::Output { stream, line } => Message::Output { run, stream, line },
+        RSpecEvent::Cancelled {} => Message::RunCancelled { run },
+        RSpecEvent::Error { msg } => Message::RunError { run, message: msg },
+        RSpecEvent::Exit => {
+            let results = state
+                .runs
+                .get(&run_id.project)
+                .filter(|results| results.run == *run_id);
+
+            Message::RunFinished {
+                run,
+                passed: results.map_or(0, |r| r.passed()),
+                failed: results.map_or(0, |r| r.failed()),
+                errors: results.map_or_else(Vec::new, |r| r.errors.clone()),
+                duration: results.map_or(0.0, |r| r.elapsed().as_secs_f64()),
+            }
+        }
+    }
+}
+
+impl Message {
+    /// The message for an event, given the state just after it.
+    pub fn from_event(event: &Event, state: &AppState) -> Self {
+        match event {
+            Event::Start => Message::Start,
+            Event::FilesChanged(files) => Message::FilesChanged {
+                files: files.iter().map(File::from).collect(),
+            },
+            Event::SpecsSelected(project, matches) => Message::SpecsSelected {
+                project: project.clone(),
+                specs: matches.iter().map(Spec::from).collect(),
+            },
+            Event::TestRunning(run, root, locations) => Message::RunStarted {
+                run: Run::from(run),
+                root: root_string(root),
+                locations: locations.clone(),
+            },
+            Event::TestEvent(run, event) => test_message(run, event, state),
+            Event::RunFile(path) => Message::RunFileRequested {
+                path: path_string(path),
+            },
+            Event::RerunFailures => Message::RerunFailuresRequested,
+            Event::RunAllChanged => Message::RunAllChangedRequested,
+            Event::RunLocations(project, locations) => Message::RunLocationsRequested {
+                project: project.clone(),
+                locations: locations.clone(),
+            },
+            Event::CancelRun => Message::CancelRequested,
+            Event::ClearChanged => Message::ChangedCleared,
+            Event::SetBranch(branch) => Message::SetBranchRequested {
+                branch: branch.clone(),
+            },
+            Event::BranchChanged(branch) => Message::BranchChanged {
+                branch: branch.clone(),
+            },
+            Event::ToggleWatching => Message::WatchingToggled {
+                watching: state.watching,
+            },
+            Event::SpecFilesIndexed(project, files) => Message::SpecFilesIndexed {
+                project: project.clone(),
+                files: files.clone(),
+            },
+            Event::HistoryUpdated(project, examples) => Message::HistoryUpdated {
+                project: project.clone(),
+                examples: examples.clone(),
+            },
+            Event::Error(message) => Message::Error {
+                message: message.clone(),
+            },
+            Event::Quit => Message::Quit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_runner::result_store::ResultRecord;
+    use std::path::PathBuf;
+
+    fn run() -> Run {
+        Run {
+            project: String::from("web"),
+            number: 3,
+        }
+    }
+
+    fn json(message: &Message) -> String {
+        serde_json::to_string(&Versioned::new(message)).unwrap()
+    }
+
+    #[test]
+    fn every_message_has_the_version_first() {
+        assert_eq!(VERSION, 1);
+        assert_eq!(json(&Message::Start), r#"{"version":1,"event":"start"}"#);
+    }
+
+    #[test]
+    fn file_statuses_are_spelled_out() {
+        let file = |status| {
+            File::from(&ChangedFile {
+                path: PathBuf::from("app/models/user.rb"),
+                status,
+            })
+        };
+
+        assert_eq!(file(Delta::Added).status, "added");
+        assert_eq!(file(Delta::Modified).status, "modified");
+        assert_eq!(file(Delta::Deleted).status, "deleted");
+        assert_eq!(file(Delta::Renamed).status, "renamed");
+        assert_eq!(file(Delta::Untracked).status, "untracked");
+        assert_eq!(file(Delta::Typechange).status, "typechange");
+    }
+
+    #[test]
+    fn changes_and_selections() {
+        let files = Message::FilesChanged {
+            files: vec![File::from(&ChangedFile::new(PathBuf::from("lib/a.rb")))],
+        };
+        assert_eq!(
+            json(&files),
+            r#"{"version":1,"event":"files_changed","files":[{"path":"lib/a.rb","status":"modified"}]}"#
+        );
+
+        let selected = Message::SpecsSelected {
+            project: String::from("web"),
+            specs: vec![
+                Spec::from(&SpecMatch {
+                    changed: PathBuf::from("lib/a.rb"),
+                    location: String::from("spec/a_spec.rb"),
+                    reason: Reason::Rule {
+                        pattern: String::from("lib/(.*).rb"),
+                        target: String::from("spec/$1_spec.rb"),
+                    },
+                }),
+                Spec::from(&SpecMatch {
+                    changed: PathBuf::from("lib/a.rb"),
+                    location: String::from("spec/b_spec.rb"),
+                    reason: Reason::CoChange { score: 0.5 },
+                }),
+            ],
+        };
+        assert_eq!(
+            json(&selected),
+            concat!(
+                r#"{"version":1,"event":"specs_selected","project":"web","specs":["#,
+                r#"{"changed":"lib/a.rb","location":"spec/a_spec.rb","reason":{"type":"rule","pattern":"lib/(.*).rb","target":"spec/$1_spec.rb"},"suggestion":false},"#,
+                r#"{"changed":"lib/a.rb","location":"spec/b_spec.rb","reason":{"type":"co_change","score":0.5},"suggestion":true}"#,
+                r#"]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn runs() {
+        let cases = vec![
+            (
+                Message::RunStarted {
+                    run: run(),
+                    root: String::from("."),
+                    locations: vec![String::from("spec/a_spec.rb")],
+                },
+                r#"{"version":1,"event":"run_started","run":{"project":"web","number":3},"root":".","locations":["spec/a_spec.rb"]}"#,
+            ),
+            (
+                Message::RunLoaded {
+                    run: run(),
+                    count: Some(2),
+                },
+                r#"{"version":1,"event":"run_loaded","run":{"project":"web","number":3},"count":2}"#,
+            ),
+            (
+                Message::ExampleStarted {
+                    run: run(),
+                    id: String::from("./spec/a_spec.rb[1:1]"),
+                    location: String::from("./spec/a_spec.rb:2"),
+                    description: None,
+                },
+                r#"{"version":1,"event":"example_started","run":{"project":"web","number":3},"id":"./spec/a_spec.rb[1:1]","location":"./spec/a_spec.rb:2","description":null}"#,
+            ),
+            (
+                Message::ExamplePassed {
+                    run: run(),
+                    id: String::from("./spec/a_spec.rb[1:1]"),
+                    location: String::from("./spec/a_spec.rb:2"),
+                    description: String::from("works"),
+                    run_time: 0.25,
+                },
+                r#"{"version":1,"event":"example_passed","run":{"project":"web","number":3},"id":"./spec/a_spec.rb[1:1]","location":"./spec/a_spec.rb:2","description":"works","run_time":0.25}"#,
+            ),
+            (
+                Message::ExampleFailed {
+                    run: run(),
+                    id: String::from("./spec/a_spec.rb[1:2]"),
+                    location: Some(String::from("./spec/a_spec.rb:5")),
+                    description: Some(String::from("breaks")),
+                    run_time: 0.5,
+                    exception: Some(String::from("expected 1")),
+                    backtrace: Some(vec![String::from("./spec/a_spec.rb:6")]),
+                },
+                r#"{"version":1,"event":"example_failed","run":{"project":"web","number":3},"id":"./spec/a_spec.rb[1:2]","location":"./spec/a_spec.rb:5","description":"breaks","run_time":0.5,"exception":"expected 1","backtrace":["./spec/a_spec.rb:6"]}"#,
+            ),
+            (
+                Message::ExampleCoverage {
+                    run: run(),
+                    id: String::from("./spec/a_spec.rb[1:1]"),
+                    files: vec![String::from("lib/a.rb")],
+                },
+                r#"{"version":1,"event":"example_coverage","run":{"project":"web","number":3},"id":"./spec/a_spec.rb[1:1]","files":["lib/a.rb"]}"#,
+            ),
+            (
+                Message::ExamplesFinished { run: run() },
+                r#"{"version":1,"event":"examples_finished","run":{"project":"web","number":3}}"#,
+            ),
+            (
+                Message::Output {
+                    run: run(),
+                    stream: OutputStream::Stderr,
+                    line: String::from("warning"),
+                },
+                r#"{"version":1,"event":"output","run":{"project":"web","number":3},"stream":"stderr","line":"warning"}"#,
+            ),
+            (
+                Message::RunCancelled { run: run() },
+                r#"{"version":1,"event":"run_cancelled","run":{"project":"web","number":3}}"#,
+            ),
+            (
+                Message::RunError {
+                    run: run(),
+                    message: String::from("LoadError"),
+                },
+                r#"{"version":1,"event":"run_error","run":{"project":"web","number":3},"message":"LoadError"}"#,
+            ),
+            (
+                Message::RunFinished {
+                    run: run(),
+                    passed: 1,
+                    failed: 1,
+                    errors: vec![],
+                    duration: 1.5,
+                },
+                r#"{"version":1,"event":"run_finished","run":{"project":"web","number":3},"passed":1,"failed":1,"errors":[],"duration":1.5}"#,
+            ),
+        ];
+
+        for (message, expected) in cases {
+            assert_eq!(json(&message), expected);
+        }
+    }
+
+    #[test]
+    fn requests_and_settings() {
+        let cases = vec![
+            (
+                Message::RunFileRequested {
+                    path: String::from("lib/a.rb"),
+                },
+                r#"{"version":1,"event":"run_file_requested","path":"lib/a.rb"}"#,
+            ),
+            (
+                Message::RerunFailuresRequested,
+                r#"{"version":1,"event":"rerun_failures_requested"}"#,
+            ),
+            (
+                Message::RunAllChangedRequested,
+                r#"{"version":1,"event":"run_all_changed_requested"}"#,
+            ),
+            (
+                Message::RunLocationsRequested {
+                    project: String::from("web"),
+                    locations: vec![String::from("spec/a_spec.rb:2")],
+                },
+                r#"{"version":1,"event":"run_locations_requested","project":"web","locations":["spec/a_spec.rb:2"]}"#,
+            ),
+            (
+                Message::CancelRequested,
+                r#"{"version":1,"event":"cancel_requested"}"#,
+            ),
+            (
+                Message::ChangedCleared,
+                r#"{"version":1,"event":"changed_cleared"}"#,
+            ),
+            (
+                Message::SetBranchRequested {
+                    branch: String::from("main"),
+                },
+                r#"{"version":1,"event":"set_branch_requested","branch":"main"}"#,
+            ),
+            (
+                Message::BranchChanged {
+                    branch: String::from("main"),
+                },
+                r#"{"version":1,"event":"branch_changed","branch":"main"}"#,
+            ),
+            (
+                Message::WatchingToggled { watching: false },
+                r#"{"version":1,"event":"watching_toggled","watching":false}"#,
+            ),
+            (
+                Message::SpecFilesIndexed {
+                    project: String::from("web"),
+                    files: vec![String::from("spec/a_spec.rb")],
+                },
+                r#"{"version":1,"event":"spec_files_indexed","project":"web","files":["spec/a_spec.rb"]}"#,
+            ),
+            (
+                Message::Error {
+                    message: String::from("Could not save results"),
+                },
+                r#"{"version":1,"event":"error","message":"Could not save results"}"#,
+            ),
+            (
+                Message::Lagged { missed: 4 },
+                r#"{"version":1,"event":"lagged","missed":4}"#,
+            ),
+            (Message::Quit, r#"{"version":1,"event":"quit"}"#),
+        ];
+
+        for (message, expected) in cases {
+            assert_eq!(json(&message), expected);
+        }
+    }
+
+    #[test]
+    fn history() {
+        let mut examples = BTreeMap::new();
+        examples.insert(
+            String::from("./spec/a_spec.rb[1:1]"),
+            ExampleHistory {
+                location: Some(String::from("./spec/a_spec.rb:2")),
+                description: Some(String::from("works")),
+                results: vec![ResultRecord {
+                    passed: true,
+                    run_time: 0.25,
+                    at: 1600000000,
+                }],
+            },
+        );
+        let message = Message::HistoryUpdated {
+            project: String::from("web"),
+            examples,
+        };
+
+        assert_eq!(
+            json(&message),
+            concat!(
+                r#"{"version":1,"event":"history_updated","project":"web","examples":{"./spec/a_spec.rb[1:1]":"#,
+                r#"{"location":"./spec/a_spec.rb:2","description":"works","results":[{"passed":true,"run_time":0.25,"at":1600000000}]}"#,
+                r#"}}"#
+            )
+        );
+    }
+}
@@ -16,4 +16,90 @@ pub struct Opt {
     pub cli: bool,
     #[structopt(long)]
     pub ui: bool,
+    /// `tui`, `text` for a line at a time, `ndjson` for every event as a
+    /// line of JSON, or `jsonrpc` to take JSON-RPC requests on stdin, for
+    /// editors and other tools
+    #[structopt(long, possible_values = &["tui", "text", "ndjson", "jsonrpc"])]
+    pub format: Option<OutputFormat>,
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Without a command spec_detect watches for changes until quit.
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Run the specs for everything changed from the base branch once, and
+    /// exit non-zero if any fail
+    Run,
+    /// Print the changed files and the spec locations they map to, without
+    /// running anything
+    List {
+        /// `text`, `json`, or `null` for just the locations separated by NUL
+        /// characters, for `xargs -0`. Locations are relative to their
+        /// project's root, except with `null`, where they are relative to the
+        /// repository root
+        #[structopt(long, default_value = "text", possible_values = &["text", "json", "null"])]
+        format: ListFormat,
+    },
+    /// Show how a file is filtered and mapped to specs, to see why it does
+    /// or doesn't select the specs you expect
+    Explain {
+        /// The file, relative to the current directory
+        path: std::path::PathBuf,
+    },
+    /// Inspect the project in the current directory and write a
+    /// spec_detect.toml for it
+    Init {
+        /// Overwrite an existing spec_detect.toml
+        #[structopt(long)]
+        force: bool,
+    },
+    /// Check the repository, configuration and rspec setup, and suggest
+    /// fixes for any problems
+    Doctor,
+    /// Watch for changes as a language server on stdio, showing failed
+    /// examples as diagnostics and offering code lenses to run specs
+    Lsp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Tui,
+    Text,
+    Ndjson,
+    Jsonrpc,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tui" => Ok(OutputFormat::Tui),
+            "text" => Ok(OutputFormat::Text),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "jsonrpc" => Ok(OutputFormat::Jsonrpc),
+            _ => Err(anyhow::anyhow!("Unknown format {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListFormat {
+    Text,
+    Json,
+    Null,
+}
+
+impl std::str::FromStr for ListFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ListFormat::Text),
+            "json" => Ok(ListFormat::Json),
+            "null" => Ok(ListFormat::Null),
+            _ => Err(anyhow::anyhow!("Unknown format {}", s)),
+        }
+    }
 }
@@ -0,0 +1,250 @@
+use crate::configuration::{Configuration, ProjectConfiguration};
+use crate::mapping::presets;
+use crate::mapping::{
+    CoChangeIndex, CoChangeSelector, ConstantSelector, CoverageIndex, CoverageSelector, MapRules,
+    RequireSelector, Selector, SpecMatch, SupportSelector,
+};
+use crate::repo_watcher::{ChangedFile, CodeRepo};
+use crate::ruby::rspec::RSpecConfiguration;
+use crate::test_runner::ResultStore;
+use crate::util::path_filter::PathFilter;
+use crate::util::source_files;
+
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::UnboundedSender;
+
+type Map = HashMap<String, Vec<(String, String)>>;
+
+/// A project within the repository, with its own include filter, mapping
+/// rules and rspec settings. Changed files are reported relative to the
+/// repository root, so everything here translates them relative to `root`.
+pub struct Project {
+    pub name: String,
+    pub root: PathBuf,
+    pub rspec: RSpecConfiguration,
+    pub rules: MapRules,
+    /// Present when coverage collection is on, for the runner to record into
+    pub coverage: Option<Arc<Mutex<CoverageIndex>>>,
+    /// The recent results of each example, for the runner to record into
+    pub results: Arc<Mutex<ResultStore>>,
+    path_filter: PathFilter,
+    spec_filter: PathFilter,
+    selectors: Vec<Box<dyn Selector>>,
+}
+
+/// The include globs and `[map]` rules of a project once its preset, if it
+/// has one, is applied.
+pub fn with_preset(config: &ProjectConfiguration) -> anyhow::Result<(Vec<String>, Map)> {
+    let preset = config.preset.as_deref().map(presets::find).transpose()?;
+    let map = match preset {
+        Some(preset) => presets::layer(preset, &config.map),
+        None => config.map.clone(),
+    };
+    let include: Vec<String> = match preset {
+        Some(preset) if config.include.is_empty() => {
+            preset.include.iter().map(|s| s.to_string()).collect()
+        }
+        _ => config.include.clone(),
+    };
+
+    Ok((include, map))
+}
+
+/// The files changed by each of the last `limit` commits, relative to the
+/// project root.
+fn commit_history(root: &Path, limit: usize) -> anyhow::Result<Vec<Vec<PathBuf>>> {
+    let history = CodeRepo::open(".")?.commit_history(limit)?;
+
+    Ok(history
+        .into_iter()
+        .map(|files| {
+            files
+                .iter()
+                .filter_map(|f| f.strip_prefix(root).ok().map(|p| p.to_path_buf()))
+                .collect()
+        })
+        .collect())
+}
+
+impl Project {
+    /// Selectors that index in the background send what goes wrong doing so
+    /// to `problems`.
+    pub fn new(
+        config: &ProjectConfiguration,
+        problems: &UnboundedSender<String>,
+    ) -> anyhow::Result<Self> {
+        let root: PathBuf = Path::new(&config.root)
+            .components()
+            .filter(|c| *c != Component::CurDir)
+            .collect();
+
+        let (include, map) = with_preset(config)?;
+
+        let path_filter = PathFilter::new(&include)
+            .with_context(|| format!("Invalid include configuration for {}", config.name))?;
+        let rules = MapRules::new(&map, "rspec")
+            .with_context(|| format!("Invalid map configuration for {}", config.name))?;
+
+        let spec_files = || {
+            PathFilter::new(&config.spec_files)
+                .with_context(|| format!("Invalid spec_files for {}", config.name))
+        };
+        let mut selectors: Vec<Box<dyn Selector>> = vec![];
+
+        if config.select.requires {
+            selectors.push(Box::new(RequireSelector::new(&root, spec_files()?)));
+        }
+
+        if config.select.constants {
+            selectors.push(Box::new(ConstantSelector::new(
+                &root,
+                spec_files()?,
+                config.select.inflections.clone(),
+            )));
+        }
+
+        if config.select.support {
+            selectors.push(Box::new(SupportSelector::new(&root, spec_files()?)));
+        }
+
+        if config.select.co_change {
+            let (name, root, limit, problems) = (
+                config.name.clone(),
+                root.clone(),
+                config.select.co_change_commits,
+                problems.clone(),
+            );
+            let spec_files = spec_files()?;
+
+            // Without history there is nothing to suggest, but the other
+            // selectors still work
+            selectors.push(Box::new(CoChangeSelector::spawn(
+                move || match commit_history(&root, limit) {
+                    Ok(history) => CoChangeIndex::build(&history, &spec_files),
+                    Err(err) => {
+                        let problem =
+                            format!("Not suggesting co-changed specs for {}: {:#}", name, err);
+                        problems.send(problem).ok();
+                        CoChangeIndex::default()
+                    }
+                },
+                config.select.co_change_min_score,
+            )));
+        }
+
+        let coverage = if config.select.coverage {
+            let index = Arc::new(Mutex::new(CoverageIndex::load(&root)));
+            selectors.push(Box::new(CoverageSelector::new(Arc::clone(&index))));
+            Some(index)
+        } else {
+            None
+        };
+
+        let results = Arc::new(Mutex::new(ResultStore::load(&root)));
+
+        Ok(Project {
+            name: config.name.clone(),
+            root,
+            rspec: config.rspec.clone(),
+            rules,
+            coverage,
+            results,
+            path_filter,
+            spec_filter: spec_files()?,
+            selectors,
+        })
+    }
+
+    pub fn from_configuration(
+        config: &Configuration,
+        problems: &UnboundedSender<String>,
+    ) -> anyhow::Result<Vec<Self>> {
+        config
+            .projects()
+            .iter()
+            .map(|project| Project::new(project, problems))
+            .collect()
+    }
+
+    /// The path relative to this project's root, if it lives inside it.
+    pub fn relative_path<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
+        path.as_ref()
+            .strip_prefix(&self.root)
+            .ok()
+            .map(|p| p.to_path_buf())
+    }
+
+    pub fn path_filter(&self) -> &PathFilter {
+        &self.path_filter
+    }
+
+    pub fn spec_filter(&self) -> &PathFilter {
+        &self.spec_filter
+    }
+
+    pub fn include_path<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.relative_path(path)
+            .map_or(false, |p| self.path_filter.include_path(p))
+    }
+
+    /// Whether a path relative to the repository root is one of this
+    /// project's spec files.
+    pub fn is_spec_file<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.relative_path(path).map_or(false, |p| {
+            self.path_filter.include_path(&p) && self.spec_filter.include_path(&p)
+        })
+    }
+
+    /// Every spec file in the project that `include` covers, relative to the
+    /// project root.
+    pub fn spec_files(&self) -> Vec<String> {
+        source_files::find_files(&self.root, "rb")
+            .into_iter()
+            .filter(|p| self.path_filter.include_path(p) && self.spec_filter.include_path(p))
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn project_paths(&self, files: &[ChangedFile]) -> Vec<PathBuf> {
+        files
+            .iter()
+            .filter(|f| self.include_path(&f.path))
+            .filter_map(|f| self.relative_path(&f.path))
+            .collect()
+    }
+
+    /// Let the selectors refresh anything they have indexed for the files
+    /// that have changed.
+    pub fn update(&self, files: &[ChangedFile]) {
+        let paths = self.project_paths(files);
+
+        for selector in self.selectors.iter() {
+            selector.update(&self.root, &paths);
+        }
+    }
+
+    /// Block until every selector has finished indexing in the background.
+    pub fn wait_for_selectors(&self) {
+        for selector in self.selectors.iter() {
+            selector.wait();
+        }
+    }
+
+    /// Select the specs to run for the changed files that belong to this
+    /// project.
+    pub fn select(&self, files: &[ChangedFile]) -> Vec<SpecMatch> {
+        let paths = self.project_paths(files);
+
+        std::iter::once(&self.rules as &dyn Selector)
+            .chain(self.selectors.iter().map(|s| s.as_ref()))
+            .flat_map(|selector| selector.select(&self.root, &paths))
+            .map(|m| SpecMatch {
+                changed: self.root.join(&m.changed),
+                ..m
+            })
+            .collect()
+    }
+}
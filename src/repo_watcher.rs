@@ -1,11 +1,11 @@
 mod changed_file;
 mod code_repo;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 pub use changed_file::ChangedFile;
 
-use crate::util::path_sort;
-use code_repo::CodeRepo;
+use crate::util::{path_sort, store};
+pub use code_repo::CodeRepo;
 use owning_ref::MutexGuardRef;
 use std::collections::BTreeSet;
 use std::path::{Path, PathBuf};
@@ -13,122 +13,209 @@ use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
-use tokio::sync::watch;
+use tokio::sync::{mpsc::UnboundedSender, watch};
 
-use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
 
 pub struct RepoWatcher {
     repo: Arc<Mutex<CodeRepo>>,
-    branch: String,
+    branch: Arc<Mutex<String>>,
 }
 
 impl RepoWatcher {
     pub fn new<P: AsRef<Path>, S: AsRef<str>>(path: P, branch: S) -> Result<Self> {
         let repo = CodeRepo::open(path)?;
+        repo.check_branch(branch.as_ref())?;
         Ok(Self {
             repo: Arc::new(Mutex::new(repo)),
-            branch: branch.as_ref().to_owned(),
+            branch: Arc::new(Mutex::new(branch.as_ref().to_owned())),
         })
     }
 
+    /// The branch changes are compared against. Once it is changed the
+    /// watcher reports everything changed from the new branch.
+    pub fn branch(&self) -> Arc<Mutex<String>> {
+        Arc::clone(&self.branch)
+    }
+
+    /// Start watching in the background. Problems setting up, such as no
+    /// working tree or too few inotify watches, are returned here; later
+    /// ones are sent to `problems`.
     pub fn watch(
         &self,
         poll_duration: Duration,
         current_changes: bool,
-    ) -> watch::Receiver<Vec<ChangedFile>> {
-        let poll_duration = poll_duration.to_owned();
-        let repo = Arc::clone(&self.repo);
-        let branch = self.branch.clone();
+        problems: UnboundedSender<String>,
+    ) -> Result<watch::Receiver<Vec<ChangedFile>>> {
         let (tx, rx) = watch::channel(vec![]);
-
-        thread::spawn(move || {
-            let watch = RepoWatch {
-                repo,
-                branch,
-                poll_duration,
-                tx,
-            };
-            watch.watch_loop(current_changes).unwrap();
-        });
-
-        rx
+        let mut watch = RepoWatch::start(
+            Arc::clone(&self.repo),
+            Arc::clone(&self.branch),
+            poll_duration,
+            tx,
+            current_changes,
+            problems,
+        )?;
+
+        thread::spawn(move || watch.watch_loop());
+
+        Ok(rx)
     }
 }
 
 pub struct RepoWatch {
     repo: Arc<Mutex<CodeRepo>>,
-    branch: String,
+    branch: Arc<Mutex<String>>,
     poll_duration: Duration,
     tx: watch::Sender<Vec<ChangedFile>>,
+    watcher: RecommendedWatcher,
+    events: mpsc::Receiver<DebouncedEvent>,
+    seen_files: BTreeSet<ChangedFile>,
+    prefix: PathBuf,
+    /// The branch the seen files were found from
+    compared_to: String,
+    problems: UnboundedSender<String>,
 }
 
 impl RepoWatch {
-    fn checkout_repo<F>(&self, f: F) -> Result<()>
-    where
-        F: FnOnce(&CodeRepo),
-    {
-        let repo = MutexGuardRef::new(self.repo.lock().unwrap());
-        f(repo.as_ref());
-        Ok(())
-    }
-
-    fn watch_loop(&self, current_changes: bool) -> Result<()> {
-        let (w_tx, w_rx) = mpsc::channel::<DebouncedEvent>();
-        let mut seen_files: BTreeSet<ChangedFile> = BTreeSet::new();
-        let mut prefix: PathBuf = PathBuf::new();
-        let mut first_changed_files = vec![];
-
-        self.checkout_repo(|r| {
-            prefix = r.path().unwrap();
-            first_changed_files = r.all_changed_files(&self.branch);
-        })?;
+    fn start(
+        repo: Arc<Mutex<CodeRepo>>,
+        branch: Arc<Mutex<String>>,
+        poll_duration: Duration,
+        tx: watch::Sender<Vec<ChangedFile>>,
+        current_changes: bool,
+        problems: UnboundedSender<String>,
+    ) -> Result<Self> {
+        let (w_tx, events) = mpsc::channel::<DebouncedEvent>();
+        let compared_to = branch.lock().unwrap().clone();
+
+        let (prefix, mut first_changed_files) = {
+            let r = repo.lock().unwrap();
+            let prefix = r
+                .path()
+                .context("The repository has no working tree to watch")?;
+            (prefix, r.all_changed_files(&compared_to)?)
+        };
 
         first_changed_files.sort_unstable_by(|a, b| path_sort::mtime_comparator(&a.path, &b.path));
 
         if current_changes {
-            self.tx.broadcast(first_changed_files.clone())?;
+            tx.broadcast(first_changed_files.clone())?;
         }
 
+        let watcher = watcher(w_tx, Duration::from_millis(100)).context(
+            "Could not watch for changes, the inotify limits (fs.inotify.max_user_*) may be too low",
+        )?;
+
+        let mut watch = RepoWatch {
+            repo,
+            branch,
+            poll_duration,
+            tx,
+            watcher,
+            events,
+            seen_files: BTreeSet::new(),
+            prefix,
+            compared_to,
+            problems,
+        };
+
         for file in first_changed_files.into_iter() {
-            seen_files.insert(file);
+            watch.watch_file(file);
         }
 
-        let mut watcher = watcher(w_tx, Duration::from_millis(100)).unwrap();
-        for file in seen_files.iter() {
-            watcher.watch(file.path.to_owned(), RecursiveMode::NonRecursive)?;
+        Ok(watch)
+    }
+
+    /// Pass on a problem that watching carries on from.
+    fn report(&self, problem: String) {
+        // Sending fails once nothing is listening, as when quitting
+        self.problems.send(problem).ok();
+    }
+
+    fn checkout_repo<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&CodeRepo) -> T,
+    {
+        let repo = MutexGuardRef::new(self.repo.lock().unwrap());
+        f(repo.as_ref())
+    }
+
+    /// Watch `file` for writes unless it is already, returning whether it
+    /// is newly seen. A file that is gone, deleted from the branch or since
+    /// git reported it, is seen without being watched, and one that can't be
+    /// watched for another reason is reported and otherwise skipped. Files
+    /// spec_detect stores are never seen.
+    fn watch_file(&mut self, file: ChangedFile) -> bool {
+        if self.seen_files.contains(&file) || store::in_store(&file.path) {
+            return false;
+        }
+
+        let path = self.prefix.join(&file.path);
+        if path.exists() {
+            match self.watcher.watch(&path, RecursiveMode::NonRecursive) {
+                Ok(()) => {}
+                Err(_) if !path.exists() => {}
+                Err(err) => {
+                    self.report(format!("Could not watch {}: {}", file.path.display(), err))
+                }
+            }
         }
 
+        self.seen_files.insert(file);
+        true
+    }
+
+    /// Report changes until nothing is listening for them.
+    fn watch_loop(&mut self) {
         loop {
-            let inform_files = match w_rx.recv_timeout(self.poll_duration) {
+            let inform_files = match self.events.recv_timeout(self.poll_duration) {
                 Ok(event) => match event {
-                    DebouncedEvent::Write(path) => vec![ChangedFile::new(
-                        path.strip_prefix(&prefix).unwrap().to_owned(),
-                    )],
+                    // Only the changed files are watched, so anything else
+                    // isn't one of them
+                    DebouncedEvent::Write(path) => match path.strip_prefix(&self.prefix) {
+                        Ok(path) => vec![ChangedFile::new(path.to_owned())],
+                        Err(_) => vec![],
+                    },
                     _ => vec![],
                 },
-                Err(_) => {
-                    let mut new_files: BTreeSet<ChangedFile> = BTreeSet::new();
+                Err(_) if *self.branch.lock().unwrap() != self.compared_to => {
+                    self.compared_to = self.branch.lock().unwrap().clone();
+
+                    // Everything changed from the new branch, as on starting
+                    let changed_files = self
+                        .checkout_repo(|r| r.all_changed_files(&self.compared_to))
+                        .unwrap_or_else(|err| {
+                            self.report(format!("{:#}", err));
+                            vec![]
+                        });
+
+                    for file in changed_files.iter() {
+                        self.watch_file(file.clone());
+                    }
 
-                    self.checkout_repo(|r| {
-                        for file in r.new_files().into_iter() {
-                            new_files.insert(file);
+                    changed_files
+                }
+                Err(_) => {
+                    let new_files = self.checkout_repo(|r| r.new_files()).unwrap_or_else(|err| {
+                        self.report(format!("{:#}", err));
+                        vec![]
+                    });
+
+                    let mut inform_files = vec![];
+                    for file in new_files.into_iter().collect::<BTreeSet<_>>() {
+                        if self.watch_file(file.clone()) {
+                            inform_files.push(file);
                         }
-                    })?;
-
-                    let new_files: Vec<ChangedFile> =
-                        new_files.difference(&seen_files).cloned().collect();
-
-                    for file in new_files.clone() {
-                        watcher.watch(file.path.clone(), RecursiveMode::NonRecursive)?;
-                        seen_files.insert(file);
                     }
 
-                    new_files
+                    inform_files
                 }
             };
 
-            if !inform_files.is_empty() {
-                self.tx.broadcast(inform_files)?;
+            // Sending fails once nothing is listening, as when quitting
+            if !inform_files.is_empty() && self.tx.broadcast(inform_files).is_err() {
+                return;
             }
         }
     }
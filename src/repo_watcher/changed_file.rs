@@ -34,4 +34,50 @@ impl ChangedFile {
             status: Delta::Modified,
         }
     }
+
+    /// The letter `git status --short` shows for the status, or `X` for a
+    /// file that couldn't be read as libgit2 shows it.
+    pub fn status_letter(&self) -> &'static str {
+        match self.status {
+            Delta::Unmodified => " ",
+            Delta::Added => "A",
+            Delta::Deleted => "D",
+            Delta::Modified => "M",
+            Delta::Renamed => "R",
+            Delta::Copied => "C",
+            Delta::Ignored => "!",
+            Delta::Untracked => "?",
+            Delta::Typechange => "T",
+            Delta::Unreadable => "X",
+            Delta::Conflicted => "U",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_letters_are_gits() {
+        let letter = |status| {
+            ChangedFile {
+                path: PathBuf::from("app/models/user.rb"),
+                status,
+            }
+            .status_letter()
+        };
+
+        assert_eq!(letter(Delta::Unmodified), " ");
+        assert_eq!(letter(Delta::Added), "A");
+        assert_eq!(letter(Delta::Deleted), "D");
+        assert_eq!(letter(Delta::Modified), "M");
+        assert_eq!(letter(Delta::Renamed), "R");
+        assert_eq!(letter(Delta::Copied), "C");
+        assert_eq!(letter(Delta::Ignored), "!");
+        assert_eq!(letter(Delta::Untracked), "?");
+        assert_eq!(letter(Delta::Typechange), "T");
+        assert_eq!(letter(Delta::Unreadable), "X");
+        assert_eq!(letter(Delta::Conflicted), "U");
+    }
 }
@@ -1,44 +1,85 @@
-use git2::{BranchType, Delta, DiffOptions, Repository, Status, StatusEntry, StatusOptions};
+use git2::{
+    BranchType, Delta, DiffOptions, ErrorCode, Repository, Status, StatusEntry, StatusOptions, Tree,
+};
 // use std::ffi::CString;
 use crate::repo_watcher::ChangedFile;
+use crate::util::store;
+use anyhow::Context;
 use std::path::{Path, PathBuf};
 
 fn status_to_changed_file(status: StatusEntry, delta: Delta) -> Option<ChangedFile> {
-    status.path().map(|p| ChangedFile {
-        path: PathBuf::from(p),
-        status: delta,
-    })
+    status
+        .path()
+        .filter(|p| !store::in_store(Path::new(p)))
+        .map(|p| ChangedFile {
+            path: PathBuf::from(p),
+            status: delta,
+        })
+}
+
+fn prefix_files(prefix: &Path, files: Vec<ChangedFile>) -> Vec<ChangedFile> {
+    files
+        .into_iter()
+        .map(|f| ChangedFile {
+            path: prefix.join(f.path),
+            status: f.status,
+        })
+        .collect()
 }
 
 pub struct CodeRepo {
     repo: Repository,
+    submodules: Vec<(PathBuf, CodeRepo)>,
 }
 
 impl CodeRepo {
+    /// Opens the repository containing `path`. Linked worktrees are resolved
+    /// by libgit2, and any checked out submodules are opened alongside so
+    /// their changes can be reported with paths relative to this worktree.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<CodeRepo, git2::Error> {
-        let repo = Repository::open(path);
+        let repo = Repository::discover(path)?;
+        Ok(CodeRepo::from_repository(repo))
+    }
 
-        match repo {
-            Ok(repo) => Ok(CodeRepo { repo }),
-            Err(e) => Err(e),
-        }
+    fn from_repository(repo: Repository) -> CodeRepo {
+        let submodules = repo
+            .submodules()
+            .map(|submodules| {
+                submodules
+                    .iter()
+                    .filter_map(|sm| {
+                        sm.open()
+                            .ok()
+                            .map(|r| (sm.path().to_path_buf(), CodeRepo::from_repository(r)))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        CodeRepo { repo, submodules }
     }
 
+    /// The root of the working tree. For a linked worktree `.git` is a file
+    /// pointing into the main repository, so the parent of `repo.path()`
+    /// can't be used here.
     pub fn path(&self) -> Option<PathBuf> {
-        self.repo
-            .path()
-            .canonicalize()
-            .ok()
-            .and_then(|p| p.parent().map(|p| p.to_path_buf()))
+        self.repo.workdir().and_then(|p| p.canonicalize().ok())
     }
 
-    pub fn new_files(&self) -> Vec<ChangedFile> {
+    fn is_submodule_path(&self, path: &Path) -> bool {
+        self.submodules.iter().any(|(p, _)| p == path)
+    }
+
+    pub fn new_files(&self) -> anyhow::Result<Vec<ChangedFile>> {
         let mut status_options = StatusOptions::default();
         status_options.include_untracked(true);
+        status_options.exclude_submodules(true);
 
         let r = &self.repo;
 
-        let statuses = r.statuses(Some(&mut status_options)).unwrap();
+        let statuses = r
+            .statuses(Some(&mut status_options))
+            .context("Could not read the repository's status")?;
         let from_status = statuses.iter().filter_map(|s| match s.status() {
             Status::WT_NEW => status_to_changed_file(s, Delta::Added),
             Status::INDEX_NEW => status_to_changed_file(s, Delta::Added),
@@ -46,24 +87,82 @@ impl CodeRepo {
             _ => None,
         });
 
-        let new_files: Vec<ChangedFile> = from_status.collect();
+        let mut new_files: Vec<ChangedFile> = from_status.collect();
 
-        new_files
-    }
+        for (sm_path, sm) in self.submodules.iter() {
+            new_files.extend(prefix_files(sm_path, sm.new_files()?));
+        }
 
-    pub fn changed_files(&self, branch_name: &str) -> Vec<ChangedFile> {
-        let mut diff_options = DiffOptions::default();
+        Ok(new_files)
+    }
 
+    /// The branch changes are likely compared against: the one `origin/HEAD`
+    /// points to, else `main` or `master`, else whatever is checked out.
+    pub fn default_branch(&self) -> Option<String> {
         let r = &self.repo;
 
-        let diff = r
+        let remote_head = r
+            .find_reference("refs/remotes/origin/HEAD")
+            .ok()
+            .and_then(|head| {
+                head.symbolic_target()
+                    .and_then(|t| t.strip_prefix("refs/remotes/origin/"))
+                    .map(String::from)
+            });
+        if remote_head.is_some() {
+            return remote_head;
+        }
+
+        for name in ["main", "master"].iter() {
+            if r.find_branch(name, BranchType::Local).is_ok() {
+                return Some(name.to_string());
+            }
+        }
+
+        r.head().ok().and_then(|h| h.shorthand().map(String::from))
+    }
+
+    fn branch_tree(&self, branch_name: &str) -> anyhow::Result<Tree<'_>> {
+        self.repo
             .find_branch(branch_name, BranchType::Local)
             .map(|m| m.into_reference())
             .and_then(|r| r.peel_to_tree())
-            .and_then(|t| r.diff_tree_to_workdir_with_index(Some(&t), Some(&mut diff_options)))
-            .unwrap();
+            .with_context(|| {
+                format!(
+                    "No local branch {} to compare changes against, set `branch` in spec_detect.toml",
+                    branch_name
+                )
+            })
+    }
+
+    /// Check that changes can be compared against `branch_name`.
+    pub fn check_branch(&self, branch_name: &str) -> anyhow::Result<()> {
+        self.branch_tree(branch_name).map(|_| ())
+    }
+
+    pub fn changed_files(&self, branch_name: &str) -> anyhow::Result<Vec<ChangedFile>> {
+        let tree = self.branch_tree(branch_name)?;
+
+        self.changed_files_from(Some(&tree))
+    }
+
+    /// Diff the working tree against `base`. Submodules are compared against
+    /// the commit recorded for them in `base`, or reported in full when the
+    /// submodule doesn't exist there yet. When the recorded commit hasn't
+    /// been fetched they are compared against what is checked out instead,
+    /// rather than every file in them counting as changed.
+    fn changed_files_from(&self, base: Option<&Tree>) -> anyhow::Result<Vec<ChangedFile>> {
+        let mut diff_options = DiffOptions::default();
+        diff_options.ignore_submodules(true);
+
+        let r = &self.repo;
+
+        let diff = r
+            .diff_tree_to_workdir_with_index(base, Some(&mut diff_options))
+            .context("Could not diff the working tree")?;
 
-        diff.deltas()
+        let mut files: Vec<ChangedFile> = diff
+            .deltas()
             .filter_map(|delta| {
                 let status = delta.status();
 
@@ -72,23 +171,217 @@ impl CodeRepo {
                     Delta::Unmodified => None,
                     Delta::Ignored => None,
                     Delta::Unreadable => None,
-                    _ => delta.new_file().path().map(|p| ChangedFile {
-                        path: p.to_path_buf(),
-                        status,
-                    }),
+                    _ => delta
+                        .new_file()
+                        .path()
+                        .filter(|p| !self.is_submodule_path(p) && !store::in_store(p))
+                        .map(|p| ChangedFile {
+                            path: p.to_path_buf(),
+                            status,
+                        }),
                 }
             })
-            .collect()
+            .collect();
+
+        for (sm_path, sm) in self.submodules.iter() {
+            let sm_base = base
+                .and_then(|t| t.get_path(sm_path).ok())
+                .and_then(|entry| match sm.repo.find_commit(entry.id()) {
+                    Ok(commit) => commit.tree().ok(),
+                    Err(_) => sm.repo.head().and_then(|h| h.peel_to_tree()).ok(),
+                });
+
+            files.extend(prefix_files(
+                sm_path,
+                sm.changed_files_from(sm_base.as_ref())?,
+            ));
+        }
+
+        Ok(files)
     }
 
-    pub fn all_changed_files(&self, branch_name: &str) -> Vec<ChangedFile> {
+    /// The files changed by each of the last `limit` non-merge commits on
+    /// HEAD, newest first. There are none before the first commit.
+    pub fn commit_history(&self, limit: usize) -> Result<Vec<Vec<PathBuf>>, git2::Error> {
+        let r = &self.repo;
+        match r.head() {
+            Err(err) if err.code() == ErrorCode::UnbornBranch => return Ok(vec![]),
+            head => head?,
+        };
+        let mut revwalk = r.revwalk()?;
+        revwalk.push_head()?;
+
+        let mut history = vec![];
+
+        for oid in revwalk {
+            if history.len() >= limit {
+                break;
+            }
+
+            let commit = r.find_commit(oid?)?;
+            if commit.parent_count() != 1 {
+                continue;
+            }
+
+            let tree = commit.tree()?;
+            let parent_tree = commit.parent(0)?.tree()?;
+            let diff = r.diff_tree_to_tree(Some(&parent_tree), Some(&tree), None)?;
+
+            history.push(
+                diff.deltas()
+                    .filter_map(|delta| delta.new_file().path().map(|p| p.to_path_buf()))
+                    .collect(),
+            );
+        }
+
+        Ok(history)
+    }
+
+    pub fn all_changed_files(&self, branch_name: &str) -> anyhow::Result<Vec<ChangedFile>> {
         let mut files = self
-            .new_files()
+            .new_files()?
             .into_iter()
-            .chain(self.changed_files(branch_name).into_iter())
+            .chain(self.changed_files(branch_name)?)
             .collect::<Vec<ChangedFile>>();
         files.sort_unstable();
         files.dedup();
-        return files;
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_dir::TestDir;
+
+    use git2::{Commit, Oid, Signature};
+    use std::fs;
+
+    fn init(dir: &TestDir, name: &str) -> Repository {
+        Repository::init(dir.path.join(name)).unwrap()
+    }
+
+    fn add(repo: &Repository, path: &str) {
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(path)).unwrap();
+        index.write().unwrap();
+    }
+
+    /// Commit the index onto HEAD.
+    fn commit(repo: &Repository) -> Oid {
+        let mut index = repo.index().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<&Commit> = parent.iter().collect();
+
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Commit",
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    /// A repository at `main` with a submodule at `vendor/sub`, cloned from
+    /// one at `origin` holding `lib/a.rb`.
+    fn with_submodule(dir: &TestDir) -> Repository {
+        dir.write("origin/lib/a.rb", "A = 1\n")
+            .write("main/README", "");
+        let origin = init(dir, "origin");
+        add(&origin, "lib/a.rb");
+        commit(&origin);
+
+        let main = init(dir, "main");
+        add(&main, "README");
+        let url = dir.path.join("origin").to_string_lossy().into_owned();
+        {
+            let mut submodule = main.submodule(&url, Path::new("vendor/sub"), true).unwrap();
+            submodule.clone(None).unwrap();
+            submodule.add_finalize().unwrap();
+        }
+        commit(&main);
+
+        main
+    }
+
+    fn changed_paths(dir: &TestDir) -> Vec<(String, Delta)> {
+        CodeRepo::open(dir.path.join("main"))
+            .unwrap()
+            .all_changed_files("master")
+            .unwrap()
+            .into_iter()
+            .map(|f| (f.path.to_string_lossy().into_owned(), f.status))
+            .collect()
+    }
+
+    #[test]
+    fn submodule_changes_are_under_its_path() {
+        let dir = TestDir::new("code-repo-submodule");
+        with_submodule(&dir);
+        dir.write("main/vendor/sub/lib/a.rb", "A = 2\n")
+            .write("main/vendor/sub/lib/b.rb", "B = 1\n");
+
+        assert_eq!(
+            changed_paths(&dir),
+            vec![
+                (String::from("vendor/sub/lib/a.rb"), Delta::Modified),
+                (String::from("vendor/sub/lib/b.rb"), Delta::Added),
+            ]
+        );
+    }
+
+    #[test]
+    fn an_unfetched_submodule_commit_is_compared_to_its_head() {
+        let dir = TestDir::new("code-repo-unfetched");
+        let main = with_submodule(&dir);
+
+        // Record a commit for the submodule that it doesn't have
+        let mut index = main.index().unwrap();
+        let mut entry = index.get_path(Path::new("vendor/sub"), 0).unwrap();
+        entry.id = Oid::from_str("1111111111111111111111111111111111111111").unwrap();
+        index.add(&entry).unwrap();
+        index.write().unwrap();
+        commit(&main);
+        dir.write("main/vendor/sub/lib/b.rb", "B = 1\n");
+
+        assert_eq!(
+            changed_paths(&dir),
+            vec![(String::from("vendor/sub/lib/b.rb"), Delta::Added)]
+        );
+    }
+
+    #[test]
+    fn a_linked_worktree_is_its_own_root() {
+        let dir = TestDir::new("code-repo-worktree");
+        dir.write("main/lib/a.rb", "").write("linked/lib/a.rb", "");
+        let main = init(&dir, "main");
+        add(&main, "lib/a.rb");
+        let head = main.find_commit(commit(&main)).unwrap();
+        main.branch("linked", &head, false).unwrap();
+
+        // What `git worktree add ../linked linked` leaves behind
+        let admin = dir.path.join("main/.git/worktrees/linked");
+        fs::create_dir_all(&admin).unwrap();
+        fs::write(admin.join("HEAD"), "ref: refs/heads/linked\n").unwrap();
+        fs::write(admin.join("commondir"), "../..\n").unwrap();
+        fs::write(
+            admin.join("gitdir"),
+            format!("{}\n", dir.path.join("linked/.git").display()),
+        )
+        .unwrap();
+        dir.write("linked/.git", &format!("gitdir: {}\n", admin.display()));
+
+        let repo = CodeRepo::open(dir.path.join("linked/lib")).unwrap();
+
+        assert!(repo.repo.is_worktree());
+        assert_eq!(
+            repo.path(),
+            Some(dir.path.join("linked").canonicalize().unwrap())
+        );
+        assert_eq!(repo.default_branch(), Some(String::from("master")));
     }
 }
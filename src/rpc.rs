@@ -0,0 +1,428 @@
+pub mod protocol;
+mod snapshot;
+
+use protocol::{
+    Notification, Reply, Request, Response, RpcError, INVALID_PARAMS, INVALID_REQUEST,
+    METHOD_NOT_FOUND, PARSE_ERROR,
+};
+use snapshot::StateSnapshot;
+
+use crate::app_state::{AppStateManager, Event};
+use crate::ndjson::{Message, Versioned, VERSION};
+use crate::repo_watcher::CodeRepo;
+use crate::{Program, CONFIG};
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::stream::StreamExt;
+use tokio::sync::broadcast::RecvError;
+
+/// Takes JSON-RPC 2.0 requests on stdin and answers on stdout, a message per
+/// line, so that an editor can drive spec_detect. After `subscribe` every
+/// event is sent as an `event` notification, in the same form as the ndjson
+/// output. Batches of requests are answered with an array of responses.
+pub struct RpcApp {}
+
+#[derive(Deserialize)]
+struct RunParams {
+    /// Optional when there is only one project
+    project: Option<String>,
+    /// Relative to the project root
+    locations: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RunFileParams {
+    /// A changed file, relative to the repository root
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct SetBranchParams {
+    branch: String,
+}
+
+fn write_line<T: Serialize>(message: &T) -> anyhow::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    serde_json::to_writer(&mut out, message)?;
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+}
+
+struct Session {
+    app: AppStateManager,
+    /// The names of the configured projects
+    projects: Vec<String>,
+    subscribed: bool,
+}
+
+impl Session {
+    async fn dispatch(&self, event: Event) -> Result<Value, RpcError> {
+        self.app.dispatch(event).await.map_err(RpcError::failed)?;
+        Ok(Value::Null)
+    }
+
+    /// The project a request is for, which can be left out when there is
+    /// only one.
+    fn project(&self, name: Option<String>) -> Result<String, RpcError> {
+        match name {
+            Some(name) if self.projects.contains(&name) => Ok(name),
+            Some(name) => Err(RpcError::failed(format!("No project named {}", name))),
+            None => match self.projects.as_slice() {
+                [name] => Ok(name.clone()),
+                _ => Err(RpcError::new(
+                    INVALID_PARAMS,
+                    "project is needed when there is more than one",
+                )),
+            },
+        }
+    }
+
+    async fn call(&mut self, method: &str, params_value: Value) -> Result<Value, RpcError> {
+        match method {
+            "run" => {
+                let run: RunParams = params(params_value)?;
+                let project = self.project(run.project)?;
+                self.dispatch(Event::RunLocations(project, run.locations))
+                    .await
+            }
+            "run_file" => {
+                let run: RunFileParams = params(params_value)?;
+                self.dispatch(Event::RunFile(run.path)).await
+            }
+            "run_all_changed" => self.dispatch(Event::RunAllChanged).await,
+            "rerun_failures" => self.dispatch(Event::RerunFailures).await,
+            "cancel" => self.dispatch(Event::CancelRun).await,
+            "clear_changed" => self.dispatch(Event::ClearChanged).await,
+            "state" => serde_json::to_value(StateSnapshot::from(&self.app.state()))
+                .map_err(RpcError::failed),
+            "set_branch" => {
+                let set: SetBranchParams = params(params_value)?;
+                CodeRepo::open(".")
+                    .map_err(RpcError::failed)?
+                    .check_branch(&set.branch)
+                    .map_err(RpcError::failed)?;
+                self.dispatch(Event::SetBranch(set.branch)).await
+            }
+            "subscribe" => {
+                self.subscribed = true;
+                Ok(json!({ "version": VERSION }))
+            }
+            "unsubscribe" => {
+                self.subscribed = false;
+                Ok(Value::Null)
+            }
+            "shutdown" => self.dispatch(Event::Quit).await,
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("No method {}", method),
+            )),
+        }
+    }
+
+    /// Handle a line from the client, returning the reply to send if it
+    /// needs one.
+    async fn on_line(&mut self, line: &str) -> Option<Reply> {
+        let value: Value = match serde_json::from_str(line) {
+            Ok(value) => value,
+            Err(err) => {
+                return Some(Reply::One(Response::new(
+                    Value::Null,
+                    Err(RpcError::new(PARSE_ERROR, err.to_string())),
+                )))
+            }
+        };
+
+        match value {
+            Value::Array(values) if values.is_empty() => Some(Reply::One(Response::new(
+                Value::Null,
+                Err(RpcError::new(INVALID_REQUEST, "An empty batch")),
+            ))),
+            // Notifications in a batch get no response either, and a batch of
+            // only notifications gets nothing at all
+            Value::Array(values) => {
+                let mut responses = vec![];
+                for value in values {
+                    if let Some(response) = self.on_value(value).await {
+                        responses.push(response);
+                    }
+                }
+
+                if responses.is_empty() {
+                    None
+                } else {
+                    Some(Reply::Batch(responses))
+                }
+            }
+            value => self.on_value(value).await.map(Reply::One),
+        }
+    }
+
+    /// Handle a single request, returning the response if it isn't a
+    /// notification.
+    async fn on_value(&mut self, value: Value) -> Option<Response> {
+        let request: Request = match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(err) => {
+                return Some(Response::new(
+                    Value::Null,
+                    Err(RpcError::new(INVALID_REQUEST, err.to_string())),
+                ))
+            }
+        };
+
+        let result = if request.jsonrpc == "2.0" {
+            self.call(&request.method, request.params).await
+        } else {
+            Err(RpcError::new(
+                INVALID_REQUEST,
+                "Only JSON-RPC 2.0 is supported",
+            ))
+        };
+
+        request.id.map(|id| Response::new(id, result))
+    }
+}
+
+#[async_trait]
+impl Program for RpcApp {
+    async fn run<'stream>(&self, app: AppStateManager) -> anyhow::Result<()> {
+        let mut events = app.subscribe();
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let mut session = Session {
+            app,
+            projects: CONFIG
+                .get()
+                .projects()
+                .into_iter()
+                .map(|p| p.name)
+                .collect(),
+            subscribed: false,
+        };
+
+        loop {
+            tokio::select! {
+                line = lines.next() => match line {
+                    Some(line) => {
+                        let line = line?;
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+                        if let Some(reply) = session.on_line(&line).await {
+                            write_line(&reply)?;
+                        }
+                    }
+                    // The client has gone, so there is no one to run for
+                    None => {
+                        session.app.dispatch(Event::Quit).await?;
+                        break;
+                    }
+                },
+                event = events.recv() => {
+                    let message = match event {
+                        Ok((event, state)) => Message::from_event(&event, &state),
+                        Err(RecvError::Lagged(missed)) => Message::Lagged { missed },
+                        Err(RecvError::Closed) => break,
+                    };
+                    if session.subscribed {
+                        write_line(&Notification::new("event", Versioned::new(&message)))?;
+                    }
+                    if let Message::Quit = message {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(projects: &[&str]) -> Session {
+        Session {
+            app: AppStateManager::new(),
+            projects: projects.iter().map(|p| p.to_string()).collect(),
+            subscribed: false,
+        }
+    }
+
+    async fn reply(session: &mut Session, request: Value) -> Option<Value> {
+        session
+            .on_line(&request.to_string())
+            .await
+            .map(|reply| serde_json::to_value(reply).unwrap())
+    }
+
+    fn run(project: Option<&str>) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "run",
+            "params": { "project": project, "locations": ["spec/a_spec.rb:3"] },
+        })
+    }
+
+    #[tokio::test]
+    async fn methods_dispatch_their_events() {
+        let mut session = session(&["web"]);
+        let mut commands = session.app.commands().unwrap();
+
+        let response = reply(&mut session, run(None)).await.unwrap();
+        assert_eq!(
+            response,
+            json!({ "jsonrpc": "2.0", "id": 1, "result": null })
+        );
+
+        match commands.recv().await {
+            Some((Event::RunLocations(project, locations), _)) => {
+                assert_eq!(project, "web");
+                assert_eq!(locations, vec!["spec/a_spec.rb:3"]);
+            }
+            other => panic!("Expected a run, got {:?}", other.map(|(e, _)| e)),
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribe_gives_the_message_version() {
+        let mut session = session(&["web"]);
+
+        let response = reply(
+            &mut session,
+            json!({ "jsonrpc": "2.0", "id": "a", "method": "subscribe" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response["id"], "a");
+        assert_eq!(response["result"]["version"], VERSION);
+        assert!(session.subscribed);
+    }
+
+    #[tokio::test]
+    async fn bad_params_are_invalid() {
+        let mut session = session(&["web"]);
+
+        let response = reply(
+            &mut session,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "run", "params": { "locations": 3 } }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn the_project_is_needed_when_there_are_several() {
+        let mut session = session(&["web", "admin"]);
+
+        let response = reply(&mut session, run(None)).await.unwrap();
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+
+        let response = reply(&mut session, run(Some("api"))).await.unwrap();
+        assert_eq!(response["error"]["code"], protocol::REQUEST_FAILED);
+
+        let response = reply(&mut session, run(Some("admin"))).await.unwrap();
+        assert_eq!(response["result"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn only_json_rpc_2_is_supported() {
+        let mut session = session(&["web"]);
+
+        let response = reply(
+            &mut session,
+            json!({ "jsonrpc": "1.0", "id": 1, "method": "state" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response["error"]["code"], INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn unknown_methods_are_not_found() {
+        let mut session = session(&["web"]);
+
+        let response = reply(
+            &mut session,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "explode" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn notifications_get_no_response() {
+        let mut session = session(&["web"]);
+
+        let response = reply(
+            &mut session,
+            json!({ "jsonrpc": "2.0", "method": "explode" }),
+        )
+        .await;
+        assert_eq!(response, None);
+    }
+
+    #[tokio::test]
+    async fn unparsable_lines_are_parse_errors() {
+        let mut session = session(&["web"]);
+
+        let response = session.on_line("{").await.unwrap();
+        let response = serde_json::to_value(response).unwrap();
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+    }
+
+    #[tokio::test]
+    async fn batches_are_answered_together() {
+        let mut session = session(&["web"]);
+
+        let response = reply(
+            &mut session,
+            json!([
+                { "jsonrpc": "2.0", "id": 1, "method": "unsubscribe" },
+                { "jsonrpc": "2.0", "method": "unsubscribe" },
+                { "jsonrpc": "2.0", "id": 2, "method": "explode" },
+                3,
+            ]),
+        )
+        .await
+        .unwrap();
+
+        let responses = response.as_array().unwrap();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(responses[2]["error"]["code"], INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn batches_of_notifications_get_no_response() {
+        let mut session = session(&["web"]);
+
+        let response = reply(
+            &mut session,
+            json!([{ "jsonrpc": "2.0", "method": "unsubscribe" }]),
+        )
+        .await;
+        assert_eq!(response, None);
+
+        let response = reply(&mut session, json!([])).await.unwrap();
+        assert_eq!(response["error"]["code"], INVALID_REQUEST);
+    }
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::fmt::Display;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// The request was understood but couldn't be carried out, such as a run
+/// of a project that doesn't exist
+pub const REQUEST_FAILED: i64 = -32000;
+
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    /// Absent for notifications, which get no response. A null id is still
+    /// a request, answered with a null id.
+    #[serde(default, deserialize_with = "present")]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// Serde leaves a missing field at its default, `None`, and only calls
+/// this for one that is there, so `null` becomes `Some(Value::Null)`.
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new<S: Into<String>>(code: i64, message: S) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn failed<E: Display>(err: E) -> Self {
+        RpcError::new(REQUEST_FAILED, err.to_string())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub enum Outcome {
+    #[serde(rename = "result")]
+    Result(Value),
+    #[serde(rename = "error")]
+    Error(RpcError),
+}
+
+#[derive(Debug, Serialize)]
+pub struct Response {
+    pub jsonrpc: &'static str,
+    pub id: Value,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+impl Response {
+    pub fn new(id: Value, result: Result<Value, RpcError>) -> Self {
+        Response {
+            jsonrpc: "2.0",
+            id,
+            outcome: match result {
+                Ok(value) => Outcome::Result(value),
+                Err(err) => Outcome::Error(err),
+            },
+        }
+    }
+}
+
+/// What goes back for a line: the response to a request, or the responses
+/// to a batch of them in one array.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Reply {
+    One(Response),
+    Batch(Vec<Response>),
+}
+
+/// A message to the client that needs no reply.
+#[derive(Debug, Serialize)]
+pub struct Notification<T: Serialize> {
+    pub jsonrpc: &'static str,
+    pub method: &'static str,
+    pub params: T,
+}
+
+impl<T: Serialize> Notification<T> {
+    pub fn new(method: &'static str, params: T) -> Self {
+        Notification {
+            jsonrpc: "2.0",
+            method,
+            params,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: &str) -> Request {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn notifications_have_no_id() {
+        let notification = request(r#"{"jsonrpc":"2.0","method":"exit"}"#);
+        assert_eq!(notification.id, None);
+    }
+
+    #[test]
+    fn null_ids_are_kept() {
+        let with_null = request(r#"{"jsonrpc":"2.0","id":null,"method":"status"}"#);
+        assert_eq!(with_null.id, Some(Value::Null));
+
+        let with_number = request(r#"{"jsonrpc":"2.0","id":1,"method":"status"}"#);
+        assert_eq!(with_number.id, Some(Value::from(1)));
+    }
+}
@@ -0,0 +1,107 @@
+use crate::app_state::AppState;
+use crate::ndjson::{root_string, File, Run, Spec};
+use crate::test_runner::{ExampleResult, ExampleStatus, RunResults};
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Serialize)]
+pub struct ExampleSnapshot {
+    pub id: String,
+    pub location: Option<String>,
+    pub description: Option<String>,
+    /// `running`, `passed` or `failed`
+    pub status: &'static str,
+    pub run_time: Option<f64>,
+    pub exception: Option<String>,
+    pub backtrace: Option<Vec<String>>,
+}
+
+impl From<&ExampleResult> for ExampleSnapshot {
+    fn from(example: &ExampleResult) -> Self {
+        ExampleSnapshot {
+            id: example.id.clone(),
+            location: example.location.clone(),
+            description: example.description.clone(),
+            status: match example.status {
+                ExampleStatus::Running => "running",
+                ExampleStatus::Passed => "passed",
+                ExampleStatus::Failed => "failed",
+            },
+            run_time: example.run_time,
+            exception: example.exception.clone(),
+            backtrace: example.backtrace.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunSnapshot {
+    pub run: Run,
+    pub root: String,
+    pub locations: Vec<String>,
+    pub finished: bool,
+    /// How many examples rspec said it would run, once it has
+    pub count: Option<i64>,
+    pub passed: usize,
+    pub failed: usize,
+    pub errors: Vec<String>,
+    /// In seconds, so far if the run hasn't finished
+    pub duration: f64,
+    pub examples: Vec<ExampleSnapshot>,
+}
+
+impl From<&RunResults> for RunSnapshot {
+    fn from(results: &RunResults) -> Self {
+        RunSnapshot {
+            run: Run::from(&results.run),
+            root: root_string(&results.root),
+            locations: results.locations.clone(),
+            finished: results.finished,
+            count: results.count,
+            passed: results.passed(),
+            failed: results.failed(),
+            errors: results.errors.clone(),
+            duration: results.elapsed().as_secs_f64(),
+            examples: results.examples.iter().map(ExampleSnapshot::from).collect(),
+        }
+    }
+}
+
+/// The app state as returned by the `state` method, in the same terms as
+/// the events.
+#[derive(Debug, Serialize)]
+pub struct StateSnapshot {
+    pub branch: String,
+    pub watching: bool,
+    pub changed_files: Vec<File>,
+    /// Keyed by project name
+    pub selections: BTreeMap<String, Vec<Spec>>,
+    /// The latest run of each project, keyed by project name
+    pub runs: BTreeMap<String, RunSnapshot>,
+    /// Keyed by project name, relative to the project root
+    pub spec_files: BTreeMap<String, Vec<String>>,
+}
+
+impl From<&AppState> for StateSnapshot {
+    fn from(state: &AppState) -> Self {
+        StateSnapshot {
+            branch: state.branch.clone(),
+            watching: state.watching,
+            changed_files: state.changed_files.iter().map(File::from).collect(),
+            selections: state
+                .selections
+                .iter()
+                .map(|(project, matches)| {
+                    (project.clone(), matches.iter().map(Spec::from).collect())
+                })
+                .collect(),
+            runs: state
+                .runs
+                .iter()
+                .map(|(project, results)| (project.clone(), RunSnapshot::from(results)))
+                .collect(),
+            spec_files: state.spec_files.clone(),
+        }
+    }
+}
@@ -1,4 +1,9 @@
+#[allow(dead_code)]
 mod ruby;
+// What the ruby module's tests use
+#[cfg(test)]
+#[allow(dead_code)]
+mod util;
 use ruby::rspec::{RSpec, RSpecConfiguration, RSpecEvent};
 use std::sync::mpsc::channel;
 use std::thread;
@@ -44,12 +49,17 @@ fn main() -> anyhow::Result<()> {
                 description: _,
                 run_time: _,
                 exception: _,
+                backtrace: _,
             } => {
                 println!("Example failed");
             }
+            RSpecEvent::ExampleCoverage { id: _, files: _ } => {}
             RSpecEvent::Stop {} => {
                 println!("Done");
             }
+            RSpecEvent::Cancelled {} => {
+                println!("Cancelled");
+            }
             RSpecEvent::Exit => {
                 println!("Exit");
                 break;
@@ -57,6 +67,9 @@ fn main() -> anyhow::Result<()> {
             RSpecEvent::Error { msg } => {
                 println!("RSpec error {}", msg);
             }
+            RSpecEvent::Output { stream: _, line } => {
+                println!("{}", line);
+            }
         }
     });
 
@@ -0,0 +1,173 @@
+use std::path::{Path, PathBuf};
+
+/// Absolute backtrace paths containing any of these come from Ruby itself or
+/// from the executables of installed gems. rspec shows paths in the project
+/// relative to it, so a project's own `bin/` and `lib/` are never excluded.
+const EXCLUDE: &[&str] = &["/rubygems/", "/lib/ruby/", "/bin/"];
+
+/// Whether a backtrace line is in an installed gem, which bundler and
+/// rubygems put in a directory named for the gem and its version, like
+/// `gems/rspec-core-3.9.2/`, or for a git checkout under `bundler/gems/`.
+/// A monorepo's own `gems/<name>/` has no version, so isn't one.
+fn in_installed_gem(line: &str) -> bool {
+    line.contains("/bundler/gems/")
+        || line.match_indices("gems/").any(|(i, m)| {
+            let at_segment = i == 0 || line[..i].ends_with('/');
+            let rest = &line[i + m.len()..];
+
+            at_segment
+                && rest.find('/').map_or(false, |end| {
+                    let dir = &rest[..end];
+                    dir.rfind('-').map_or(false, |dash| {
+                        dir[dash + 1..].starts_with(|c: char| c.is_ascii_digit())
+                    })
+                })
+        })
+}
+
+fn in_project(line: &str) -> bool {
+    !line.starts_with("<internal:")
+        && !in_installed_gem(line)
+        && !(line.starts_with('/') && EXCLUDE.iter().any(|e| line.contains(e)))
+}
+
+/// A file and line from a backtrace, like
+/// `./spec/models/user_spec.rb:12:in 'block (2 levels)'`, or from an
+/// example's location.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+impl Frame {
+    pub fn parse(line: &str) -> Option<Frame> {
+        let mut parts = line.splitn(3, ':');
+        let path = parts.next().filter(|p| !p.is_empty())?;
+        let line = parts.next()?.parse().ok()?;
+
+        Some(Frame {
+            path: PathBuf::from(path),
+            line,
+        })
+    }
+}
+
+/// The lines of a backtrace that are in the project.
+pub fn filter(backtrace: &[String]) -> Vec<&str> {
+    backtrace
+        .iter()
+        .map(|l| l.as_str())
+        .filter(|l| in_project(l))
+        .collect()
+}
+
+/// Where an example failed: the first frame in its spec file, or else the
+/// first frame in the project.
+pub fn failure_frame(backtrace: &[String], spec_file: Option<&Path>) -> Option<Frame> {
+    let frames: Vec<Frame> = filter(backtrace)
+        .into_iter()
+        .filter_map(Frame::parse)
+        .collect();
+
+    spec_file
+        .and_then(|spec_file| frames.iter().find(|f| f.path == spec_file))
+        .or_else(|| frames.first())
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backtrace(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn filters_out_gem_and_ruby_frames() {
+        let backtrace = backtrace(&[
+            "/usr/local/bundle/gems/rspec-expectations-3.9.2/lib/rspec/expectations/fail_with.rb:37:in `fail_with'",
+            "/home/me/.rubies/ruby-2.7.1/lib/ruby/2.7.0/forwardable.rb:235:in `size'",
+            "/home/me/.gem/ruby/2.7.1/bin/rspec:23:in `load'",
+            "<internal:kernel>:90:in `tap'",
+            "./app/models/user.rb:12:in `name'",
+            "./spec/models/user_spec.rb:8:in `block (2 levels) in <top (required)>'",
+        ]);
+
+        assert_eq!(
+            filter(&backtrace),
+            vec![
+                "./app/models/user.rb:12:in `name'",
+                "./spec/models/user_spec.rb:8:in `block (2 levels) in <top (required)>'",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_a_monorepos_own_gems_and_executables() {
+        let backtrace = backtrace(&[
+            "./vendor/bundle/ruby/2.7.0/gems/rspec-core-3.9.2/lib/rspec/core/example.rb:257:in `run'",
+            "/usr/local/bundle/bundler/gems/rails-0a1b2c3d4e5f/activesupport/lib/active_support.rb:3:in `<top>'",
+            "./gems/billing/lib/billing/invoice.rb:14:in `total'",
+            "./bin/setup:4:in `<main>'",
+            "/home/me/repo/gems/shared-ui/lib/shared_ui.rb:2:in `<top>'",
+            "./spec/invoice_spec.rb:8:in `block (2 levels) in <top (required)>'",
+        ]);
+
+        assert_eq!(
+            filter(&backtrace),
+            vec![
+                "./gems/billing/lib/billing/invoice.rb:14:in `total'",
+                "./bin/setup:4:in `<main>'",
+                "/home/me/repo/gems/shared-ui/lib/shared_ui.rb:2:in `<top>'",
+                "./spec/invoice_spec.rb:8:in `block (2 levels) in <top (required)>'",
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_frames() {
+        assert_eq!(
+            Frame::parse("./spec/a_spec.rb:12:in `block'"),
+            Some(Frame {
+                path: PathBuf::from("./spec/a_spec.rb"),
+                line: 12
+            })
+        );
+        assert_eq!(
+            Frame::parse("./spec/a_spec.rb:3"),
+            Some(Frame {
+                path: PathBuf::from("./spec/a_spec.rb"),
+                line: 3
+            })
+        );
+        assert_eq!(Frame::parse("./spec/a_spec.rb"), None);
+        assert_eq!(Frame::parse(":12:in `block'"), None);
+    }
+
+    #[test]
+    fn failure_frame_prefers_the_spec_file() {
+        let backtrace = backtrace(&[
+            "/gems/rspec-core-3.9.2/lib/rspec/core/example.rb:1:in `run'",
+            "./app/models/user.rb:12:in `name'",
+            "./spec/models/user_spec.rb:8:in `block'",
+        ]);
+        let frame = |spec_file: Option<&str>| {
+            failure_frame(&backtrace, spec_file.map(Path::new)).map(|f| (f.path, f.line))
+        };
+
+        assert_eq!(
+            frame(Some("./spec/models/user_spec.rb")),
+            Some((PathBuf::from("./spec/models/user_spec.rb"), 8))
+        );
+        assert_eq!(
+            frame(Some("./spec/other_spec.rb")),
+            Some((PathBuf::from("./app/models/user.rb"), 12))
+        );
+        assert_eq!(
+            frame(None),
+            Some((PathBuf::from("./app/models/user.rb"), 12))
+        );
+    }
+}
@@ -1 +1,5 @@
+pub mod backtrace;
+pub mod require_graph;
 pub mod rspec;
+pub mod spec_support;
+pub mod zeitwerk;
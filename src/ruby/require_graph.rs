@@ -0,0 +1,351 @@
+use regex::Regex;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Directories searched, in order, when resolving a plain `require`. These
+/// are the directories rspec and bundler put on the load path for a project.
+const LOAD_PATHS: &[&str] = &["lib", "spec", "test", "app", ""];
+
+/// Resolve `.` and `..` without touching the filesystem, so paths stay
+/// relative to the project root.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+
+    normalized
+}
+
+fn with_rb_extension(path: &str) -> String {
+    if path.ends_with(".rb") {
+        path.to_owned()
+    } else {
+        format!("{}.rb", path)
+    }
+}
+
+/// A graph of which Ruby files require which, built from `require`,
+/// `require_relative` and `load` statements. All paths are relative to the
+/// project root. Requires that can't be resolved to a file in the project,
+/// such as gems, are left out, but kept to retry when files are added.
+pub struct RequireGraph {
+    pattern: Regex,
+    requires: HashMap<PathBuf, BTreeSet<PathBuf>>,
+    required_by: HashMap<PathBuf, BTreeSet<PathBuf>>,
+    /// The method and argument of each require that didn't resolve, by the
+    /// file making it
+    unresolved: HashMap<PathBuf, BTreeSet<(String, String)>>,
+}
+
+impl RequireGraph {
+    pub fn new() -> Self {
+        RequireGraph {
+            pattern: Regex::new(
+                r##"(?m)^\s*(require_relative|require|load)[\s(]+['"]([^'"#]+)['"]"##,
+            )
+            .unwrap(),
+            requires: HashMap::new(),
+            required_by: HashMap::new(),
+            unresolved: HashMap::new(),
+        }
+    }
+
+    pub fn build<P: AsRef<Path>>(root: &Path, files: &[P]) -> Self {
+        let mut graph = RequireGraph::new();
+
+        for file in files.iter() {
+            graph.rescan(root, file.as_ref());
+        }
+
+        graph
+    }
+
+    fn resolve(&self, root: &Path, from: &Path, method: &str, required: &str) -> Option<PathBuf> {
+        let candidates: Vec<PathBuf> = match method {
+            "require_relative" => {
+                let dir = from.parent().unwrap_or_else(|| Path::new(""));
+                vec![dir.join(with_rb_extension(required))]
+            }
+            "load" => vec![PathBuf::from(required)],
+            _ => LOAD_PATHS
+                .iter()
+                .map(|load_path| Path::new(load_path).join(with_rb_extension(required)))
+                .collect(),
+        };
+
+        candidates
+            .into_iter()
+            .map(|c| normalize(&c))
+            .find(|c| root.join(c).is_file())
+    }
+
+    /// The files `path` requires, and the requires that didn't resolve.
+    fn scan(
+        &self,
+        root: &Path,
+        path: &Path,
+        source: &str,
+    ) -> (BTreeSet<PathBuf>, BTreeSet<(String, String)>) {
+        let mut requires = BTreeSet::new();
+        let mut unresolved = BTreeSet::new();
+
+        for caps in self.pattern.captures_iter(source) {
+            match self.resolve(root, path, &caps[1], &caps[2]) {
+                Some(required) if required == path => {}
+                Some(required) => {
+                    requires.insert(required);
+                }
+                None => {
+                    unresolved.insert((caps[1].to_owned(), caps[2].to_owned()));
+                }
+            }
+        }
+
+        (requires, unresolved)
+    }
+
+    /// Rescan a single file, replacing its edges in the graph. A file that
+    /// no longer exists is removed. A file that is new is linked to the
+    /// files whose requires it now satisfies.
+    pub fn update_file(&mut self, root: &Path, path: &Path) {
+        let added = !self.requires.contains_key(path);
+        self.rescan(root, path);
+
+        if added && self.requires.contains_key(path) {
+            for waiting in self.waiting_for(root, path) {
+                self.rescan(root, &waiting);
+            }
+        }
+    }
+
+    /// The files with a require that didn't resolve before but resolves to
+    /// `path` now.
+    fn waiting_for(&self, root: &Path, path: &Path) -> Vec<PathBuf> {
+        self.unresolved
+            .iter()
+            .filter(|(from, unresolved)| {
+                unresolved.iter().any(|(method, required)| {
+                    // Only worth checking the filesystem when the names match
+                    Path::new(&with_rb_extension(required)).file_name() == path.file_name()
+                        && self.resolve(root, from, method, required).as_deref() == Some(path)
+                })
+            })
+            .map(|(from, _)| from.clone())
+            .collect()
+    }
+
+    fn rescan(&mut self, root: &Path, path: &Path) {
+        self.unresolved.remove(path);
+        if let Some(old) = self.requires.remove(path) {
+            for required in old.iter() {
+                if let Some(by) = self.required_by.get_mut(required) {
+                    by.remove(path);
+                }
+            }
+        }
+
+        let source = match fs::read_to_string(root.join(path)) {
+            Ok(source) => source,
+            Err(_) => return,
+        };
+
+        let (requires, unresolved) = self.scan(root, path, &source);
+        if !unresolved.is_empty() {
+            self.unresolved.insert(path.to_path_buf(), unresolved);
+        }
+
+        for required in requires.iter() {
+            self.required_by
+                .entry(required.clone())
+                .or_default()
+                .insert(path.to_path_buf());
+        }
+
+        self.requires.insert(path.to_path_buf(), requires);
+    }
+
+    /// Every file that requires `path`, directly or through other files,
+    /// with the number of requires between them.
+    pub fn dependents(&self, path: &Path) -> Vec<(PathBuf, usize)> {
+        let mut seen: BTreeSet<&Path> = BTreeSet::new();
+        let mut queue: VecDeque<(&Path, usize)> = VecDeque::new();
+        let mut dependents = vec![];
+
+        seen.insert(path);
+        queue.push_back((path, 0));
+
+        while let Some((current, depth)) = queue.pop_front() {
+            if let Some(by) = self.required_by.get(current) {
+                for dependent in by.iter() {
+                    if seen.insert(dependent) {
+                        dependents.push((dependent.clone(), depth + 1));
+                        queue.push_back((dependent, depth + 1));
+                    }
+                }
+            }
+        }
+
+        dependents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_dir::TestDir;
+
+    fn graph(dir: &TestDir) -> RequireGraph {
+        let files = crate::util::source_files::find_files(&dir.path, "rb");
+        RequireGraph::build(&dir.path, &files)
+    }
+
+    fn requires(graph: &RequireGraph, path: &str) -> Vec<String> {
+        graph
+            .requires
+            .get(Path::new(path))
+            .into_iter()
+            .flatten()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn require_searches_the_load_paths() {
+        let dir = TestDir::new("require_graph-require");
+        dir.write("lib/user.rb", "")
+            .write("spec/spec_helper.rb", "")
+            .write(
+                "spec/user_spec.rb",
+                "require 'spec_helper'\nrequire(\"user.rb\")\nrequire 'rails'\n",
+            );
+
+        assert_eq!(
+            requires(&graph(&dir), "spec/user_spec.rb"),
+            vec!["lib/user.rb", "spec/spec_helper.rb"]
+        );
+    }
+
+    #[test]
+    fn require_relative_is_from_the_requiring_file() {
+        let dir = TestDir::new("require_graph-relative");
+        dir.write("app/models/user.rb", "")
+            .write("spec/models/user.rb", "")
+            .write(
+                "spec/models/user_spec.rb",
+                "require_relative '../../app/models/user'\nrequire_relative 'user'\n",
+            );
+
+        assert_eq!(
+            requires(&graph(&dir), "spec/models/user_spec.rb"),
+            vec!["app/models/user.rb", "spec/models/user.rb"]
+        );
+    }
+
+    #[test]
+    fn load_is_from_the_root_as_given() {
+        let dir = TestDir::new("require_graph-load");
+        dir.write("config/setup.rb", "")
+            .write("lib/setup.rb", "")
+            .write(
+                "spec/setup_spec.rb",
+                "load 'config/setup.rb'\nload 'lib/setup'\n",
+            );
+
+        assert_eq!(
+            requires(&graph(&dir), "spec/setup_spec.rb"),
+            vec!["config/setup.rb"]
+        );
+    }
+
+    #[test]
+    fn dependents_are_found_transitively() {
+        let dir = TestDir::new("require_graph-transitive");
+        dir.write("lib/a.rb", "")
+            .write("lib/b.rb", "require 'a'\n")
+            .write("spec/b_spec.rb", "require 'b'\n");
+
+        assert_eq!(
+            graph(&dir).dependents(Path::new("lib/a.rb")),
+            vec![
+                (PathBuf::from("lib/b.rb"), 1),
+                (PathBuf::from("spec/b_spec.rb"), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn cycles_are_followed_once() {
+        let dir = TestDir::new("require_graph-cycle");
+        dir.write("lib/a.rb", "require 'b'\n")
+            .write("lib/b.rb", "require 'a'\n")
+            .write("spec/a_spec.rb", "require 'a'\n");
+
+        assert_eq!(
+            graph(&dir).dependents(Path::new("lib/a.rb")),
+            vec![
+                (PathBuf::from("lib/b.rb"), 1),
+                (PathBuf::from("spec/a_spec.rb"), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn rescanning_a_file_replaces_its_edges() {
+        let dir = TestDir::new("require_graph-update");
+        dir.write("lib/a.rb", "")
+            .write("lib/b.rb", "")
+            .write("spec/a_spec.rb", "require 'a'\n");
+        let mut graph = graph(&dir);
+        let spec = Path::new("spec/a_spec.rb");
+
+        dir.write("spec/a_spec.rb", "require 'b'\n");
+        graph.update_file(&dir.path, spec);
+        assert!(graph.dependents(Path::new("lib/a.rb")).is_empty());
+        assert_eq!(
+            graph.dependents(Path::new("lib/b.rb")),
+            vec![(spec.to_path_buf(), 1)]
+        );
+
+        dir.remove("spec/a_spec.rb");
+        graph.update_file(&dir.path, spec);
+        assert!(graph.dependents(Path::new("lib/b.rb")).is_empty());
+    }
+
+    #[test]
+    fn added_files_are_linked_to_requires_waiting_for_them() {
+        let dir = TestDir::new("require_graph-added");
+        dir.write("lib/b.rb", "require 'a'\nrequire 'rails'\n")
+            .write("spec/b_spec.rb", "require 'b'\n")
+            .write("spec/c_spec.rb", "require_relative '../lib/a'\n");
+        let mut graph = graph(&dir);
+        assert!(graph.dependents(Path::new("lib/a.rb")).is_empty());
+
+        dir.write("lib/a.rb", "");
+        graph.update_file(&dir.path, Path::new("lib/a.rb"));
+
+        assert_eq!(
+            graph.dependents(Path::new("lib/a.rb")),
+            vec![
+                (PathBuf::from("lib/b.rb"), 1),
+                (PathBuf::from("spec/c_spec.rb"), 1),
+                (PathBuf::from("spec/b_spec.rb"), 2),
+            ]
+        );
+        assert_eq!(
+            graph.unresolved[Path::new("lib/b.rb")],
+            vec![(String::from("require"), String::from("rails"))]
+                .into_iter()
+                .collect()
+        );
+        assert!(!graph.unresolved.contains_key(Path::new("spec/c_spec.rb")));
+    }
+}
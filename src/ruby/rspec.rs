@@ -1,6 +1,11 @@
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader};
-use std::process::{Command, Stdio};
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
 
 use serde::{Deserialize, Serialize};
@@ -30,15 +35,78 @@ pub enum RSpecEvent {
         description: Option<String>,
         run_time: f64,
         exception: Option<String>,
+        backtrace: Option<Vec<String>>,
+    },
+    ExampleCoverage {
+        id: String,
+        files: Vec<String>,
     },
     Stop {},
+    /// The run was killed, as when cancelled, so it didn't finish
+    Cancelled {},
     Error {
         msg: String,
     },
+    /// A line rspec wrote that isn't from the formatter, such as `puts`
+    /// output, a deprecation warning or an error while loading specs
+    Output {
+        stream: OutputStream,
+        line: String,
+    },
     Exit,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// The formatter writes each event as a JSON object starting like this
+const EVENT_START: &str = r#"{"type":"#;
+
+/// The events in a line of rspec's stdout. Anything that isn't an event is
+/// output, including text printed without a newline just before one.
+fn parse_stdout_line(line: &str) -> Vec<RSpecEvent> {
+    let output = |line: &str| RSpecEvent::Output {
+        stream: OutputStream::Stdout,
+        line: line.to_owned(),
+    };
+
+    if let Some(start) = line.find(EVENT_START) {
+        if let Ok(event) = serde_json::from_str::<RSpecEvent>(&line[start..]) {
+            return match &line[..start] {
+                "" => vec![event],
+                before => vec![output(before), event],
+            };
+        }
+    }
+
+    vec![output(line)]
+}
+
+/// Read lines until the end of the stream, replacing anything that isn't
+/// UTF-8 rather than giving up on it.
+fn read_lines<R: Read, F: FnMut(&str) -> bool>(reader: R, mut on_line: F) -> std::io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut buf = vec![];
+
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(());
+        }
+
+        let line = String::from_utf8_lossy(&buf);
+        if !on_line(line.trim_end_matches(|c| c == '\n' || c == '\r')) {
+            return Ok(());
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RSpecConfiguration {
     pub path_to_rspec: String,
     pub use_bundler: bool,
@@ -55,9 +123,78 @@ impl Default for RSpecConfiguration {
     }
 }
 
+const FORMATTER: &str = include_str!("../../test/rust_rspec_formatter.rb");
+
+/// A directory in the temp dir that only this user can write to, checked
+/// each time since anyone could have created it first.
+fn private_temp_dir() -> io::Result<PathBuf> {
+    let uid = unsafe { libc::getuid() };
+    let dir = std::env::temp_dir().join(format!("spec_detect-{}", uid));
+
+    match DirBuilder::new().mode(0o700).create(&dir) {
+        Err(err) if err.kind() != ErrorKind::AlreadyExists => return Err(err),
+        _ => {}
+    }
+
+    let metadata = fs::symlink_metadata(&dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!("{} is not private to this user", dir.display()),
+        ));
+    }
+
+    Ok(dir)
+}
+
+/// Starts `Coverage` before rspec loads anything, through `RUBYOPT`
+const COVERAGE: &str = include_str!("../../test/rust_rspec_coverage.rb");
+
+/// Tells the scripts written for concurrent runs apart
+static SCRIPTS_WRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+/// An embedded script written out for one run so rspec can require it from
+/// whatever directory it is run in, removed once the run is over.
+struct Script {
+    path: PathBuf,
+}
+
+impl Script {
+    /// rspec runs whatever the file holds, so it is created afresh rather
+    /// than trusting a file left at the same path.
+    fn write(name: &str, source: &str) -> io::Result<Self> {
+        let path = private_temp_dir()?.join(format!(
+            "{}_{}_{}.rb",
+            name,
+            std::process::id(),
+            SCRIPTS_WRITTEN.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?
+            .write_all(source.as_bytes())?;
+
+        Ok(Script { path })
+    }
+}
+
+impl Drop for Script {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
 pub struct RSpecRun {
     handle: std::thread::JoinHandle<()>,
-    cmd: std::process::Child,
+    cmd: Arc<Mutex<Child>>,
+    killed: Arc<AtomicBool>,
 }
 
 impl RSpecRun {
@@ -67,18 +204,49 @@ impl RSpecRun {
             .map_err(|_e| anyhow::Error::msg("rspec wait error"))
     }
 
-    pub fn kill(&mut self) -> anyhow::Result<()> {
-        self.cmd.kill().map_err(anyhow::Error::from)
+    pub fn kill(&self) -> anyhow::Result<()> {
+        self.killed.store(true, Ordering::SeqCst);
+        self.cmd.lock().unwrap().kill().map_err(anyhow::Error::from)
     }
 }
 
 pub struct RSpec {
     config: RSpecConfiguration,
+    working_dir: Option<PathBuf>,
+    coverage: bool,
+    dry_run: bool,
 }
 
 impl RSpec {
     pub fn new(config: RSpecConfiguration) -> Self {
-        RSpec { config }
+        RSpec {
+            config,
+            working_dir: None,
+            coverage: false,
+            dry_run: false,
+        }
+    }
+
+    /// Run rspec from `dir` rather than the current directory. Locations are
+    /// then relative to `dir`.
+    pub fn working_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.working_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Have the formatter report the files each example executes, as
+    /// `ExampleCoverage` events. Coverage starts through `RUBYOPT`, before
+    /// rspec and `.rspec`'s requires, but files loaded before that, such as
+    /// by `bundler/setup`, are never measured.
+    pub fn collect_coverage(mut self, coverage: bool) -> Self {
+        self.coverage = coverage;
+        self
+    }
+
+    /// Load the specs and report their examples without running them.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
     }
 
     pub fn run<T: AsRef<str>>(
@@ -102,66 +270,213 @@ impl RSpec {
             args.push(&config.path_to_rspec);
         }
 
+        let formatter_script = Script::write("formatter", FORMATTER)?;
+        let formatter = formatter_script.path.to_string_lossy();
+
         args.push("--format");
         args.push("RustRspecFormatter");
         args.push("--require");
-        args.push("./test/rust_rspec_formatter.rb");
+        args.push(&formatter);
+
+        if self.dry_run {
+            args.push("--dry-run");
+        }
 
         let args_with_locations: Vec<&&str> = args.iter().chain(ref_locations.iter()).collect();
-        dbg!(args_with_locations.clone());
 
-        let mut cmd = Command::new(program)
+        let mut command = Command::new(program);
+        command
             .args(args_with_locations)
             .envs(&config.env)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .spawn()
-            .unwrap();
+            .stderr(Stdio::piped());
+
+        let mut scripts = vec![formatter_script];
+        if self.coverage {
+            let coverage = Script::write("coverage", COVERAGE)?;
+            // Ruby splits RUBYOPT on whitespace, so a path with any in it is
+            // left to the formatter to start coverage, later
+            if !coverage
+                .path
+                .to_string_lossy()
+                .contains(char::is_whitespace)
+            {
+                let rubyopt = config
+                    .env
+                    .get("RUBYOPT")
+                    .cloned()
+                    .or_else(|| std::env::var("RUBYOPT").ok())
+                    .unwrap_or_default();
+                command.env(
+                    "RUBYOPT",
+                    format!("{} -r{}", rubyopt, coverage.path.display()).trim_start(),
+                );
+            }
+            command.env("SPEC_DETECT_COVERAGE", "1");
+            scripts.push(coverage);
+        }
+
+        if let Some(dir) = &self.working_dir {
+            if !dir.as_os_str().is_empty() {
+                command.current_dir(dir);
+            }
+        }
+
+        let mut cmd = command.spawn().map_err(|err| match err.kind() {
+            ErrorKind::NotFound => anyhow::anyhow!(
+                "Could not find {} to run rspec with, check `path_to_rspec` and `use_bundler` in [rspec]",
+                program
+            ),
+            _ => anyhow::anyhow!("Could not run {}: {}", program, err),
+        })?;
 
         let stdout = cmd.stdout.take().unwrap();
+        let stderr = cmd.stderr.take().unwrap();
+        let cmd = Arc::new(Mutex::new(cmd));
+        let reap_cmd = Arc::clone(&cmd);
+        let killed = Arc::new(AtomicBool::new(false));
+        let reap_killed = Arc::clone(&killed);
+
+        let stderr_tx = tx.clone();
+        let stderr_handle = thread::spawn(move || {
+            read_lines(stderr, |line| {
+                stderr_tx
+                    .send(RSpecEvent::Output {
+                        stream: OutputStream::Stderr,
+                        line: line.to_owned(),
+                    })
+                    .is_ok()
+            })
+        });
+
         #[allow(unused_must_use)]
         let handle = thread::spawn(move || {
-            let mut stdout_reader = BufReader::with_capacity(10, stdout);
-
-            loop {
-                let mut buf = String::new();
-                let line = stdout_reader.read_line(&mut buf);
-
-                match line {
-                    Err(err) => {
-                        tx.send(RSpecEvent::Error {
-                            msg: err.to_string(),
-                        });
-                        break;
-                    }
-                    Ok(usize) if usize == 0 => {
-                        break;
-                    }
-                    Ok(_usize) => {
-                        let deser = serde_json::from_str::<RSpecEvent>(&buf);
-
-                        if deser.is_err() {
-                            let err = deser.err().unwrap();
-                            tx.send(RSpecEvent::Error {
-                                msg: err.to_string(),
-                            });
-                            break;
-                        }
-
-                        let event = deser.unwrap();
-                        let res = tx.send(event);
-
-                        if res.is_err() {
-                            break;
-                        }
-                    }
-                }
+            let mut failed = false;
+            let read = read_lines(stdout, |line| {
+                parse_stdout_line(line).into_iter().all(|event| {
+                    failed |= matches!(event, RSpecEvent::ExampleFailed { .. });
+                    tx.send(event).is_ok()
+                })
+            });
+
+            if let Err(err) = read {
+                tx.send(RSpecEvent::Error {
+                    msg: err.to_string(),
+                });
             }
 
+            stderr_handle.join();
+            // rspec exits unsuccessfully when examples fail or it is killed,
+            // but otherwise it means something went wrong outside of them,
+            // like a spec file that couldn't be loaded
+            let status = reap_cmd.lock().unwrap().wait();
+            let killed = reap_killed.load(Ordering::SeqCst);
+            match status {
+                Ok(_) if killed => {
+                    tx.send(RSpecEvent::Cancelled {});
+                }
+                Ok(status) if !status.success() && !failed => {
+                    tx.send(RSpecEvent::Error {
+                        msg: format!("rspec exited unsuccessfully ({})", status),
+                    });
+                }
+                Err(err) => {
+                    tx.send(RSpecEvent::Error {
+                        msg: err.to_string(),
+                    });
+                }
+                _ => {}
+            }
+            drop(scripts);
             tx.send(RSpecEvent::Exit);
             drop(tx);
         });
 
-        Ok(RSpecRun { handle, cmd })
+        Ok(RSpecRun {
+            handle,
+            cmd,
+            killed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stdout(line: &str) -> RSpecEvent {
+        RSpecEvent::Output {
+            stream: OutputStream::Stdout,
+            line: line.to_owned(),
+        }
+    }
+
+    fn lines(input: &[u8]) -> Vec<String> {
+        let mut lines = vec![];
+        read_lines(input, |line| {
+            lines.push(line.to_owned());
+            true
+        })
+        .unwrap();
+        lines
+    }
+
+    #[test]
+    fn parses_formatter_events() {
+        assert_eq!(
+            parse_stdout_line(r#"{"type":"start","count":3}"#),
+            vec![RSpecEvent::Start { count: Some(3) }]
+        );
+        assert_eq!(
+            parse_stdout_line(r#"{"type":"stop"}"#),
+            vec![RSpecEvent::Stop {}]
+        );
+    }
+
+    #[test]
+    fn output_before_an_event_is_split_off() {
+        assert_eq!(
+            parse_stdout_line(r#"loading...{"type":"stop"}"#),
+            vec![stdout("loading..."), RSpecEvent::Stop {}]
+        );
+    }
+
+    #[test]
+    fn anything_else_is_output() {
+        assert_eq!(parse_stdout_line("hello"), vec![stdout("hello")]);
+        assert_eq!(
+            parse_stdout_line(r#"{"type":"unknown_event"}"#),
+            vec![stdout(r#"{"type":"unknown_event"}"#)]
+        );
+        assert_eq!(
+            parse_stdout_line(r#"{"type":"start""#),
+            vec![stdout(r#"{"type":"start""#)]
+        );
+    }
+
+    #[test]
+    fn reads_lines_without_their_endings() {
+        assert_eq!(
+            lines(b"one\r\ntwo\n\nthree"),
+            vec!["one", "two", "", "three"]
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        assert_eq!(lines(b"caf\xe9\nok\n"), vec!["caf\u{fffd}", "ok"]);
+    }
+
+    #[test]
+    fn reading_stops_when_asked() {
+        let mut seen = vec![];
+        read_lines(&b"one\ntwo\nthree\n"[..], |line| {
+            seen.push(line.to_owned());
+            line != "two"
+        })
+        .unwrap();
+
+        assert_eq!(seen, vec!["one", "two"]);
     }
 }
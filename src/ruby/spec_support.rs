@@ -0,0 +1,360 @@
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+
+/// Something defined in a factory, fixture or support file that specs can
+/// use by name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Definition {
+    Factory(String),
+    FixtureSet(String),
+    FileFixture(String),
+    SharedGroup(String),
+    Constant(String),
+}
+
+impl Definition {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Definition::Factory(_) => "factory",
+            Definition::FixtureSet(_) => "fixtures",
+            Definition::FileFixture(_) => "file fixture",
+            Definition::SharedGroup(_) => "shared group",
+            Definition::Constant(_) => "constant",
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Definition::Factory(name)
+            | Definition::FixtureSet(name)
+            | Definition::FileFixture(name)
+            | Definition::SharedGroup(name)
+            | Definition::Constant(name) => name,
+        }
+    }
+}
+
+/// Methods that take a single symbol like a fixture accessor, such as
+/// `users(:one)`, but are RSpec's or FactoryBot's own.
+const NOT_FIXTURE_ACCESSORS: &[&str] = &[
+    "let",
+    "subject",
+    "receive",
+    "have_received",
+    "respond_to",
+    "create",
+    "build",
+    "build_stubbed",
+    "attributes_for",
+    "create_list",
+    "build_list",
+    "create_pair",
+    "build_pair",
+];
+
+/// The names a spec file uses from factories, fixtures and support files.
+#[derive(Debug, Default)]
+pub struct SpecUsage {
+    factories: BTreeSet<String>,
+    /// Sets named by `fixtures`
+    fixture_sets: BTreeSet<String>,
+    all_fixtures: bool,
+    /// Methods called with a single symbol, which are the accessors of any
+    /// fixture sets they are named after
+    fixture_accessors: BTreeSet<String>,
+    strings: BTreeSet<String>,
+    shared_groups: BTreeSet<String>,
+    constants: BTreeSet<String>,
+}
+
+impl SpecUsage {
+    pub fn uses(&self, definition: &Definition) -> bool {
+        match definition {
+            Definition::Factory(name) => self.factories.contains(name),
+            Definition::FixtureSet(name) => {
+                self.all_fixtures
+                    || self.fixture_sets.contains(name)
+                    || self.fixture_accessors.contains(&name.replace('/', "_"))
+            }
+            Definition::FileFixture(name) => self.strings.iter().any(|s| {
+                s.strip_suffix(name.as_str())
+                    .map_or(false, |dir| dir.is_empty() || dir.ends_with('/'))
+            }),
+            Definition::SharedGroup(name) => self.shared_groups.contains(name),
+            Definition::Constant(name) => self.constants.contains(name),
+        }
+    }
+}
+
+/// Where a file sits within the spec directory, going by the conventional
+/// `spec/factories`, `spec/fixtures` and `spec/support` layout (or `test/`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SupportKind {
+    Factories,
+    Fixtures,
+    Support,
+}
+
+pub fn support_kind(path: &Path) -> Option<SupportKind> {
+    let mut components = path.components().filter_map(|c| match c {
+        Component::Normal(s) => s.to_str(),
+        _ => None,
+    });
+
+    match (components.next(), components.next()) {
+        (Some("spec"), Some(dir)) | (Some("test"), Some(dir)) => match dir {
+            "factories" => Some(SupportKind::Factories),
+            "fixtures" => Some(SupportKind::Fixtures),
+            "support" => Some(SupportKind::Support),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub struct SupportScanner {
+    factory_definition: Regex,
+    factory_usage: Regex,
+    fixtures_usage: Regex,
+    fixture_accessor: Regex,
+    shared_definition: Regex,
+    shared_usage: Regex,
+    constant_definition: Regex,
+    constant_usage: Regex,
+    string: Regex,
+}
+
+impl SupportScanner {
+    pub fn new() -> Self {
+        SupportScanner {
+            factory_definition: Regex::new(r"\bfactory[\s(]+:(\w+)").unwrap(),
+            factory_usage: Regex::new(
+                r"\b(?:create|build|build_stubbed|attributes_for|create_list|build_list|create_pair|build_pair)[\s(]+:(\w+)",
+            )
+            .unwrap(),
+            fixtures_usage: Regex::new(r"(?m)^\s*fixtures\s+(.+)$").unwrap(),
+            fixture_accessor: Regex::new(r"\b(\w+)\(:\w+\)").unwrap(),
+            shared_definition: Regex::new(
+                r#"\b(?:shared_examples_for|shared_examples|shared_context)[\s(]+['"]([^'"]+)['"]"#,
+            )
+            .unwrap(),
+            shared_usage: Regex::new(
+                r#"\b(?:it_behaves_like|it_should_behave_like|include_examples|include_context)[\s(]+['"]([^'"]+)['"]"#,
+            )
+            .unwrap(),
+            constant_definition: Regex::new(r"(?m)^\s*(?:module|class)\s+([A-Z][\w:]*)").unwrap(),
+            constant_usage: Regex::new(r"\b[A-Z][A-Za-z0-9_]*(?:::[A-Z][A-Za-z0-9_]*)*").unwrap(),
+            string: Regex::new(r#"['"]([^'"\s]+\.\w+)['"]"#).unwrap(),
+        }
+    }
+
+    fn captures(regex: &Regex, source: &str) -> BTreeSet<String> {
+        regex
+            .captures_iter(source)
+            .map(|caps| caps[1].to_owned())
+            .collect()
+    }
+
+    pub fn usage(&self, source: &str) -> SpecUsage {
+        let mut usage = SpecUsage {
+            factories: Self::captures(&self.factory_usage, source),
+            fixture_accessors: Self::captures(&self.fixture_accessor, source)
+                .into_iter()
+                .filter(|name| !NOT_FIXTURE_ACCESSORS.contains(&name.as_str()))
+                .collect(),
+            strings: Self::captures(&self.string, source),
+            shared_groups: Self::captures(&self.shared_usage, source),
+            constants: self
+                .constant_usage
+                .find_iter(source)
+                .map(|m| m.as_str().to_owned())
+                .collect(),
+            ..SpecUsage::default()
+        };
+
+        for caps in self.fixtures_usage.captures_iter(source) {
+            for name in caps[1].split(',').map(|n| n.trim().trim_start_matches(':')) {
+                if name == "all" {
+                    usage.all_fixtures = true;
+                } else {
+                    usage.fixture_sets.insert(name.to_owned());
+                }
+            }
+        }
+
+        usage
+    }
+
+    /// What a changed support file defines. `path` is relative to the
+    /// project root and `source` is its current content.
+    pub fn definitions(&self, path: &Path, source: &str) -> Vec<Definition> {
+        match support_kind(path) {
+            Some(SupportKind::Factories) => Self::captures(&self.factory_definition, source)
+                .into_iter()
+                .map(Definition::Factory)
+                .collect(),
+            Some(SupportKind::Fixtures) => {
+                let fixture: PathBuf = path.iter().skip(2).collect();
+
+                let definition = if fixture.extension().map_or(false, |e| e == "yml") {
+                    Definition::FixtureSet(
+                        fixture.with_extension("").to_string_lossy().into_owned(),
+                    )
+                } else {
+                    // File fixtures are looked up relative to `fixtures/files`
+                    let name = fixture.strip_prefix("files").unwrap_or(&fixture);
+                    Definition::FileFixture(name.to_string_lossy().into_owned())
+                };
+
+                vec![definition]
+            }
+            Some(SupportKind::Support) => Self::captures(&self.shared_definition, source)
+                .into_iter()
+                .map(Definition::SharedGroup)
+                .chain(
+                    Self::captures(&self.constant_definition, source)
+                        .into_iter()
+                        .map(Definition::Constant),
+                )
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definitions(path: &str, source: &str) -> Vec<Definition> {
+        SupportScanner::new().definitions(Path::new(path), source)
+    }
+
+    fn usage(source: &str) -> SpecUsage {
+        SupportScanner::new().usage(source)
+    }
+
+    #[test]
+    fn finds_support_directories() {
+        assert_eq!(
+            support_kind(Path::new("spec/factories/users.rb")),
+            Some(SupportKind::Factories)
+        );
+        assert_eq!(
+            support_kind(Path::new("test/fixtures/users.yml")),
+            Some(SupportKind::Fixtures)
+        );
+        assert_eq!(
+            support_kind(Path::new("./spec/support/helpers.rb")),
+            Some(SupportKind::Support)
+        );
+        assert_eq!(support_kind(Path::new("spec/models/user_spec.rb")), None);
+        assert_eq!(support_kind(Path::new("app/support/user.rb")), None);
+    }
+
+    #[test]
+    fn factories() {
+        assert_eq!(
+            definitions(
+                "spec/factories/users.rb",
+                "FactoryBot.define do\n  factory :user do\n  end\n  factory(:admin_user, class: 'User')\nend\n"
+            ),
+            vec![
+                Definition::Factory(String::from("admin_user")),
+                Definition::Factory(String::from("user"))
+            ]
+        );
+
+        let spec = usage("let(:user) { create(:user) }\nlet(:posts) { build_list :post, 3 }\n");
+        assert!(spec.uses(&Definition::Factory(String::from("user"))));
+        assert!(spec.uses(&Definition::Factory(String::from("post"))));
+        assert!(!spec.uses(&Definition::Factory(String::from("admin_user"))));
+
+        let admin = usage("let(:admin) { build_stubbed(:admin_user) }");
+        assert!(!admin.uses(&Definition::Factory(String::from("admin"))));
+        assert!(!admin.uses(&Definition::Factory(String::from("user"))));
+    }
+
+    #[test]
+    fn fixture_sets() {
+        assert_eq!(
+            definitions("spec/fixtures/users.yml", "one:\n  name: One\n"),
+            vec![Definition::FixtureSet(String::from("users"))]
+        );
+        assert_eq!(
+            definitions("spec/fixtures/admin/roles.yml", ""),
+            vec![Definition::FixtureSet(String::from("admin/roles"))]
+        );
+
+        let users = Definition::FixtureSet(String::from("users"));
+        let roles = Definition::FixtureSet(String::from("roles"));
+
+        let declared = usage("  fixtures :users, :posts\n");
+        assert!(declared.uses(&users));
+        assert!(!declared.uses(&roles));
+
+        assert!(usage("let(:user) { users(:one) }").uses(&users));
+        assert!(usage("  fixtures :all\n").uses(&roles));
+        assert!(
+            usage("admin_roles(:owner)").uses(&Definition::FixtureSet(String::from("admin/roles")))
+        );
+    }
+
+    #[test]
+    fn rspec_and_factory_methods_arent_fixture_accessors() {
+        let spec = usage("let(:user) { create(:post) }\nsubject(:page) { build(:x) }\n");
+
+        for name in ["let", "create", "subject", "build"].iter() {
+            assert!(!spec.uses(&Definition::FixtureSet(name.to_string())));
+        }
+    }
+
+    #[test]
+    fn file_fixtures() {
+        let avatar = definitions("spec/fixtures/files/avatar.png", "");
+        assert_eq!(
+            avatar,
+            vec![Definition::FileFixture(String::from("avatar.png"))]
+        );
+
+        assert!(usage("file_fixture('avatar.png')").uses(&avatar[0]));
+        assert!(usage("fixture_file_upload('files/avatar.png')").uses(&avatar[0]));
+        assert!(!usage("file_fixture('banner.png')").uses(&avatar[0]));
+        assert!(!usage("file_fixture('big_avatar.png')").uses(&avatar[0]));
+    }
+
+    #[test]
+    fn shared_groups() {
+        assert_eq!(
+            definitions(
+                "spec/support/shared.rb",
+                "RSpec.shared_examples \"a model\" do\nend\nshared_context('signed in') do\nend\n"
+            ),
+            vec![
+                Definition::SharedGroup(String::from("a model")),
+                Definition::SharedGroup(String::from("signed in"))
+            ]
+        );
+
+        let model = Definition::SharedGroup(String::from("a model"));
+        assert!(usage("it_behaves_like \"a model\"").uses(&model));
+        assert!(usage("include_examples('a model')").uses(&model));
+        assert!(!usage("it_behaves_like 'a model with tags'").uses(&model));
+    }
+
+    #[test]
+    fn support_constants() {
+        let helpers = definitions(
+            "spec/support/auth_helpers.rb",
+            "module AuthHelpers\n  def sign_in; end\nend\n",
+        );
+        assert_eq!(
+            helpers,
+            vec![Definition::Constant(String::from("AuthHelpers"))]
+        );
+
+        assert!(usage("config.include AuthHelpers").uses(&helpers[0]));
+        assert!(!usage("config.include AuthHelpersExtra").uses(&helpers[0]));
+    }
+}
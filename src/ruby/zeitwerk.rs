@@ -0,0 +1,277 @@
+use regex::Regex;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// Directories under `app/` that Rails doesn't autoload.
+const NON_AUTOLOAD_DIRS: &[&str] = &["assets", "javascript", "views"];
+
+/// Maps file paths to constant names the way Zeitwerk does, so that
+/// `app/models/admin/user.rb` defines `Admin::User`. `inflections` maps a
+/// file name segment to its constant name where simple camelizing gets it
+/// wrong, e.g. `"api" = "API"`.
+pub struct Inflector {
+    inflections: HashMap<String, String>,
+}
+
+impl Inflector {
+    pub fn new(inflections: HashMap<String, String>) -> Self {
+        Inflector { inflections }
+    }
+
+    pub fn camelize(&self, segment: &str) -> String {
+        if let Some(inflected) = self.inflections.get(segment) {
+            return inflected.clone();
+        }
+
+        segment
+            .split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect(),
+                    None => String::new(),
+                }
+            })
+            .collect()
+    }
+
+    /// The constant a file under an autoload root defines, if any. The
+    /// autoload roots are each directory in `app/` (and their `concerns`
+    /// directories) and `lib/`.
+    pub fn constant_for_path(&self, path: &Path) -> Option<String> {
+        if path.extension().map_or(true, |e| e != "rb") {
+            return None;
+        }
+
+        let stem = path.with_extension("");
+        let segments: Vec<&str> = stem.iter().map(|s| s.to_str()).collect::<Option<_>>()?;
+
+        let namespace = match segments.as_slice() {
+            ["app", dir, rest @ ..] if !NON_AUTOLOAD_DIRS.contains(dir) => match rest {
+                ["concerns", rest @ ..] => rest,
+                rest => rest,
+            },
+            ["lib", rest @ ..] => rest,
+            _ => return None,
+        };
+
+        if namespace.is_empty() {
+            return None;
+        }
+
+        Some(
+            namespace
+                .iter()
+                .map(|s| self.camelize(s))
+                .collect::<Vec<String>>()
+                .join("::"),
+        )
+    }
+}
+
+/// Finds the constants referenced in Ruby source.
+pub struct ConstantScanner {
+    pattern: Regex,
+    namespace: Regex,
+}
+
+impl ConstantScanner {
+    pub fn new() -> Self {
+        ConstantScanner {
+            pattern: Regex::new(r"(?:::)?\b[A-Z][A-Za-z0-9_]*(?:::[A-Z][A-Za-z0-9_]*)*").unwrap(),
+            namespace: Regex::new(r"^(\s*)(?:module|class)\s+([A-Z][\w:]*)").unwrap(),
+        }
+    }
+
+    /// The constants referenced, including what a relative reference could
+    /// resolve to in the modules and classes it is nested in: `User` inside
+    /// `module Admin` is recorded as both `Admin::User` and `User`. Nesting
+    /// is followed by indentation, with each `module` or `class` closed by
+    /// the `end` lined up with it, so source laid out otherwise may resolve
+    /// in the wrong namespaces.
+    pub fn scan(&self, source: &str) -> BTreeSet<String> {
+        let mut references = BTreeSet::new();
+        // The namespaces the current line is in, with their indentation
+        let mut nesting: Vec<(usize, String)> = vec![];
+
+        for line in source.lines() {
+            let trimmed = line.trim_start();
+            let indent = line.len() - trimmed.len();
+
+            let word_end = |c: char| c.is_alphanumeric() || c == '_';
+            if trimmed.starts_with("end") && !trimmed[3..].starts_with(word_end) {
+                if nesting.last().map_or(false, |(i, _)| *i == indent) {
+                    nesting.pop();
+                }
+                continue;
+            }
+
+            for m in self.pattern.find_iter(line) {
+                let reference = m.as_str();
+                if let Some(absolute) = reference.strip_prefix("::") {
+                    references.insert(absolute.to_owned());
+                    continue;
+                }
+
+                let mut namespace = String::new();
+                for (_, name) in nesting.iter() {
+                    namespace.push_str(name);
+                    references.insert(format!("{}::{}", namespace, reference));
+                    namespace.push_str("::");
+                }
+                references.insert(reference.to_owned());
+            }
+
+            // One-line definitions like `class Error < StandardError; end`
+            // don't open a namespace for the lines after them
+            if let Some(caps) = self.namespace.captures(line) {
+                if !line.trim_end().ends_with("end") {
+                    let name = caps[2].trim_start_matches("::").to_owned();
+                    nesting.push((caps[1].len(), name));
+                }
+            }
+        }
+
+        references
+    }
+}
+
+/// Whether a reference to `reference` in source could be a use of
+/// `constant`. Nested constants such as `Admin::User::Role` count as using
+/// `Admin::User`.
+pub fn references_constant(reference: &str, constant: &str) -> bool {
+    reference == constant
+        || (reference.starts_with(constant) && reference[constant.len()..].starts_with("::"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant(path: &str) -> Option<String> {
+        Inflector::new(HashMap::new()).constant_for_path(Path::new(path))
+    }
+
+    fn scan(source: &str) -> Vec<String> {
+        ConstantScanner::new().scan(source).into_iter().collect()
+    }
+
+    #[test]
+    fn directories_are_namespaces() {
+        assert_eq!(constant("app/models/user.rb").as_deref(), Some("User"));
+        assert_eq!(
+            constant("app/models/admin/user.rb").as_deref(),
+            Some("Admin::User")
+        );
+        assert_eq!(
+            constant("app/services/billing/invoice_sender.rb").as_deref(),
+            Some("Billing::InvoiceSender")
+        );
+        assert_eq!(
+            constant("lib/my_gem/version.rb").as_deref(),
+            Some("MyGem::Version")
+        );
+    }
+
+    #[test]
+    fn concerns_are_collapsed() {
+        assert_eq!(
+            constant("app/models/concerns/trackable.rb").as_deref(),
+            Some("Trackable")
+        );
+        assert_eq!(
+            constant("app/controllers/concerns/admin/authentication.rb").as_deref(),
+            Some("Admin::Authentication")
+        );
+    }
+
+    #[test]
+    fn only_autoloaded_ruby_files_define_constants() {
+        assert_eq!(constant("app/views/users/index.rb"), None);
+        assert_eq!(constant("app/assets/config.rb"), None);
+        assert_eq!(constant("app/models/user.erb"), None);
+        assert_eq!(constant("config/application.rb"), None);
+        assert_eq!(constant("app/models.rb"), None);
+    }
+
+    #[test]
+    fn inflections_name_acronyms() {
+        let mut inflections = HashMap::new();
+        inflections.insert(String::from("api"), String::from("API"));
+        inflections.insert(String::from("html_parser"), String::from("HTMLParser"));
+        let inflector = Inflector::new(inflections);
+        let constant = |path: &str| inflector.constant_for_path(Path::new(path));
+
+        assert_eq!(
+            constant("app/controllers/api/users_controller.rb").as_deref(),
+            Some("API::UsersController")
+        );
+        assert_eq!(
+            constant("lib/html_parser.rb").as_deref(),
+            Some("HTMLParser")
+        );
+        // Inflections replace whole segments, as Zeitwerk's do
+        assert_eq!(constant("app/models/api_key.rb").as_deref(), Some("ApiKey"));
+    }
+
+    #[test]
+    fn scans_whole_constant_references() {
+        assert_eq!(
+            scan("RSpec.describe Admin::User do\n  let(:role) { ::Admin::User::Role.new }\nend"),
+            vec!["Admin::User", "Admin::User::Role", "RSpec"]
+        );
+        assert_eq!(
+            scan("user = build(:user)\nuser_count = my_User"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn relative_references_resolve_in_their_namespaces() {
+        let source = "module Admin\n  class UsersController < BaseController\n    User.find(1)\n    ::Post.first\n  end\n  class Error < StandardError; end\n  Role\nend\nComment\n";
+
+        assert_eq!(
+            scan(source),
+            vec![
+                "Admin",
+                "Admin::BaseController",
+                "Admin::Error",
+                "Admin::Role",
+                "Admin::StandardError",
+                "Admin::User",
+                "Admin::UsersController",
+                "Admin::UsersController::User",
+                "BaseController",
+                "Comment",
+                "Error",
+                "Post",
+                "Role",
+                "StandardError",
+                "User",
+                "UsersController",
+            ]
+        );
+    }
+
+    #[test]
+    fn compact_namespaces_are_nested_whole() {
+        let refs = ConstantScanner::new()
+            .scan("class Admin::UsersController\n  end_date = Date.today\n  User\nend\n");
+
+        assert!(refs.contains("Admin::UsersController::User"));
+        assert!(refs.contains("User"));
+        assert!(!refs.contains("Admin::User"));
+    }
+
+    #[test]
+    fn longer_names_are_not_references() {
+        assert!(references_constant("User", "User"));
+        assert!(references_constant("Admin::User::Role", "Admin::User"));
+        assert!(!references_constant("UserProfile", "User"));
+        assert!(!references_constant("Admin::UserRole", "Admin::User"));
+        assert!(!references_constant("Admin", "Admin::User"));
+
+        let refs = ConstantScanner::new().scan("UserProfile.create(user: CurrentUser.first)");
+        assert!(!refs.iter().any(|r| references_constant(r, "User")));
+    }
+}
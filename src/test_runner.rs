@@ -1,15 +1,166 @@
+pub mod result_store;
+mod run_results;
 mod test_run;
 
-use std::path::PathBuf;
-use test_run::TestRun;
-pub struct TestRunner {}
+pub use result_store::{ExampleHistory, ResultStore, HISTORY_LENGTH};
+pub use run_results::{ExampleResult, ExampleStatus, OutputLine, RunResults};
+pub use test_run::{RunId, TestRun};
+
+use crate::app_state::Event;
+use crate::project::Project;
+use crate::ruby::rspec::{RSpec, RSpecEvent};
+use crate::some_loop;
+
+use std::collections::HashMap;
+use std::thread;
+use tokio::sync::mpsc;
+
+/// Runs specs for projects, feeding rspec's events back into the app state.
+/// Each project has at most one run in progress.
+pub struct TestRunner {
+    dispatcher: mpsc::Sender<Event>,
+    runs: HashMap<String, TestRun>,
+    run_count: usize,
+}
 
 impl TestRunner {
-    pub fn new() -> TestRunner {
-        TestRunner {}
+    pub fn new(dispatcher: mpsc::Sender<Event>) -> TestRunner {
+        TestRunner {
+            dispatcher,
+            runs: HashMap::new(),
+            run_count: 0,
+        }
+    }
+
+    /// Start running `locations` for `project`, cancelling any run of that
+    /// project that is still going.
+    pub async fn queue(
+        &mut self,
+        project: &Project,
+        locations: Vec<String>,
+    ) -> anyhow::Result<TestRun> {
+        self.cancel(&project.name);
+
+        self.run_count += 1;
+        let id = RunId {
+            project: project.name.clone(),
+            number: self.run_count,
+        };
+
+        self.dispatcher
+            .send(Event::TestRunning(
+                id.clone(),
+                project.root.clone(),
+                locations.clone(),
+            ))
+            .await?;
+
+        let (rspec_tx, rspec_rx) = std::sync::mpsc::channel::<RSpecEvent>();
+        let rspec = RSpec::new(project.rspec.clone())
+            .working_dir(&project.root)
+            .collect_coverage(project.coverage.is_some());
+        let ran_locations = locations.clone();
+        let rspec_run = match rspec.run(locations, rspec_tx) {
+            Ok(rspec_run) => rspec_run,
+            Err(err) => {
+                // Finish the run announced above, so it isn't left running
+                let events = vec![
+                    RSpecEvent::Error {
+                        msg: err.to_string(),
+                    },
+                    RSpecEvent::Exit,
+                ];
+                for event in events {
+                    self.dispatcher
+                        .send(Event::TestEvent(id.clone(), event))
+                        .await?;
+                }
+                return Err(err);
+            }
+        };
+        let coverage = project.coverage.clone();
+        let results = std::sync::Arc::clone(&project.results);
+
+        // rspec reports on a std channel from its own thread, so bridge that
+        // through an unbounded channel that can be sent to without blocking.
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel::<Event>();
+        let event_id = id.clone();
+        thread::spawn(move || {
+            // Whether rspec ran everything it was asked to, for coverage to
+            // forget the examples that no longer exist
+            let mut stopped = false;
+            let mut errored = false;
+
+            for event in rspec_rx.iter() {
+                let exit = event == RSpecEvent::Exit;
+                results.lock().unwrap().on_rspec_event(&event);
+
+                match &event {
+                    RSpecEvent::Stop {} => stopped = true,
+                    RSpecEvent::Error { .. } => errored = true,
+                    _ => {}
+                }
+
+                if let Some(coverage) = &coverage {
+                    match &event {
+                        RSpecEvent::ExampleCoverage { id, files } => {
+                            coverage.lock().unwrap().record(id, files);
+                        }
+                        RSpecEvent::Exit => {
+                            let mut coverage = coverage.lock().unwrap();
+                            coverage.finish_run(&ran_locations, stopped && !errored);
+                            if let Err(err) = coverage.save() {
+                                let message = format!("Could not save coverage: {}", err);
+                                event_tx.send(Event::Error(message)).ok();
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                if event_tx
+                    .send(Event::TestEvent(event_id.clone(), event))
+                    .is_err()
+                {
+                    break;
+                }
+
+                if exit {
+                    let results = results.lock().unwrap();
+                    if let Err(err) = results.save() {
+                        let message = format!("Could not save results: {}", err);
+                        event_tx.send(Event::Error(message)).ok();
+                    }
+
+                    event_tx
+                        .send(Event::HistoryUpdated(
+                            event_id.project.clone(),
+                            results.examples().clone(),
+                        ))
+                        .ok();
+                    break;
+                }
+            }
+        });
+
+        let mut dispatcher = self.dispatcher.clone();
+        tokio::spawn(async move {
+            some_loop!(event = event_rx.recv() => {
+                if dispatcher.send(event).await.is_err() {
+                    break;
+                }
+            });
+        });
+
+        let run = TestRun::new(id, rspec_run);
+        self.runs.insert(project.name.clone(), run.clone());
+
+        Ok(run)
     }
 
-    pub fn queue(files: Vec<PathBuf>) -> TestRun {
-        TestRun {}
+    pub fn cancel(&mut self, project: &str) {
+        if let Some(run) = self.runs.remove(project) {
+            run.cancel();
+        }
     }
 }
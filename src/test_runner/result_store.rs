@@ -0,0 +1,120 @@
+use crate::ruby::rspec::RSpecEvent;
+use crate::util::store;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const RESULTS_FILE: &str = "results.json";
+/// How many results to keep for each example
+pub const HISTORY_LENGTH: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultRecord {
+    pub passed: bool,
+    pub run_time: f64,
+    /// When the example finished, in seconds since the Unix epoch
+    pub at: u64,
+}
+
+/// The latest results of one example, oldest first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExampleHistory {
+    pub location: Option<String>,
+    pub description: Option<String>,
+    pub results: Vec<ResultRecord>,
+}
+
+impl ExampleHistory {
+    pub fn last_failure(&self) -> Option<u64> {
+        self.results.iter().rev().find(|r| !r.passed).map(|r| r.at)
+    }
+
+    pub fn mean_run_time(&self) -> Option<f64> {
+        match self.results.len() {
+            0 => None,
+            len => Some(self.results.iter().map(|r| r.run_time).sum::<f64>() / len as f64),
+        }
+    }
+}
+
+/// The results of each example over its last few runs, keyed by example
+/// id. Persisted under the project root so it survives restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ResultStore {
+    examples: BTreeMap<String, ExampleHistory>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl ResultStore {
+    pub fn load(root: &Path) -> Self {
+        let path = store::store_path(root, RESULTS_FILE);
+        let mut results: ResultStore = store::read_json(&path).unwrap_or_default();
+        results.path = path;
+        results
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        store::write_json(&self.path, self)
+    }
+
+    pub fn examples(&self) -> &BTreeMap<String, ExampleHistory> {
+        &self.examples
+    }
+
+    fn record(
+        &mut self,
+        id: &str,
+        location: Option<&String>,
+        description: Option<&String>,
+        passed: bool,
+        run_time: f64,
+    ) {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let history = self.examples.entry(id.to_owned()).or_default();
+        history.location = location.cloned().or_else(|| history.location.take());
+        history.description = description.cloned().or_else(|| history.description.take());
+        history.results.push(ResultRecord {
+            passed,
+            run_time,
+            at,
+        });
+
+        if history.results.len() > HISTORY_LENGTH {
+            history.results.remove(0);
+        }
+    }
+
+    /// Record the result of an example as rspec reports it.
+    pub fn on_rspec_event(&mut self, event: &RSpecEvent) {
+        match event {
+            RSpecEvent::ExamplePassed {
+                id,
+                location,
+                description,
+                run_time,
+                ..
+            } => self.record(id, Some(location), Some(description), true, *run_time),
+            RSpecEvent::ExampleFailed {
+                id,
+                location,
+                description,
+                run_time,
+                ..
+            } => self.record(
+                id,
+                location.as_ref(),
+                description.as_ref(),
+                false,
+                *run_time,
+            ),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use crate::ruby::backtrace::{self, Frame};
+use crate::ruby::rspec::{OutputStream, RSpecEvent};
+use crate::test_runner::RunId;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The most output kept for a run, as it is copied with every state update.
+/// Older lines are dropped first.
+const MAX_OUTPUT_LINES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExampleStatus {
+    Running,
+    Passed,
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExampleResult {
+    pub id: String,
+    pub location: Option<String>,
+    pub description: Option<String>,
+    pub status: ExampleStatus,
+    pub run_time: Option<f64>,
+    pub exception: Option<String>,
+    pub backtrace: Option<Vec<String>>,
+}
+
+impl ExampleResult {
+    /// The spec file the example is in, going by its id, e.g.
+    /// `./spec/models/user_spec.rb[1:2]`.
+    pub fn spec_file(&self) -> &Path {
+        Path::new(self.id.split('[').next().unwrap_or(&self.id))
+    }
+
+    /// Where the example failed, relative to the project root. This is the
+    /// line in the spec file that raised if the backtrace has it, or else the
+    /// example's own location.
+    pub fn failure_frame(&self) -> Option<Frame> {
+        self.backtrace
+            .as_ref()
+            .and_then(|b| backtrace::failure_frame(b, Some(self.spec_file())))
+            .or_else(|| self.location.as_deref().and_then(Frame::parse))
+    }
+}
+
+/// A line rspec wrote outside of the formatter.
+#[derive(Debug, Clone)]
+pub struct OutputLine {
+    pub stream: OutputStream,
+    pub line: String,
+    /// The example that was running when the line was written. Only stdout
+    /// is attributed, as stderr is read apart from the formatter's events and
+    /// can't be ordered with them.
+    pub example: Option<String>,
+}
+
+/// The accumulated results of a single run.
+#[derive(Debug, Clone)]
+pub struct RunResults {
+    pub run: RunId,
+    /// The project root the run is in, which paths from rspec are relative to
+    pub root: PathBuf,
+    pub locations: Vec<String>,
+    pub count: Option<i64>,
+    pub examples: Vec<ExampleResult>,
+    pub errors: Vec<String>,
+    pub output: VecDeque<OutputLine>,
+    pub finished: bool,
+    /// Whether the run was cancelled before it finished
+    pub cancelled: bool,
+    pub started_at: Instant,
+    pub finished_at: Option<Instant>,
+}
+
+impl RunResults {
+    pub fn new(run: RunId, root: PathBuf, locations: Vec<String>) -> Self {
+        RunResults {
+            run,
+            root,
+            locations,
+            count: None,
+            examples: vec![],
+            errors: vec![],
+            output: VecDeque::new(),
+            finished: false,
+            cancelled: false,
+            started_at: Instant::now(),
+            finished_at: None,
+        }
+    }
+
+    /// The result for example `id`, added if its start was never seen.
+    fn example_entry(
+        &mut self,
+        id: String,
+        location: Option<String>,
+        description: Option<String>,
+    ) -> &mut ExampleResult {
+        let index = match self.examples.iter().position(|e| e.id == id) {
+            Some(index) => index,
+            None => {
+                self.examples.push(ExampleResult {
+                    id,
+                    location,
+                    description,
+                    status: ExampleStatus::Running,
+                    run_time: None,
+                    exception: None,
+                    backtrace: None,
+                });
+                self.examples.len() - 1
+            }
+        };
+
+        &mut self.examples[index]
+    }
+
+    pub fn on_rspec_event(&mut self, event: RSpecEvent) {
+        match event {
+            RSpecEvent::Start { count } => {
+                self.count = count;
+            }
+            RSpecEvent::ExampleStarted {
+                id,
+                location,
+                description,
+            } => {
+                self.example_entry(id, Some(location), description);
+            }
+            RSpecEvent::ExamplePassed {
+                id,
+                location,
+                description,
+                run_time,
+                ..
+            } => {
+                let example = self.example_entry(id, Some(location), Some(description));
+                example.status = ExampleStatus::Passed;
+                example.run_time = Some(run_time);
+            }
+            RSpecEvent::ExampleFailed {
+                id,
+                location,
+                description,
+                run_time,
+                exception,
+                backtrace,
+                ..
+            } => {
+                let example = self.example_entry(id, location, description);
+                example.status = ExampleStatus::Failed;
+                example.run_time = Some(run_time);
+                example.exception = exception;
+                example.backtrace = backtrace;
+            }
+            RSpecEvent::ExampleCoverage { .. } => {}
+            RSpecEvent::Stop {} => {}
+            RSpecEvent::Output { stream, line } => {
+                let example = match stream {
+                    OutputStream::Stdout => self
+                        .examples
+                        .iter()
+                        .rev()
+                        .find(|e| e.status == ExampleStatus::Running)
+                        .map(|e| e.id.clone()),
+                    OutputStream::Stderr => None,
+                };
+
+                if self.output.len() == MAX_OUTPUT_LINES {
+                    self.output.pop_front();
+                }
+                self.output.push_back(OutputLine {
+                    stream,
+                    line,
+                    example,
+                });
+            }
+            RSpecEvent::Cancelled {} => {
+                self.cancelled = true;
+            }
+            RSpecEvent::Error { msg } => {
+                self.errors.push(msg);
+            }
+            RSpecEvent::Exit => {
+                self.finished = true;
+                self.finished_at = Some(Instant::now());
+            }
+        }
+    }
+
+    /// How long the run took, or has taken so far.
+    pub fn elapsed(&self) -> Duration {
+        self.finished_at
+            .unwrap_or_else(Instant::now)
+            .duration_since(self.started_at)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &ExampleResult> {
+        self.examples
+            .iter()
+            .filter(|e| e.status == ExampleStatus::Failed)
+    }
+
+    /// The examples that have finished, whether they passed or failed.
+    pub fn completed(&self) -> usize {
+        self.passed() + self.failed()
+    }
+
+    /// The examples rspec said it would run, or those seen so far until it
+    /// has said.
+    pub fn total(&self) -> usize {
+        self.count
+            .map(|c| c as usize)
+            .unwrap_or_else(|| self.examples.len())
+    }
+
+    /// What the example printed to stdout while it was running.
+    pub fn output_for<'a>(&'a self, example: &'a str) -> impl Iterator<Item = &'a OutputLine> {
+        self.output
+            .iter()
+            .filter(move |o| o.example.as_deref() == Some(example))
+    }
+
+    pub fn running(&self) -> usize {
+        self.count_with_status(ExampleStatus::Running)
+    }
+
+    pub fn count_with_status(&self, status: ExampleStatus) -> usize {
+        self.examples.iter().filter(|e| e.status == status).count()
+    }
+
+    pub fn passed(&self) -> usize {
+        self.count_with_status(ExampleStatus::Passed)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count_with_status(ExampleStatus::Failed)
+    }
+}
@@ -1,5 +1,30 @@
-pub struct TestRun {}
+use crate::ruby::rspec::RSpecRun;
+use std::sync::Arc;
+
+/// Identifies a single run of a project's specs, so that events from a run
+/// that has since been replaced can be told apart from the current one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RunId {
+    pub project: String,
+    pub number: usize,
+}
+
+#[derive(Clone)]
+pub struct TestRun {
+    pub id: RunId,
+    rspec: Arc<RSpecRun>,
+}
 
 impl TestRun {
-    pub fn cancel(&self) {}
+    pub fn new(id: RunId, rspec: RSpecRun) -> Self {
+        TestRun {
+            id,
+            rspec: Arc::new(rspec),
+        }
+    }
+
+    pub fn cancel(&self) {
+        // The process may well have already exited
+        self.rspec.kill().ok();
+    }
 }
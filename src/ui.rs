@@ -1,77 +1,155 @@
+mod changed_files;
+mod editor;
+mod failures;
+mod help;
+mod history;
+mod live_run;
+mod output;
+mod picker;
+mod progress;
+mod status_bar;
+mod ui_state;
+
 use crate::app_state::{AppState, AppStateManager, Event};
 use crate::input;
 use crate::program::Program;
-use crate::ChangedFile;
+use editor::Editor;
+use ui_state::{Action, UiState};
 
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use std::io;
-use termion::event::Key;
-use termion::raw::IntoRawMode;
-use tokio::stream::{Stream, StreamExt};
+use std::io::{self, Stdout};
+use std::path::Path;
+use std::time::Duration;
+use termion::raw::{IntoRawMode, RawTerminal};
+use tokio::stream::StreamExt;
 use tui::backend::TermionBackend;
 use tui::Terminal;
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    symbols,
-    text::{Span, Spans},
-    widgets::canvas::{Canvas, Line, Map, MapResolution, Rectangle},
-    widgets::{
-        Axis, BarChart, Block, Borders, Chart, Dataset, Gauge, List, ListItem, ListState,
-        Paragraph, Row, Sparkline, Table, Tabs, Wrap,
-    },
+    text::Span,
+    widgets::{Block, Borders},
     Frame,
 };
 
-fn changed_file_text(file: &ChangedFile, running: bool) -> Spans {
-    let t = file.path.to_string_lossy();
-
-    let status = match file.status {
-        git2::Delta::Unmodified => "U",
-        git2::Delta::Added => "A",
-        git2::Delta::Deleted => "D",
-        git2::Delta::Modified => "M",
-        git2::Delta::Renamed => "R",
-        git2::Delta::Copied => "R",
-        git2::Delta::Ignored => "I",
-        git2::Delta::Untracked => "-",
-        git2::Delta::Typechange => "M",
-        git2::Delta::Unreadable => "X",
-        git2::Delta::Conflicted => "C",
+fn pane_block(title: &str, focused: bool) -> Block {
+    let border_style = if focused {
+        Style::default().fg(Color::Magenta)
+    } else {
+        Style::default()
     };
 
-    let running_text = if running { "> " } else { "  " };
-
-    Spans::from(vec![
-        Span::styled(running_text, Style::default().fg(Color::Yellow)),
-        Span::raw(status),
-        Span::raw(" "),
-        Span::raw(t),
-    ])
-}
-
-pub fn draw<B: Backend>(f: &mut Frame<B>, state: &AppState) {
-    // let chunks = Layout::default()
-    //     .constraints([Constraint::Length(1), Constraint::Min(0)].as_ref())
-    //     .split(f.size());
-
-    let files: Vec<ListItem> = state
-        .changed_files
-        .iter()
-        .map(|c| ListItem::new(changed_file_text(c, true)))
-        .collect();
-    let list = List::new(files).block(
-        Block::default().borders(Borders::ALL).title(Span::styled(
-            "Changed files",
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(border_style)
+        .title(Span::styled(
+            title,
             Style::default()
                 .fg(Color::Magenta)
                 .add_modifier(Modifier::BOLD),
-        )),
-    );
+        ))
+}
+
+fn highlight_style(focused: bool) -> Style {
+    if focused {
+        Style::default().add_modifier(Modifier::REVERSED)
+    } else {
+        Style::default().add_modifier(Modifier::BOLD)
+    }
+}
+
+/// The panes or the history screen above a status bar, with any overlay on
+/// top.
+pub fn draw<B: Backend>(f: &mut Frame<B>, state: &AppState, ui: &UiState) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+        .split(f.size());
+
+    status_bar::draw(f, rows[1], state);
+
+    if ui.show_history {
+        history::draw(f, rows[0], state, ui);
+    } else {
+        draw_panes(f, rows[0], state, ui);
+    }
+
+    if let Some(picker) = &ui.picker {
+        picker.draw(f, f.size(), state);
+    }
 
-    f.render_widget(list, f.size())
+    if ui.show_help {
+        help::draw(f, f.size());
+    }
+}
+
+/// Changed files and their specs on the left above run timings, and the run
+/// in progress, its failures and output on the right.
+fn draw_panes<B: Backend>(f: &mut Frame<B>, area: Rect, state: &AppState, ui: &UiState) {
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+        .split(area);
+
+    let left = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Min(0),
+                Constraint::Length(6),
+                Constraint::Length(10),
+            ]
+            .as_ref(),
+        )
+        .split(columns[0]);
+
+    let right = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(3),
+                Constraint::Percentage(35),
+                Constraint::Percentage(35),
+                Constraint::Percentage(30),
+            ]
+            .as_ref(),
+        )
+        .split(columns[1]);
+
+    changed_files::draw(f, left[0], state, ui);
+    progress::draw_run_times(f, left[1], state);
+    progress::draw_slowest(f, left[2], state);
+    progress::draw_gauge(f, right[0], state);
+    live_run::draw(f, right[1], state, ui);
+    failures::draw(f, right[2], state, ui);
+    output::draw(f, right[3], state, ui);
+}
+
+/// Hand the terminal over to the editor until it exits.
+fn edit(
+    raw: &RawTerminal<Stdout>,
+    editor: &Editor,
+    file: &Path,
+    line: Option<usize>,
+) -> Result<()> {
+    let mut command = match editor.command(file, line) {
+        Some(command) => command,
+        None => return Ok(()),
+    };
+
+    input::pause();
+    raw.suspend_raw_mode().context("Could not leave raw mode")?;
+
+    // Nowhere to show an editor that wouldn't start, so carry on regardless
+    tokio::task::block_in_place(|| command.status()).ok();
+
+    raw.activate_raw_mode()
+        .context("Could not return to raw mode")?;
+    input::resume();
+
+    Ok(())
 }
 
 pub struct TuiApp {}
@@ -83,37 +161,64 @@ impl Program for TuiApp {
         tokio::pin!(state_stream);
 
         let mut input_rx = input::listen();
-
-        let stdout = io::stdout()
+        // Redraw every second, so the elapsed time keeps moving while rspec
+        // is quiet
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+        let mut app_state = AppState::new();
+        let mut ui = UiState::new();
+        let editor = Editor::from_env(&crate::CONFIG.get().editors);
+
+        // Kept apart from the backend so raw mode can be left while an
+        // editor has the terminal
+        let raw = io::stdout()
             .into_raw_mode()
             .context("Could not open stdout")?;
-        let backend = TermionBackend::new(stdout);
+        let backend = TermionBackend::new(io::stdout());
         let mut terminal = Terminal::new(backend).context("Could not create a terminal")?;
         terminal.clear().context("Could not clear the terminal")?;
 
         loop {
             tokio::select! {
-                app_state = state_stream.next() => {
-                    match app_state {
-                        Some((_, app_state)) => {
-                            if app_state.should_quit {
+                next_state = state_stream.next() => {
+                    match next_state {
+                        Some((_, next_state)) => {
+                            if next_state.should_quit {
                                 break;
                             }
-                            terminal
-                                .draw(|f| draw(f, &app_state))
-                                .context("Error while updating UI")?;
+                            app_state = next_state;
                         },
                         None => {break;}
                     }
                 }
+                _ = tick.tick() => {}
                 key = input_rx.recv() => {
-                    let key = key.unwrap();
-
-                    if let Key::Char('q') = key {
-                        app.dispatch(Event::Quit).await?;
+                    let key = match key {
+                        Some(key) => key.context("Could not read input")?,
+                        // Without input there is no way to use the UI
+                        None => {
+                            app.dispatch(Event::Quit).await?;
+                            break;
+                        }
+                    };
+
+                    match ui.on_key(key, &app_state) {
+                        Some(Action::Dispatch(events)) => {
+                            for event in events {
+                                app.dispatch(event).await?;
+                            }
+                        }
+                        Some(Action::Edit(file, line)) => {
+                            edit(&raw, &editor, &file, line)?;
+                            terminal.clear().context("Could not clear the terminal")?;
+                        }
+                        None => {}
                     }
                 }
             }
+
+            terminal
+                .draw(|f| draw(f, &app_state, &ui))
+                .context("Error while updating UI")?;
         }
 
         terminal.clear().ok();
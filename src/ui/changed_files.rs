@@ -0,0 +1,82 @@
+use crate::app_state::AppState;
+use crate::mapping::{Reason, SpecMatch};
+use crate::repo_watcher::ChangedFile;
+
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Span, Spans},
+    widgets::{List, ListItem, ListState},
+    Frame,
+};
+
+use super::ui_state::{Pane, UiState};
+
+fn changed_file_text(file: &ChangedFile, running: bool) -> Spans {
+    let running_text = if running { "> " } else { "  " };
+
+    Spans::from(vec![
+        Span::styled(running_text, Style::default().fg(Color::Yellow)),
+        Span::raw(file.status_letter()),
+        Span::raw(" "),
+        Span::raw(file.path.to_string_lossy()),
+    ])
+}
+
+fn spec_match_text(m: &SpecMatch) -> Spans {
+    match m.reason {
+        Reason::CoChange { score } => Spans::from(vec![
+            Span::raw("      "),
+            Span::styled("? ", Style::default().fg(Color::Cyan)),
+            Span::raw(m.location.as_str()),
+            Span::styled(
+                format!(" ({:.0}% co-changed)", score * 100.0),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]),
+        _ => Spans::from(vec![
+            Span::raw("      "),
+            Span::styled("→ ", Style::default().fg(Color::DarkGray)),
+            Span::raw(m.location.as_str()),
+        ]),
+    }
+}
+
+/// Each changed file followed by the specs selected for it, along with the
+/// row of the file at `selected`.
+fn items(state: &AppState, selected: Option<usize>) -> (Vec<ListItem>, Option<usize>) {
+    let mut items = vec![];
+    let mut selected_row = None;
+
+    for (i, file) in state.changed_files.iter().enumerate() {
+        if selected == Some(i) {
+            selected_row = Some(items.len());
+        }
+
+        items.push(ListItem::new(changed_file_text(
+            file,
+            state.is_running(file),
+        )));
+        items.extend(
+            state
+                .matches_for(file)
+                .map(|m| ListItem::new(spec_match_text(m))),
+        );
+    }
+
+    (items, selected_row)
+}
+
+pub fn draw<B: Backend>(f: &mut Frame<B>, area: Rect, state: &AppState, ui: &UiState) {
+    let focused = ui.focus == Pane::ChangedFiles;
+    let (items, selected_row) = items(state, ui.selected(Pane::ChangedFiles, state));
+    let list = List::new(items)
+        .block(super::pane_block("Changed files", focused))
+        .highlight_style(super::highlight_style(focused));
+
+    let mut list_state = ListState::default();
+    list_state.select(selected_row);
+
+    f.render_stateful_widget(list, area, &mut list_state);
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+/// Arguments for editors that don't take the common `+line file` form
+const KNOWN_EDITORS: &[(&str, &str)] = &[
+    ("code", "--goto {file}:{line}"),
+    ("code-insiders", "--goto {file}:{line}"),
+    ("codium", "--goto {file}:{line}"),
+    ("subl", "{file}:{line}"),
+    ("atom", "{file}:{line}"),
+    ("zed", "{file}:{line}"),
+    ("mate", "--line {line} {file}"),
+    ("idea", "--line {line} {file}"),
+    ("rubymine", "--line {line} {file}"),
+];
+
+const DEFAULT_ARGS: &str = "+{line} {file}";
+
+/// The editor from `$VISUAL` or `$EDITOR`, and how to tell it which line to
+/// open a file at.
+pub struct Editor {
+    command: Vec<String>,
+    args: String,
+}
+
+impl Editor {
+    /// `editors` maps an editor's command name to its arguments, overriding
+    /// those it is known to take. Falls back to `vi` when neither variable
+    /// is set, like git does.
+    pub fn from_env(editors: &HashMap<String, String>) -> Editor {
+        let command: Vec<String> = env::var("VISUAL")
+            .or_else(|_| env::var("EDITOR"))
+            .unwrap_or_else(|_| "vi".to_owned())
+            .split_whitespace()
+            .map(|s| s.to_owned())
+            .collect();
+
+        let name = command
+            .first()
+            .and_then(|c| Path::new(c).file_name())
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let args = editors
+            .get(&name)
+            .map(|a| a.as_str())
+            .or_else(|| {
+                KNOWN_EDITORS
+                    .iter()
+                    .find(|(editor, _)| *editor == name)
+                    .map(|(_, args)| *args)
+            })
+            .unwrap_or(DEFAULT_ARGS)
+            .to_owned();
+
+        Editor { command, args }
+    }
+
+    /// The arguments to open `file`, at `line` if given.
+    fn args(&self, file: &Path, line: Option<usize>) -> Vec<String> {
+        let file = file.to_string_lossy().into_owned();
+
+        match line {
+            Some(line) => self
+                .args
+                .split_whitespace()
+                .map(|arg| {
+                    arg.replace("{line}", &line.to_string())
+                        .replace("{file}", &file)
+                })
+                .collect(),
+            None => vec![file],
+        }
+    }
+
+    pub fn command(&self, file: &Path, line: Option<usize>) -> Option<Command> {
+        let (program, command_args) = self.command.split_first()?;
+
+        let mut command = Command::new(program);
+        command.args(command_args).args(self.args(file, line));
+
+        Some(command)
+    }
+}
@@ -0,0 +1,224 @@
+use crate::app_state::AppState;
+use crate::ruby::backtrace;
+use crate::test_runner::{ExampleResult, RunResults};
+
+use std::fs;
+use std::path::Path;
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans, Text},
+    widgets::{Paragraph, Wrap},
+    Frame,
+};
+
+use super::ui_state::{Pane, UiState};
+
+/// Lines of source to show either side of the failing line
+const CONTEXT_LINES: usize = 4;
+
+fn heading(text: String) -> Spans<'static> {
+    Spans::from(Span::styled(
+        text,
+        Style::default().add_modifier(Modifier::BOLD),
+    ))
+}
+
+/// The source around `line` (1-based) of the file at `path`, numbered, with
+/// `line` itself highlighted.
+fn source_snippet(path: &Path, line: usize) -> Option<Vec<Spans<'static>>> {
+    let source = fs::read_to_string(path).ok()?;
+    let lines: Vec<&str> = source.lines().collect();
+    if line == 0 || line > lines.len() {
+        return None;
+    }
+
+    let first = line.saturating_sub(CONTEXT_LINES).max(1);
+    let last = (line + CONTEXT_LINES).min(lines.len());
+    let width = last.to_string().len();
+
+    let snippet = (first..=last)
+        .map(|n| {
+            let number = format!("{:>width$} ", n, width = width);
+            let code = lines[n - 1].to_owned();
+
+            if n == line {
+                Spans::from(vec![
+                    Span::styled(
+                        format!("{}> ", number),
+                        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                    ),
+                    Span::styled(code, Style::default().bg(Color::Red).fg(Color::White)),
+                ])
+            } else {
+                Spans::from(vec![
+                    Span::styled(
+                        format!("{}| ", number),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    Span::raw(code),
+                ])
+            }
+        })
+        .collect();
+
+    Some(snippet)
+}
+
+fn failure_text<'a>(run: &'a RunResults, example: &'a ExampleResult) -> Vec<Spans<'a>> {
+    let title = example.location.as_ref().unwrap_or(&example.id).as_str();
+    let mut lines = vec![Spans::from(Span::styled(
+        title,
+        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+    ))];
+
+    if let Some(description) = &example.description {
+        lines.push(Spans::from(description.as_str()));
+    }
+
+    if let Some(exception) = &example.exception {
+        lines.push(Spans::default());
+        lines.extend(exception.lines().map(|l| Spans::from(format!("  {}", l))));
+    }
+
+    if let Some(frame) = example.failure_frame() {
+        if let Some(snippet) = source_snippet(&run.root.join(&frame.path), frame.line) {
+            lines.push(Spans::default());
+            lines.push(heading(format!("{}:{}", frame.path.display(), frame.line)));
+            lines.extend(snippet);
+        }
+    }
+
+    let output: Vec<Spans> = run
+        .output_for(&example.id)
+        .map(|o| super::output::output_text(o))
+        .collect();
+    if !output.is_empty() {
+        lines.push(Spans::default());
+        lines.push(heading("Output".to_owned()));
+        lines.extend(output);
+    }
+
+    if let Some(backtrace) = &example.backtrace {
+        lines.push(Spans::default());
+        lines.push(heading("Backtrace".to_owned()));
+        lines.extend(backtrace::filter(backtrace).into_iter().map(|l| {
+            Spans::from(Span::styled(
+                format!("  # {}", l),
+                Style::default().fg(Color::DarkGray),
+            ))
+        }));
+    }
+
+    lines
+}
+
+/// The selected failure: its message, the spec source where it failed and
+/// the backtrace without gem and Ruby frames.
+pub fn draw<B: Backend>(f: &mut Frame<B>, area: Rect, state: &AppState, ui: &UiState) {
+    let focused = ui.focus == Pane::Failures;
+    let count = state.failures().count();
+
+    let (title, lines) = match ui.selected_failure(state) {
+        Some((run, example)) => {
+            let index = ui.selected(Pane::Failures, state).unwrap_or(0);
+            (
+                format!("Failures ({}/{})", index + 1, count),
+                failure_text(run, example),
+            )
+        }
+        None => ("Failures".to_owned(), vec![]),
+    };
+
+    let paragraph = Paragraph::new(Text::from(lines))
+        .block(super::pane_block(&title, focused))
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(paragraph, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_dir::TestDir;
+
+    /// The snippet's lines as text, and which is highlighted.
+    fn snippet(dir: &TestDir, line: usize) -> Option<(Vec<String>, Vec<bool>)> {
+        let snippet = source_snippet(&dir.path.join("user.rb"), line)?;
+
+        Some(
+            snippet
+                .iter()
+                .map(|spans| {
+                    let text: String = spans.0.iter().map(|s| s.content.as_ref()).collect();
+                    (text, spans.0[1].style.bg == Some(Color::Red))
+                })
+                .unzip(),
+        )
+    }
+
+    fn source(dir_name: &str) -> TestDir {
+        let dir = TestDir::new(dir_name);
+        let lines: Vec<String> = (1..=10).map(|n| format!("line {}", n)).collect();
+        dir.write("user.rb", &lines.join("\n"));
+        dir
+    }
+
+    #[test]
+    fn highlights_the_line_with_context() {
+        let dir = source("failures-middle");
+        let (lines, highlighted) = snippet(&dir, 5).unwrap();
+
+        assert_eq!(lines.first().map(String::as_str), Some("1 | line 1"));
+        assert_eq!(lines[4], "5 > line 5");
+        assert_eq!(lines.last().map(String::as_str), Some("9 | line 9"));
+        assert_eq!(highlighted.iter().filter(|h| **h).count(), 1);
+        assert!(highlighted[4]);
+    }
+
+    #[test]
+    fn highlights_the_first_line() {
+        let dir = source("failures-first");
+        let (lines, highlighted) = snippet(&dir, 1).unwrap();
+
+        assert_eq!(
+            lines,
+            vec![
+                "1 > line 1",
+                "2 | line 2",
+                "3 | line 3",
+                "4 | line 4",
+                "5 | line 5"
+            ]
+        );
+        assert!(highlighted[0]);
+    }
+
+    #[test]
+    fn highlights_the_last_line() {
+        let dir = source("failures-last");
+        let (lines, highlighted) = snippet(&dir, 10).unwrap();
+
+        assert_eq!(
+            lines,
+            vec![
+                " 6 | line 6",
+                " 7 | line 7",
+                " 8 | line 8",
+                " 9 | line 9",
+                "10 > line 10"
+            ]
+        );
+        assert!(highlighted[4]);
+    }
+
+    #[test]
+    fn lines_outside_the_file_have_no_snippet() {
+        let dir = source("failures-outside");
+
+        assert!(snippet(&dir, 0).is_none());
+        assert!(snippet(&dir, 11).is_none());
+        assert!(source_snippet(&dir.path.join("missing.rb"), 1).is_none());
+    }
+}
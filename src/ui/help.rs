@@ -0,0 +1,41 @@
+use super::ui_state::BINDINGS;
+
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Span, Spans, Text},
+    widgets::{Clear, Paragraph},
+    Frame,
+};
+
+/// A box in the middle of `area`, `width` by `height` where there is room.
+fn centered(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+pub fn draw<B: Backend>(f: &mut Frame<B>, area: Rect) {
+    let lines: Vec<Spans> = BINDINGS
+        .iter()
+        .map(|(keys, action)| {
+            Spans::from(vec![
+                Span::styled(format!(" {:<10}", keys), Style::default().fg(Color::Yellow)),
+                Span::raw(*action),
+            ])
+        })
+        .collect();
+
+    let area = centered(50, lines.len() as u16 + 2, area);
+    let help = Paragraph::new(Text::from(lines)).block(super::pane_block("Keys", true));
+
+    f.render_widget(Clear, area);
+    f.render_widget(help, area);
+}
@@ -0,0 +1,113 @@
+use crate::app_state::AppState;
+use crate::test_runner::{ExampleHistory, HISTORY_LENGTH};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Span, Spans},
+    widgets::{List, ListItem, ListState},
+    Frame,
+};
+
+use super::ui_state::UiState;
+
+/// Every example with a history, the most recently failed first.
+pub fn rows(state: &AppState) -> Vec<(&str, &ExampleHistory)> {
+    let mut rows: Vec<(&str, &ExampleHistory)> = state
+        .history
+        .values()
+        .flat_map(|examples| examples.iter().map(|(id, h)| (id.as_str(), h)))
+        .collect();
+
+    rows.sort_by(|(a_id, a), (b_id, b)| {
+        b.last_failure()
+            .cmp(&a.last_failure())
+            .then_with(|| a_id.cmp(b_id))
+    });
+    rows
+}
+
+fn ago(at: u64, now: u64) -> String {
+    let seconds = now.saturating_sub(at);
+
+    match seconds {
+        s if s < 60 => format!("{}s ago", s),
+        s if s < 60 * 60 => format!("{}m ago", s / 60),
+        s if s < 60 * 60 * 24 => format!("{}h ago", s / (60 * 60)),
+        s => format!("{}d ago", s / (60 * 60 * 24)),
+    }
+}
+
+fn history_text<'a>(id: &'a str, history: &'a ExampleHistory, now: u64) -> Spans<'a> {
+    let padding = HISTORY_LENGTH.saturating_sub(history.results.len());
+    let mut spans = vec![Span::styled(
+        "·".repeat(padding),
+        Style::default().fg(Color::DarkGray),
+    )];
+
+    spans.extend(history.results.iter().map(|r| {
+        let color = if r.passed { Color::Green } else { Color::Red };
+        Span::styled("■", Style::default().fg(color))
+    }));
+
+    let run_times = match (history.results.last(), history.mean_run_time()) {
+        (Some(last), Some(mean)) => format!(" {:>6.2}s avg {:>6.2}s ", last.run_time, mean),
+        _ => String::from(" "),
+    };
+    spans.push(Span::styled(
+        run_times,
+        Style::default().fg(Color::DarkGray),
+    ));
+
+    spans.push(match history.last_failure() {
+        Some(at) => Span::styled(
+            format!("{:>12} ", format!("failed {}", ago(at, now))),
+            Style::default().fg(Color::Red),
+        ),
+        None => Span::styled(
+            format!("{:>12} ", "never failed"),
+            Style::default().fg(Color::DarkGray),
+        ),
+    });
+
+    spans.push(Span::raw(history.location.as_deref().unwrap_or(id)));
+
+    if let Some(description) = &history.description {
+        spans.push(Span::styled(
+            format!(" {}", description),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+
+    Spans::from(spans)
+}
+
+/// Each example's recent results as a strip, oldest on the left, with its
+/// run times and when it last failed.
+pub fn draw<B: Backend>(f: &mut Frame<B>, area: Rect, state: &AppState, ui: &UiState) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let rows = rows(state);
+    let selected = match rows.len() {
+        0 => None,
+        len => Some(ui.history_row.min(len - 1)),
+    };
+    let items: Vec<ListItem> = rows
+        .into_iter()
+        .map(|(id, history)| ListItem::new(history_text(id, history, now)))
+        .collect();
+
+    let list = List::new(items)
+        .block(super::pane_block("History", true))
+        .highlight_style(super::highlight_style(true));
+
+    let mut list_state = ListState::default();
+    list_state.select(selected);
+
+    f.render_stateful_widget(list, area, &mut list_state);
+}
@@ -0,0 +1,89 @@
+use crate::app_state::AppState;
+use crate::test_runner::{ExampleStatus, RunResults};
+
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{List, ListItem, ListState},
+    Frame,
+};
+
+use super::ui_state::{Pane, UiState};
+
+fn run_results_items(results: &RunResults) -> Vec<ListItem> {
+    let status = if results.finished { "" } else { " (running)" };
+    let title = format!(
+        "{}: {}/{} examples{}",
+        results.run.project,
+        results.completed(),
+        results.total(),
+        status
+    );
+
+    let header = ListItem::new(Spans::from(Span::styled(
+        title,
+        Style::default().add_modifier(Modifier::BOLD),
+    )));
+
+    let examples = results.examples.iter().map(|example| {
+        let (mark, color) = match example.status {
+            ExampleStatus::Running => ("> ", Color::Yellow),
+            ExampleStatus::Passed => ("✓ ", Color::Green),
+            ExampleStatus::Failed => ("✗ ", Color::Red),
+        };
+        let description = example
+            .description
+            .as_ref()
+            .or_else(|| example.location.as_ref())
+            .unwrap_or(&example.id);
+        let run_time = example
+            .run_time
+            .map(|t| format!(" {:.2}s", t))
+            .unwrap_or_default();
+
+        ListItem::new(Spans::from(vec![
+            Span::raw("  "),
+            Span::styled(mark, Style::default().fg(color)),
+            Span::raw(description.as_str()),
+            Span::styled(run_time, Style::default().fg(Color::DarkGray)),
+        ]))
+    });
+
+    std::iter::once(header).chain(examples).collect()
+}
+
+/// Examples as they start and finish. Unless the pane has focus the last
+/// row is kept in view, so the pane follows the run as it goes.
+pub fn draw<B: Backend>(f: &mut Frame<B>, area: Rect, state: &AppState, ui: &UiState) {
+    let focused = ui.focus == Pane::Run;
+    let items: Vec<ListItem> = state.runs.values().flat_map(run_results_items).collect();
+
+    let row = if focused {
+        // Skip past the header of each run up to the selected example
+        ui.selected(Pane::Run, state).map(|mut selected| {
+            let mut row = 0;
+            for run in state.runs.values() {
+                row += 1;
+                if selected < run.examples.len() {
+                    break;
+                }
+                selected -= run.examples.len();
+                row += run.examples.len();
+            }
+            row + selected
+        })
+    } else {
+        items.len().checked_sub(1)
+    };
+
+    let list = List::new(items)
+        .block(super::pane_block("Run", focused))
+        .highlight_style(super::highlight_style(focused));
+
+    let mut list_state = ListState::default();
+    list_state.select(row);
+
+    f.render_stateful_widget(list, area, &mut list_state);
+}
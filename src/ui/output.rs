@@ -0,0 +1,66 @@
+use crate::app_state::AppState;
+use crate::ruby::rspec::OutputStream;
+use crate::test_runner::OutputLine;
+
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{List, ListItem, ListState},
+    Frame,
+};
+
+use super::ui_state::{Pane, UiState};
+
+pub fn output_text(output: &OutputLine) -> Spans {
+    match output.stream {
+        OutputStream::Stdout => Spans::from(output.line.as_str()),
+        OutputStream::Stderr => Spans::from(Span::styled(
+            output.line.as_str(),
+            Style::default().fg(Color::Red),
+        )),
+    }
+}
+
+/// What rspec wrote besides its results, stderr in red, followed by what
+/// went wrong running it. Unless the pane has focus the last line is kept in
+/// view.
+pub fn draw<B: Backend>(f: &mut Frame<B>, area: Rect, state: &AppState, ui: &UiState) {
+    let focused = ui.focus == Pane::Output;
+    let error_style = Style::default().fg(Color::Red).add_modifier(Modifier::BOLD);
+    let items: Vec<ListItem> = state
+        .runs
+        .values()
+        .flat_map(|run| {
+            run.output
+                .iter()
+                .map(|o| ListItem::new(output_text(o)))
+                .chain(
+                    run.errors
+                        .iter()
+                        .map(move |e| ListItem::new(Span::styled(e.as_str(), error_style))),
+                )
+        })
+        .collect();
+
+    let row = if focused {
+        ui.selected(Pane::Output, state)
+    } else {
+        items.len().checked_sub(1)
+    };
+
+    let highlight_style = if focused {
+        super::highlight_style(focused)
+    } else {
+        Style::default()
+    };
+    let list = List::new(items)
+        .block(super::pane_block("Output", focused))
+        .highlight_style(highlight_style);
+
+    let mut list_state = ListState::default();
+    list_state.select(row);
+
+    f.render_stateful_widget(list, area, &mut list_state);
+}
@@ -0,0 +1,259 @@
+use crate::app_state::{AppState, Event};
+use crate::util::fuzzy;
+
+use std::collections::{BTreeMap, BTreeSet};
+use termion::event::Key;
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Clear, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+/// Added to the score of a recently run spec, less the longer ago it ran.
+/// It is worth a point of `fuzzy::score`'s match quality, so recent specs
+/// come before matches about as good but not before clearly better ones.
+const RECENT_BONUS: i64 = 100;
+/// Most matches to list
+const MAX_MATCHES: usize = 200;
+
+/// A spec file to pick, as project name and path relative to its root.
+type Spec = (String, String);
+
+pub enum PickerResult {
+    Open,
+    Close,
+    Run(Vec<Event>),
+}
+
+/// An overlay for finding spec files to run by fuzzy matching their paths.
+pub struct Picker {
+    query: String,
+    selected: usize,
+    marked: BTreeSet<Spec>,
+}
+
+impl Picker {
+    pub fn new() -> Self {
+        Picker {
+            query: String::new(),
+            selected: 0,
+            marked: BTreeSet::new(),
+        }
+    }
+
+    /// The spec files matching the query, best first. Recently run specs
+    /// rank higher, and with no query come first in the order they were run.
+    pub fn matches(&self, state: &AppState) -> Vec<Spec> {
+        let recent_bonus = |project: &str, path: &str| {
+            state
+                .recent_specs
+                .iter()
+                .position(|(p, f)| p == project && f == path)
+                .map_or(0, |i| (RECENT_BONUS - i as i64).max(1))
+        };
+
+        let mut matches: Vec<(i64, Spec)> = state
+            .spec_files
+            .iter()
+            .flat_map(|(project, files)| files.iter().map(move |f| (project, f)))
+            .filter_map(|(project, file)| {
+                let score = if self.query.is_empty() {
+                    0
+                } else {
+                    fuzzy::score(&self.query, file)?
+                };
+
+                Some((
+                    score + recent_bonus(project, file),
+                    (project.clone(), file.clone()),
+                ))
+            })
+            .collect();
+
+        matches.sort_by(|(a_score, a), (b_score, b)| b_score.cmp(a_score).then(a.cmp(b)));
+        matches.truncate(MAX_MATCHES);
+        matches.into_iter().map(|(_, spec)| spec).collect()
+    }
+
+    /// Run the marked specs, or the highlighted one if none are marked, as
+    /// one run per project.
+    fn run_events(&self, state: &AppState) -> Vec<Event> {
+        let specs: Vec<Spec> = if self.marked.is_empty() {
+            self.matches(state)
+                .into_iter()
+                .nth(self.selected)
+                .into_iter()
+                .collect()
+        } else {
+            self.marked.iter().cloned().collect()
+        };
+
+        let mut by_project: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (project, path) in specs {
+            by_project.entry(project).or_default().push(path);
+        }
+
+        by_project
+            .into_iter()
+            .map(|(project, paths)| Event::RunLocations(project, paths))
+            .collect()
+    }
+
+    pub fn on_key(&mut self, key: Key, state: &AppState) -> PickerResult {
+        match key {
+            Key::Esc => return PickerResult::Close,
+            Key::Char('\n') => return PickerResult::Run(self.run_events(state)),
+            Key::Down | Key::Ctrl('n') => self.selected += 1,
+            Key::Up | Key::Ctrl('p') => self.selected = self.selected.saturating_sub(1),
+            // Tab marks like it does in fzf, and so does space as matching
+            // ignores it anyway
+            Key::Char('\t') | Key::Char(' ') => {
+                if let Some(spec) = self.matches(state).into_iter().nth(self.selected) {
+                    if !self.marked.remove(&spec) {
+                        self.marked.insert(spec);
+                    }
+                }
+                self.selected += 1;
+            }
+            Key::Backspace => {
+                self.query.pop();
+                self.selected = 0;
+            }
+            Key::Char(c) => {
+                self.query.push(c);
+                self.selected = 0;
+            }
+            _ => {}
+        }
+
+        let count = self.matches(state).len();
+        self.selected = self.selected.min(count.saturating_sub(1));
+
+        PickerResult::Open
+    }
+
+    pub fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect, state: &AppState) {
+        let area = Rect::new(
+            area.x + area.width / 10,
+            area.y + area.height / 10,
+            area.width - area.width / 5,
+            area.height - area.height / 5,
+        );
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+            .split(area);
+
+        let query = Paragraph::new(Spans::from(vec![
+            Span::styled("> ", Style::default().fg(Color::Yellow)),
+            Span::raw(self.query.as_str()),
+        ]))
+        .block(super::pane_block("Run specs", true));
+
+        let show_project = state.spec_files.len() > 1;
+        let items: Vec<ListItem> = self
+            .matches(state)
+            .into_iter()
+            .map(|spec| {
+                let mark = if self.marked.contains(&spec) {
+                    "● "
+                } else {
+                    "  "
+                };
+                let (project, path) = spec;
+                let project = if show_project {
+                    format!("{}: ", project)
+                } else {
+                    String::new()
+                };
+
+                ListItem::new(Spans::from(vec![
+                    Span::styled(mark, Style::default().fg(Color::Cyan)),
+                    Span::styled(project, Style::default().fg(Color::DarkGray)),
+                    Span::raw(path),
+                ]))
+            })
+            .collect();
+        let title = format!("{} marked, Tab to mark, Enter to run", self.marked.len());
+        let list = List::new(items)
+            .block(super::pane_block(&title, true))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+        let mut list_state = ListState::default();
+        list_state.select(Some(self.selected));
+
+        f.render_widget(Clear, area);
+        f.render_widget(query, chunks[0]);
+        f.render_stateful_widget(list, chunks[1], &mut list_state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(files: &[&str], recent: &[&str]) -> AppState {
+        let mut state = AppState::new();
+        state.spec_files.insert(
+            String::from("web"),
+            files.iter().map(|f| f.to_string()).collect(),
+        );
+        state.recent_specs = recent
+            .iter()
+            .map(|f| (String::from("web"), f.to_string()))
+            .collect();
+        state
+    }
+
+    fn matches(query: &str, state: &AppState) -> Vec<String> {
+        let mut picker = Picker::new();
+        picker.query = query.to_owned();
+        picker
+            .matches(state)
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect()
+    }
+
+    #[test]
+    fn without_a_query_recent_specs_come_first_as_run() {
+        let state = state(
+            &["spec/a_spec.rb", "spec/b_spec.rb", "spec/c_spec.rb"],
+            &["spec/c_spec.rb", "spec/b_spec.rb"],
+        );
+
+        assert_eq!(
+            matches("", &state),
+            vec!["spec/c_spec.rb", "spec/b_spec.rb", "spec/a_spec.rb"]
+        );
+    }
+
+    #[test]
+    fn recent_specs_come_first_among_equal_matches() {
+        let state = state(
+            &["spec/models/user_spec.rb", "spec/requests/users_spec.rb"],
+            &["spec/requests/users_spec.rb"],
+        );
+
+        assert_eq!(
+            matches("user", &state),
+            vec!["spec/requests/users_spec.rb", "spec/models/user_spec.rb"]
+        );
+    }
+
+    #[test]
+    fn recent_specs_dont_outrank_much_better_matches() {
+        let state = state(
+            &["spec/models/user_spec.rb", "spec/lib/uxsxexr_spec.rb"],
+            &["spec/lib/uxsxexr_spec.rb"],
+        );
+
+        assert_eq!(
+            matches("user", &state),
+            vec!["spec/models/user_spec.rb", "spec/lib/uxsxexr_spec.rb"]
+        );
+    }
+}
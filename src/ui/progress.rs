@@ -0,0 +1,93 @@
+use crate::app_state::AppState;
+use crate::test_runner::ExampleResult;
+
+use std::path::Path;
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Style},
+    text::Span,
+    widgets::{BarChart, Gauge, Sparkline},
+    Frame,
+};
+
+/// How many of the slowest examples to chart
+const SLOWEST: usize = 10;
+
+/// Examples completed out of those rspec said it would run, across the
+/// latest run of every project.
+pub fn draw_gauge<B: Backend>(f: &mut Frame<B>, area: Rect, state: &AppState) {
+    let completed: usize = state.runs.values().map(|r| r.completed()).sum();
+    let total: usize = state.runs.values().map(|r| r.total()).sum();
+    let failed = state.runs.values().any(|r| r.failed() > 0);
+
+    let ratio = if total == 0 {
+        0.0
+    } else {
+        (completed as f64 / total as f64).min(1.0)
+    };
+    let color = if failed { Color::Red } else { Color::Green };
+
+    let gauge = Gauge::default()
+        .block(super::pane_block("Progress", false))
+        .gauge_style(Style::default().fg(color).bg(Color::Black))
+        .ratio(ratio)
+        .label(Span::raw(format!("{}/{}", completed, total)));
+
+    f.render_widget(gauge, area);
+}
+
+pub fn draw_run_times<B: Backend>(f: &mut Frame<B>, area: Rect, state: &AppState) {
+    let run_times: Vec<u64> = state
+        .run_times
+        .iter()
+        .map(|t| t.as_millis() as u64)
+        .collect();
+
+    let title = match state.run_times.back() {
+        Some(last) => format!("Run times (last {:.1}s)", last.as_secs_f64()),
+        None => "Run times".to_owned(),
+    };
+
+    let sparkline = Sparkline::default()
+        .block(super::pane_block(&title, false))
+        .style(Style::default().fg(Color::Cyan))
+        .data(&run_times);
+
+    f.render_widget(sparkline, area);
+}
+
+/// A short label for an example, as the bars only have room for a few
+/// characters.
+fn example_label(example: &ExampleResult) -> String {
+    let location = example.location.as_ref().unwrap_or(&example.id);
+
+    Path::new(location)
+        .file_name()
+        .map(|name| name.to_string_lossy().replace("_spec.rb", ""))
+        .unwrap_or_else(|| location.to_owned())
+}
+
+/// The slowest finished examples of the current runs, in milliseconds.
+pub fn draw_slowest<B: Backend>(f: &mut Frame<B>, area: Rect, state: &AppState) {
+    let mut examples: Vec<(String, u64)> = state
+        .runs
+        .values()
+        .flat_map(|r| r.examples.iter())
+        .filter_map(|e| e.run_time.map(|t| (example_label(e), (t * 1000.0) as u64)))
+        .collect();
+    examples.sort_by(|a, b| b.1.cmp(&a.1));
+    examples.truncate(SLOWEST);
+
+    let data: Vec<(&str, u64)> = examples.iter().map(|(l, t)| (l.as_str(), *t)).collect();
+
+    let chart = BarChart::default()
+        .block(super::pane_block("Slowest (ms)", false))
+        .bar_width(8)
+        .bar_gap(1)
+        .bar_style(Style::default().fg(Color::Yellow))
+        .value_style(Style::default().fg(Color::Black).bg(Color::Yellow))
+        .data(&data);
+
+    f.render_widget(chart, area);
+}
@@ -0,0 +1,65 @@
+use crate::app_state::AppState;
+use crate::mapping;
+
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::Paragraph,
+    Frame,
+};
+
+pub fn draw<B: Backend>(f: &mut Frame<B>, area: Rect, state: &AppState) {
+    let selected: usize = state
+        .selections
+        .values()
+        .map(|matches| mapping::locations(matches).len())
+        .sum();
+    let passed: usize = state.runs.values().map(|r| r.passed()).sum();
+    let failed: usize = state.runs.values().map(|r| r.failed()).sum();
+    let running: usize = state.runs.values().map(|r| r.running()).sum();
+    let elapsed = state
+        .runs
+        .values()
+        .max_by_key(|r| r.started_at)
+        .map(|r| format!("{:.1}s", r.elapsed().as_secs_f64()))
+        .unwrap_or_else(|| "-".to_owned());
+
+    let text = Spans::from(vec![
+        Span::raw(format!(" {} changed", state.changed_files.len())),
+        Span::raw(format!("  {} specs selected  ", selected)),
+        Span::styled(
+            format!("{} passed", passed),
+            Style::default().fg(Color::Green),
+        ),
+        Span::raw("  "),
+        Span::styled(
+            format!("{} failed", failed),
+            Style::default().fg(Color::Red),
+        ),
+        Span::raw("  "),
+        Span::styled(
+            format!("{} running", running),
+            Style::default().fg(Color::Yellow),
+        ),
+        Span::raw(format!("  {}", elapsed)),
+        Span::raw(if state.watching {
+            "  watching"
+        } else {
+            "  paused"
+        }),
+        Span::raw("  ? for help"),
+        Span::styled(
+            state
+                .error
+                .as_ref()
+                .map_or_else(String::new, |error| format!("  {}", error)),
+            Style::default().fg(Color::Red),
+        ),
+    ]);
+
+    let paragraph = Paragraph::new(text).style(Style::default().add_modifier(Modifier::REVERSED));
+
+    f.render_widget(paragraph, area);
+}
@@ -0,0 +1,250 @@
+use crate::app_state::{AppState, Event};
+use crate::repo_watcher::ChangedFile;
+use crate::ruby::backtrace::Frame;
+use crate::test_runner::{ExampleResult, RunResults};
+
+use super::picker::{Picker, PickerResult};
+
+use std::path::PathBuf;
+
+use termion::event::Key;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pane {
+    ChangedFiles,
+    Run,
+    Failures,
+    Output,
+}
+
+impl Pane {
+    const ALL: [Pane; 4] = [Pane::ChangedFiles, Pane::Run, Pane::Failures, Pane::Output];
+
+    fn index(self) -> usize {
+        Pane::ALL.iter().position(|p| *p == self).unwrap()
+    }
+
+    fn next(self) -> Pane {
+        Pane::ALL[(self.index() + 1) % Pane::ALL.len()]
+    }
+
+    fn previous(self) -> Pane {
+        Pane::ALL[(self.index() + Pane::ALL.len() - 1) % Pane::ALL.len()]
+    }
+}
+
+/// The key bindings, as shown in the help overlay.
+pub const BINDINGS: &[(&str, &str)] = &[
+    ("↑/k ↓/j", "Move the selection"),
+    ("Tab", "Focus the next pane"),
+    ("Enter/r", "Run the specs for the selected file"),
+    ("a", "Run the specs for every changed file"),
+    ("f", "Rerun failed examples"),
+    ("/", "Find specs to run"),
+    ("c", "Cancel the current run"),
+    ("e", "Open the selection in your editor"),
+    ("h", "Show or hide example history"),
+    ("x", "Clear the changed files"),
+    ("w", "Toggle watching for changes"),
+    ("?", "Show or hide this help"),
+    ("q", "Quit"),
+];
+
+/// What a key press asks for.
+#[derive(Debug)]
+pub enum Action {
+    Dispatch(Vec<Event>),
+    /// Open a file in the editor, at a line if there is one
+    Edit(PathBuf, Option<usize>),
+}
+
+/// What the TUI is showing that isn't part of the app state: which pane has
+/// focus, what is selected in each, and which overlay is open.
+pub struct UiState {
+    pub focus: Pane,
+    pub show_help: bool,
+    pub show_history: bool,
+    pub history_row: usize,
+    pub picker: Option<Picker>,
+    selected: [usize; 4],
+}
+
+impl UiState {
+    pub fn new() -> Self {
+        UiState {
+            focus: Pane::ChangedFiles,
+            show_help: false,
+            show_history: false,
+            history_row: 0,
+            picker: None,
+            selected: [0; 4],
+        }
+    }
+
+    fn len(pane: Pane, state: &AppState) -> usize {
+        match pane {
+            Pane::ChangedFiles => state.changed_files.len(),
+            Pane::Run => state.runs.values().map(|r| r.examples.len()).sum(),
+            Pane::Failures => state.failures().count(),
+            Pane::Output => state
+                .runs
+                .values()
+                .map(|r| r.output.len() + r.errors.len())
+                .sum(),
+        }
+    }
+
+    /// The selected row of a pane, kept within what the pane now holds.
+    pub fn selected(&self, pane: Pane, state: &AppState) -> Option<usize> {
+        match Self::len(pane, state) {
+            0 => None,
+            len => Some(self.selected[pane.index()].min(len - 1)),
+        }
+    }
+
+    pub fn selected_file<'a>(&self, state: &'a AppState) -> Option<&'a ChangedFile> {
+        self.selected(Pane::ChangedFiles, state)
+            .and_then(|i| state.changed_files.get(i))
+    }
+
+    pub fn selected_failure<'a>(
+        &self,
+        state: &'a AppState,
+    ) -> Option<(&'a RunResults, &'a ExampleResult)> {
+        self.selected(Pane::Failures, state)
+            .and_then(|i| state.failures().nth(i))
+    }
+
+    pub fn selected_example<'a>(
+        &self,
+        state: &'a AppState,
+    ) -> Option<(&'a RunResults, &'a ExampleResult)> {
+        self.selected(Pane::Run, state).and_then(|i| {
+            state
+                .runs
+                .values()
+                .flat_map(|run| run.examples.iter().map(move |e| (run, e)))
+                .nth(i)
+        })
+    }
+
+    /// What to open in the editor for the focused pane: the selected file,
+    /// the line a failure was raised at, or an example's location.
+    fn edit_target(&self, state: &AppState) -> Option<(PathBuf, Option<usize>)> {
+        match self.focus {
+            Pane::ChangedFiles => self.selected_file(state).map(|f| (f.path.clone(), None)),
+            Pane::Run => self.selected_example(state).and_then(|(run, example)| {
+                let frame = example.location.as_deref().and_then(Frame::parse)?;
+                Some((run.root.join(frame.path), Some(frame.line)))
+            }),
+            Pane::Failures => self.selected_failure(state).and_then(|(run, example)| {
+                let frame = example.failure_frame()?;
+                Some((run.root.join(frame.path), Some(frame.line)))
+            }),
+            Pane::Output => None,
+        }
+    }
+
+    fn move_selection(&mut self, down: bool, state: &AppState) {
+        if let Some(selected) = self.selected(self.focus, state) {
+            let last = Self::len(self.focus, state) - 1;
+            self.selected[self.focus.index()] = if down {
+                (selected + 1).min(last)
+            } else {
+                selected.saturating_sub(1)
+            };
+        }
+    }
+
+    /// Update the UI for a key press, returning what else it asks for.
+    pub fn on_key(&mut self, key: Key, state: &AppState) -> Option<Action> {
+        if let Some(picker) = self.picker.as_mut() {
+            return match picker.on_key(key, state) {
+                PickerResult::Open => None,
+                PickerResult::Close => {
+                    self.picker = None;
+                    None
+                }
+                PickerResult::Run(events) => {
+                    self.picker = None;
+                    Some(Action::Dispatch(events))
+                }
+            };
+        }
+
+        if key == Key::Char('e') && !self.show_help && !self.show_history {
+            return self
+                .edit_target(state)
+                .map(|(file, line)| Action::Edit(file, line));
+        }
+
+        self.on_key_event(key, state)
+            .map(|event| Action::Dispatch(vec![event]))
+    }
+
+    fn on_key_event(&mut self, key: Key, state: &AppState) -> Option<Event> {
+        if self.show_help {
+            if let Key::Char('?') | Key::Esc | Key::Char('q') = key {
+                self.show_help = false;
+            }
+            return None;
+        }
+
+        if self.show_history {
+            match key {
+                Key::Char('h') | Key::Esc => self.show_history = false,
+                Key::Down | Key::Char('j') => {
+                    let last = super::history::rows(state).len().saturating_sub(1);
+                    self.history_row = (self.history_row + 1).min(last);
+                }
+                Key::Up | Key::Char('k') => {
+                    self.history_row = self.history_row.saturating_sub(1);
+                }
+                Key::Char('q') => return Some(Event::Quit),
+                _ => {}
+            }
+            return None;
+        }
+
+        match key {
+            Key::Char('q') => Some(Event::Quit),
+            Key::Char('?') => {
+                self.show_help = true;
+                None
+            }
+            Key::Char('h') => {
+                self.show_history = true;
+                None
+            }
+            Key::Char('/') => {
+                self.picker = Some(Picker::new());
+                None
+            }
+            Key::Char('\t') => {
+                self.focus = self.focus.next();
+                None
+            }
+            Key::BackTab => {
+                self.focus = self.focus.previous();
+                None
+            }
+            Key::Down | Key::Char('j') => {
+                self.move_selection(true, state);
+                None
+            }
+            Key::Up | Key::Char('k') => {
+                self.move_selection(false, state);
+                None
+            }
+            Key::Char('\n') | Key::Char('r') => self
+                .selected_file(state)
+                .map(|f| Event::RunFile(f.path.clone())),
+            Key::Char('a') => Some(Event::RunAllChanged),
+            Key::Char('f') => Some(Event::RerunFailures),
+            Key::Char('c') => Some(Event::CancelRun),
+            Key::Char('x') => Some(Event::ClearChanged),
+            Key::Char('w') => Some(Event::ToggleWatching),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,131 @@
+/// Score how well `query` fuzzy matches `candidate`, fzf style: every
+/// character of the query must appear in order, case insensitively. Matches
+/// that run on from the previous character or start a path segment or word
+/// score higher, and gaps between matched characters cost a little. `None`
+/// if the query doesn't match at all.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    let candidate: Vec<char> = candidate.chars().collect();
+
+    let first = match query.first() {
+        Some(first) => *first,
+        None => return Some(-(candidate.len() as i64)),
+    };
+
+    // Matching greedily from the first place the query starts can miss a
+    // better match later on, e.g. `user` in `requests/users_spec.rb`
+    let best = (0..candidate.len())
+        .filter(|i| candidate[*i].to_ascii_lowercase() == first)
+        .filter_map(|start| score_from(&query, &candidate, start))
+        .max()?;
+
+    // Prefer the shorter of otherwise equal matches
+    Some(best * 100 - candidate.len() as i64)
+}
+
+fn score_from(query: &[char], candidate: &[char], start: usize) -> Option<i64> {
+    let mut score = 0;
+    let mut last_match: Option<usize> = None;
+    let mut i = start;
+
+    for q in query.iter() {
+        while candidate.get(i)?.to_ascii_lowercase() != *q {
+            i += 1;
+        }
+
+        score += 1;
+
+        match last_match {
+            Some(last) if last + 1 == i => score += 5,
+            Some(last) => score -= ((i - last) as i64).min(10) / 2,
+            None => {}
+        }
+
+        let boundary = i
+            .checked_sub(1)
+            .map_or(true, |b| matches!(candidate[b], '/' | '_' | '-' | '.'));
+        if boundary {
+            score += 8;
+        }
+
+        last_match = Some(i);
+        i += 1;
+    }
+
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The candidates that match, best first.
+    fn rank<'a>(query: &str, candidates: &[&'a str]) -> Vec<&'a str> {
+        let mut scored: Vec<(i64, &str)> = candidates
+            .iter()
+            .filter_map(|c| score(query, c).map(|s| (s, *c)))
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0));
+        scored.into_iter().map(|(_, c)| c).collect()
+    }
+
+    #[test]
+    fn every_query_character_must_match_in_order() {
+        assert!(score("user", "spec/models/user_spec.rb").is_some());
+        assert!(score("USER", "spec/models/user_spec.rb").is_some());
+        assert!(score("u s e r", "spec/models/user_spec.rb").is_some());
+        assert!(score("resu", "spec/models/user_spec.rb").is_none());
+        assert!(score("userz", "spec/models/user_spec.rb").is_none());
+    }
+
+    #[test]
+    fn an_empty_query_prefers_shorter_paths() {
+        assert_eq!(
+            rank("", &["spec/models/user_spec.rb", "spec/user_spec.rb"]),
+            vec!["spec/user_spec.rb", "spec/models/user_spec.rb"]
+        );
+    }
+
+    #[test]
+    fn runs_of_characters_beat_scattered_ones() {
+        assert_eq!(
+            rank("user", &["spec/uxsxexr_spec.rb", "spec/xuserx_spec.rb"]),
+            vec!["spec/xuserx_spec.rb", "spec/uxsxexr_spec.rb"]
+        );
+    }
+
+    #[test]
+    fn matches_starting_a_segment_beat_those_within_a_word() {
+        assert_eq!(
+            rank(
+                "user",
+                &["spec/models/superuser_spec.rb", "spec/models/user_spec.rb"]
+            ),
+            vec!["spec/models/user_spec.rb", "spec/models/superuser_spec.rb"]
+        );
+    }
+
+    #[test]
+    fn the_best_match_is_found_past_an_earlier_one() {
+        // The `u` in `requests` starts a worse match than the one in `users`
+        assert_eq!(
+            score("user", "requests/users_spec.rb"),
+            score("user", "xxxxxxxx/users_spec.rb")
+        );
+    }
+
+    #[test]
+    fn shorter_paths_win_otherwise_equal_matches() {
+        assert_eq!(
+            rank(
+                "user",
+                &["spec/requests/users_spec.rb", "spec/models/user_spec.rb"]
+            ),
+            vec!["spec/models/user_spec.rb", "spec/requests/users_spec.rb"]
+        );
+    }
+}
@@ -1,2 +1,9 @@
+pub mod fuzzy;
+pub mod paint;
 pub mod path_filter;
 pub mod path_sort;
+pub mod shell;
+pub mod source_files;
+pub mod store;
+#[cfg(test)]
+pub mod test_dir;
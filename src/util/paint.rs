@@ -0,0 +1,21 @@
+use termion::color::{self, Color};
+
+/// Colours text for a terminal, leaving it plain when the output is not one.
+#[derive(Clone, Copy)]
+pub struct Painter {
+    color: bool,
+}
+
+impl Painter {
+    pub fn new(color: bool) -> Self {
+        Painter { color }
+    }
+
+    pub fn paint<C: Color>(&self, c: C, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", color::Fg(c), text, color::Fg(color::Reset))
+        } else {
+            text.to_owned()
+        }
+    }
+}
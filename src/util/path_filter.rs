@@ -1,31 +1,43 @@
-use crate::Configuration;
 use globber::Pattern;
 use std::path::Path;
 
 pub struct PathFilter {
+    include: Vec<String>,
     include_patterns: Vec<Pattern>,
 }
 
 impl PathFilter {
-    pub fn new(config: &Configuration) -> anyhow::Result<Self> {
-        let patterns: Result<Vec<Pattern>, globber::Error> = config
-            .include
-            .clone()
+    pub fn new<S: AsRef<str>>(include: &[S]) -> anyhow::Result<Self> {
+        let patterns: anyhow::Result<Vec<Pattern>> = include
             .iter()
-            .map(|s| Pattern::new(s))
+            .map(|s| {
+                Pattern::new(s.as_ref())
+                    .map_err(|e| anyhow::anyhow!("Invalid glob {:?}: {}", s.as_ref(), e))
+            })
             .collect();
 
-        patterns
-            .map(|patterns| PathFilter {
-                include_patterns: patterns,
-            })
-            .map_err(|e| anyhow::anyhow!(e))
+        patterns.map(|patterns| PathFilter {
+            include: include.iter().map(|s| s.as_ref().to_owned()).collect(),
+            include_patterns: patterns,
+        })
     }
 
     pub fn include_path<T: AsRef<Path>>(&self, path: T) -> bool {
-        match path.as_ref().to_str() {
-            Some(s) => self.include_patterns.iter().any(|p| p.matches(s)),
-            None => false,
-        }
+        self.matching_glob(path).is_some()
+    }
+
+    /// The first include glob that matches `path`.
+    pub fn matching_glob<T: AsRef<Path>>(&self, path: T) -> Option<&str> {
+        let path = path.as_ref().to_str()?;
+
+        self.include
+            .iter()
+            .zip(self.include_patterns.iter())
+            .find(|(_, p)| p.matches(path))
+            .map(|(glob, _)| glob.as_str())
+    }
+
+    pub fn globs(&self) -> &[String] {
+        &self.include
     }
 }
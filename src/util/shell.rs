@@ -0,0 +1,16 @@
+use std::borrow::Cow;
+
+/// Quote a word for a POSIX shell, leaving it as it is when nothing in it
+/// needs quoting.
+pub fn quote(word: &str) -> Cow<'_, str> {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+
+    if plain {
+        Cow::Borrowed(word)
+    } else {
+        Cow::Owned(format!("'{}'", word.replace('\'', r"'\''")))
+    }
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SKIP_DIRS: &[&str] = &[
+    ".git",
+    ".spec_detect",
+    "node_modules",
+    "tmp",
+    "log",
+    "vendor",
+    "coverage",
+];
+
+/// Every file under `root` with the extension `ext`, relative to `root`.
+/// Directories that never hold project sources are skipped.
+pub fn find_files(root: &Path, ext: &str) -> Vec<PathBuf> {
+    let root = if root.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        root
+    };
+    let mut files = vec![];
+    let mut dirs = vec![PathBuf::new()];
+
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(root.join(&dir)) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = dir.join(entry.file_name());
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(_) => continue,
+            };
+
+            if file_type.is_dir() {
+                let skip = entry
+                    .file_name()
+                    .to_str()
+                    .map_or(true, |name| SKIP_DIRS.contains(&name));
+
+                if !skip {
+                    dirs.push(path);
+                }
+            } else if path.extension().map_or(false, |e| e == ext) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
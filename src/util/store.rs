@@ -0,0 +1,67 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where spec_detect keeps its own files for a project.
+pub const STORE_DIR: &str = ".spec_detect";
+
+pub fn store_path(root: &Path, name: &str) -> PathBuf {
+    root.join(STORE_DIR).join(name)
+}
+
+/// Whether a path is in a project's store, which is never a change to the
+/// project itself.
+pub fn in_store(path: &Path) -> bool {
+    path.components().any(|c| c.as_os_str() == STORE_DIR)
+}
+
+/// Read a stored JSON file, or `None` if it doesn't exist or can't be read.
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+}
+
+/// Write a stored JSON file. The store ignores itself, so that git doesn't
+/// report it as untracked in the user's repository.
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+
+        let ignore = dir.join(".gitignore");
+        if !ignore.exists() {
+            fs::write(ignore, "*\n")?;
+        }
+    }
+
+    fs::write(path, serde_json::to_string(value)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_dir::TestDir;
+
+    #[test]
+    fn the_store_ignores_itself() {
+        let dir = TestDir::new("store-ignore");
+        let path = store_path(&dir.path, "results.json");
+
+        write_json(&path, &vec![1, 2]).unwrap();
+
+        assert_eq!(read_json::<Vec<i32>>(&path), Some(vec![1, 2]));
+        assert_eq!(
+            fs::read_to_string(dir.path.join(".spec_detect/.gitignore")).unwrap(),
+            "*\n"
+        );
+    }
+
+    #[test]
+    fn store_paths_are_found_in_any_project() {
+        assert!(in_store(Path::new(".spec_detect/results.json")));
+        assert!(in_store(Path::new("apps/web/.spec_detect/coverage.json")));
+        assert!(!in_store(Path::new("app/models/spec_detect.rb")));
+    }
+}
@@ -0,0 +1,40 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A directory of files for a test to work in, removed once it is dropped.
+pub struct TestDir {
+    pub path: PathBuf,
+}
+
+impl TestDir {
+    /// An empty directory, named so that tests running at once don't share
+    /// one.
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("spec_detect-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        TestDir { path }
+    }
+
+    /// Write a file, relative to the directory, making any directories it
+    /// is in.
+    pub fn write<P: AsRef<Path>>(&self, path: P, contents: &str) -> &Self {
+        let path = self.path.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+        self
+    }
+
+    pub fn remove<P: AsRef<Path>>(&self, path: P) -> &Self {
+        fs::remove_file(self.path.join(path)).unwrap();
+        self
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,6 @@
+# Loaded through RUBYOPT when coverage is collected, so that it starts before
+# rspec loads anything, including what .rspec requires. Only files loaded
+# after this are measured: anything loaded earlier, such as by bundler/setup,
+# never shows up in an example's coverage.
+require "coverage"
+Coverage.start(lines: true) unless Coverage.running?
@@ -1,6 +1,13 @@
 require "json"
 require "rspec/core"
 
+# Coverage is normally already running from rust_rspec_coverage.rb, which is
+# loaded first through RUBYOPT
+if ENV["SPEC_DETECT_COVERAGE"]
+  require "coverage"
+  Coverage.start(lines: true) unless Coverage.running?
+end
+
 class RustRspecFormatter
   RSpec::Core::Formatters.register self, :start, :stop, :example_started, :example_passed, :example_failed
 
@@ -22,6 +29,7 @@ class RustRspecFormatter
   end
 
   def example_started(notification)
+    reset_coverage
     dump_notification("example_started", {
       id: notification.example.id,
       location: notification.example.location,
@@ -30,6 +38,7 @@ class RustRspecFormatter
   end
 
   def example_passed(notification)
+    dump_coverage(notification.example)
     dump_notification("example_passed", {
       id: notification.example.id,
       location: notification.example.location,
@@ -39,12 +48,39 @@ class RustRspecFormatter
   end
 
   def example_failed(notification)
+    dump_coverage(notification.example)
     dump_notification("example_failed", {
       id: notification.example.id,
       location: notification.example.location,
       description: notification.example.full_description,
       run_time: notification.example.execution_result.run_time,
       exception: notification.example.execution_result.exception.to_s,
+      backtrace: notification.example.execution_result.exception.backtrace,
+    })
+  end
+
+  private
+
+  def coverage?
+    defined?(Coverage) && Coverage.running?
+  end
+
+  def reset_coverage
+    Coverage.result(stop: false, clear: true) if coverage?
+  end
+
+  # Report the project files with any line executed since the example started
+  def dump_coverage(example)
+    return unless coverage?
+
+    root = File.join(Dir.pwd, "")
+    files = Coverage.result(stop: false, clear: true).select do |path, data|
+      path.start_with?(root) && data[:lines].any? { |count| count && count > 0 }
+    end
+
+    dump_notification("example_coverage", {
+      id: example.id,
+      files: files.keys.map { |path| path.delete_prefix(root) },
     })
   end
-end
\ No newline at end of file
+end